@@ -0,0 +1,47 @@
+////////////////////////////////////////
+// Road search type-ahead box
+// Suggestions come from the /roads route. Choosing a road loads the whole
+// road onto the map.
+////////////////////////////////////////
+
+let road_search_input = document.getElementById("road_search");
+let road_search_results = document.getElementById("road_search_results");
+let road_search_timeout = undefined;
+let road_search_latest = [];
+
+function road_search_label(item) {
+	let name = item.road_name.join(" / ");
+	let cwy = item.cwy.map(cwy => cwy[0]).join("");
+	return `${name} (${cwy} ${item.slk_from.toFixed(2)}-${item.slk_to.toFixed(2)})`;
+}
+
+function update_road_search_results() {
+	let search = road_search_input.value.trim();
+	if (search === "") {
+		road_search_results.replaceChildren();
+		return;
+	}
+	fetch("/roads?" + new URLSearchParams({ q: search, limit: 20 }).toString())
+		.then(response => response.ok ? response.json() : [])
+		.then(items => {
+			road_search_latest = items;
+			road_search_results.replaceChildren(...items.map(item => {
+				let option = document.createElement("option");
+				option.value = item.road;
+				option.label = road_search_label(item);
+				return option;
+			}));
+		});
+}
+
+road_search_input.addEventListener("input", () => {
+	clearTimeout(road_search_timeout);
+	road_search_timeout = setTimeout(update_road_search_results, 200);
+});
+
+road_search_input.addEventListener("change", () => {
+	let road = road_search_input.value.trim();
+	if (road_search_latest.some(item => item.road === road)) {
+		window.location.search = new URLSearchParams({ road }).toString();
+	}
+});
@@ -0,0 +1,77 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+
+use super::{cached::SourceMetadata, download::RetryingClient, sources, DataSourceSettings, SharedIndexedData};
+
+/// Returns true if the rest service reports a different feature count or last
+/// edit date compared to when the currently served data was downloaded.
+/// If the service cannot be reached the data is treated as unchanged.
+///
+/// For a local data source only the modification time of the file is checked.
+async fn source_has_changed(shared_data: &SharedIndexedData, data_source_settings: &DataSourceSettings) -> bool {
+    let current_data = shared_data.current();
+    let url_to_download_new_data = &data_source_settings.url_to_download_new_data;
+    if let Some(local_source_path) = sources::local_source_path(url_to_download_new_data) {
+        return match sources::modified_time_millis(&local_source_path) {
+            Some(modified) => current_data
+                .source_metadata()
+                .map_or(true, |loaded| loaded.last_edit_date != Some(modified)),
+            None => {
+                println!("WARNING: Scheduled refresh could not check the modification time of '{}'", local_source_path.display());
+                false
+            }
+        };
+    }
+    let query_url = match data_source_settings.field_mapping.query_url(url_to_download_new_data) {
+        Ok(query_url) => query_url,
+        Err(error_message) => {
+            println!("WARNING: Scheduled refresh could not check for new data because '{}'", error_message);
+            return false;
+        }
+    };
+    let client = match RetryingClient::new(&data_source_settings.download_settings, &query_url) {
+        Ok(client) => client,
+        Err(error_message) => {
+            println!("WARNING: Scheduled refresh could not check for new data because '{}'", error_message);
+            return false;
+        }
+    };
+    match SourceMetadata::fetch(&query_url, &client).await {
+        Ok(latest) => match current_data.source_metadata() {
+            Some(downloaded) => downloaded != &latest,
+            // data saved by an older version; we cant tell so assume it changed
+            None => true,
+        },
+        Err(error_message) => {
+            println!("WARNING: Scheduled refresh could not check for new data because '{}'", error_message);
+            false
+        }
+    }
+}
+
+/// Start a background task which periodically checks the rest service (or
+/// local file) for new data, and downloads and swaps it in if it has changed.
+pub fn spawn_background_refresh(
+    shared_data: Arc<SharedIndexedData>,
+    data_source_settings: DataSourceSettings,
+    refresh_interval: Duration,
+) -> JoinHandle<()> {
+    println!("INFO: Checking for new data every {} seconds", refresh_interval.as_secs());
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(refresh_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick completes immediately, but the data was only just loaded
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if !source_has_changed(&shared_data, &data_source_settings).await {
+                println!("INFO: Scheduled refresh skipped. The source data has not changed.");
+                continue;
+            }
+            println!("INFO: Scheduled refresh is downloading new data");
+            // Failures are logged by reload() and the previous data is kept
+            let _ = shared_data.reload(&data_source_settings, true).await;
+        }
+    })
+}
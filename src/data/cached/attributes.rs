@@ -1,8 +1,5 @@
 use serde::{Deserialize, Serialize};
-use super::{
-    Cwy,
-    super::esri_json
-};
+use super::Cwy;
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, PartialOrd)]
 #[allow(non_snake_case)]
@@ -11,10 +8,48 @@ pub struct Attributes {
     pub CWY: Cwy,
     pub START_SLK: f32,
     pub END_SLK: f32,
+    /// The following are missing from cache files created by older versions,
+    /// and from data sources which do not have these fields
+    #[serde(default)]
+    pub NETWORK_TYPE: Option<String>,
+    #[serde(default)]
+    pub ROAD_NAME: Option<String>,
+    #[serde(default)]
+    pub LG_NAME: Option<String>,
+    #[serde(default)]
+    pub RA_NO: Option<String>,
+    /// True distance in kilometres. Unlike SLK it has no points of equation.
+    #[serde(default)]
+    pub START_TRUE_DIST: Option<f32>,
+    #[serde(default)]
+    pub END_TRUE_DIST: Option<f32>,
 }
 
 impl Eq for Attributes {}
 
+#[cfg(test)]
+impl Attributes {
+    /// Attributes with only the required fields set, for tests. Set the
+    /// others with struct update syntax.
+    pub fn new(road: &str, cwy: Cwy, start_slk: f32, end_slk: f32) -> Self {
+        Self {
+            ROAD: road.to_owned(),
+            CWY: cwy,
+            START_SLK: start_slk,
+            END_SLK: end_slk,
+            NETWORK_TYPE: None,
+            ROAD_NAME: None,
+            LG_NAME: None,
+            RA_NO: None,
+            START_TRUE_DIST: None,
+            END_TRUE_DIST: None,
+        }
+    }
+}
+
+// Features are sorted by road and carriageway only, so that the order of
+// features within a carriageway is left as it was
+#[allow(clippy::derive_ord_xor_partial_ord)]
 impl Ord for Attributes {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match self.ROAD.cmp(&other.ROAD) {
@@ -24,14 +59,3 @@ impl Ord for Attributes {
         }
     }
 }
-
-impl From<esri_json::EsriAttributes> for Attributes {
-    fn from(value: esri_json::EsriAttributes) -> Self {
-        Self {
-            ROAD: value.ROAD.clone(),
-            CWY: value.CWY.into(),
-            START_SLK: value.START_SLK,
-            END_SLK: value.END_SLK,
-        }
-    }
-}
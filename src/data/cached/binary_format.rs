@@ -0,0 +1,528 @@
+//! A versioned binary cache format which is much faster to load than the
+//! LZ4 compressed JSON format. All numbers are little endian.
+//!
+//! | Bytes                  | Content                                         |
+//! | ---------------------- | ----------------------------------------------- |
+//! | 8                      | [MAGIC]                                         |
+//! | 4 (u32)                | format version                                  |
+//! | 8 (u64)                | `n` number of features                          |
+//! | 4 (u32)                | `m` length of layer metadata                    |
+//! | `m`                    | layer metadata (everything except the features) as JSON |
+//! | 8 * (`n` + 1) (u64)    | offset of each feature record, then the end offset. Relative to the start of the records |
+//! | ...                    | feature records                                 |
+//!
+//! Each feature record is
+//!
+//! | Bytes        | Content                                  |
+//! | ------------ | ---------------------------------------- |
+//! | 2 (u16)      | `r` length of road number                |
+//! | `r`          | road number utf8                         |
+//! | 1 (u8)       | carriageway; 0 Left, 1 Right, 2 Single   |
+//! | 4 (f32)      | START_SLK                                |
+//! | 4 (f32)      | END_SLK                                  |
+//! | ...          | NETWORK_TYPE, ROAD_NAME, LG_NAME and RA_NO, each as an optional string |
+//! | 4 (f32)      | START_TRUE_DIST, NaN if missing          |
+//! | 4 (f32)      | END_TRUE_DIST, NaN if missing            |
+//! | 4 (u32)      | `v` number of vertices                   |
+//! | 16 * `v`     | vertices as pairs of f64 `x`, `y`        |
+//! | 1 (u8)       | 1 if the vertices have measures, else 0  |
+//! | 8 * `v`      | f64 measure of each vertex, only if present |
+//!
+//! An optional string is a u16 length followed by that many bytes of utf8, or
+//! just the length [NONE_LENGTH] if there is no value.
+//!
+//! The offsets table means any feature can be decoded directly from a memory
+//! mapped file without reading the features before it.
+
+use std::ops::Range;
+
+use nickslinetoolsrust::vector2::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::helpers::{CalendarDate, ErrorWithStaticMessage};
+use super::{Attributes, CacheMetadata, Cwy, Feature, FeatureSummary, Layer, SourceMetadata, SpatialReference};
+
+pub const MAGIC: &[u8; 8] = b"NLRBIN\r\n";
+pub const VERSION: u32 = 4;
+
+/// Version 3 is the same except that feature records have no measures. It
+/// is still read so that existing snapshots keep working.
+const OLDEST_READABLE_VERSION: u32 = 3;
+
+const NONE_LENGTH: u16 = u16::MAX;
+
+const HEADER_LENGTH: usize = 8 + 4 + 8 + 4;
+
+/// Everything in the [Layer] except the features
+#[derive(Serialize)]
+struct LayerMetadataRef<'a> {
+    source_metadata: &'a Option<SourceMetadata>,
+    downloaded: &'a Option<CalendarDate>,
+    spatial_reference: &'a Option<SpatialReference>,
+    cache_metadata: &'a Option<CacheMetadata>,
+}
+
+/// Everything in the [Layer] except the features. See the fields of [Layer]
+#[derive(Deserialize, Default)]
+pub struct LayerMetadata {
+    #[serde(default)]
+    pub source_metadata: Option<SourceMetadata>,
+    #[serde(default)]
+    pub downloaded: Option<CalendarDate>,
+    #[serde(default)]
+    pub spatial_reference: Option<SpatialReference>,
+    #[serde(default)]
+    pub cache_metadata: Option<CacheMetadata>,
+}
+
+pub fn is_binary_format(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn cwy_to_byte(cwy: &Cwy) -> u8 {
+    match cwy {
+        Cwy::Left => 0,
+        Cwy::Right => 1,
+        Cwy::Single => 2,
+    }
+}
+
+fn cwy_from_byte(byte: u8) -> Result<Cwy, ErrorWithStaticMessage> {
+    match byte {
+        0 => Ok(Cwy::Left),
+        1 => Ok(Cwy::Right),
+        2 => Ok(Cwy::Single),
+        _ => Err(ErrorWithStaticMessage::new("Binary cache file contains an invalid carriageway")),
+    }
+}
+
+fn write_optional_string(buffer: &mut Vec<u8>, value: &Option<String>) -> Result<(), ErrorWithStaticMessage> {
+    match value {
+        Some(value) => {
+            let length = u16::try_from(value.len())
+                .ok()
+                .filter(|&length| length != NONE_LENGTH)
+                .ok_or(ErrorWithStaticMessage::new("Attribute is too long to save in the binary cache file"))?;
+            buffer.extend_from_slice(&length.to_le_bytes());
+            buffer.extend_from_slice(value.as_bytes());
+        },
+        None => buffer.extend_from_slice(&NONE_LENGTH.to_le_bytes()),
+    }
+    Ok(())
+}
+
+fn write_optional_f32(buffer: &mut Vec<u8>, value: &Option<f32>) {
+    buffer.extend_from_slice(&value.unwrap_or(f32::NAN).to_le_bytes());
+}
+
+fn write_feature(buffer: &mut Vec<u8>, feature: &Feature) -> Result<(), ErrorWithStaticMessage> {
+    let attributes = &feature.attributes;
+    let road_length = u16::try_from(attributes.ROAD.len())
+        .map_err(|_| ErrorWithStaticMessage::new("Road number is too long to save in the binary cache file"))?;
+    buffer.extend_from_slice(&road_length.to_le_bytes());
+    buffer.extend_from_slice(attributes.ROAD.as_bytes());
+    buffer.push(cwy_to_byte(&attributes.CWY));
+    buffer.extend_from_slice(&attributes.START_SLK.to_le_bytes());
+    buffer.extend_from_slice(&attributes.END_SLK.to_le_bytes());
+    write_optional_string(buffer, &attributes.NETWORK_TYPE)?;
+    write_optional_string(buffer, &attributes.ROAD_NAME)?;
+    write_optional_string(buffer, &attributes.LG_NAME)?;
+    write_optional_string(buffer, &attributes.RA_NO)?;
+    write_optional_f32(buffer, &attributes.START_TRUE_DIST);
+    write_optional_f32(buffer, &attributes.END_TRUE_DIST);
+    let vertex_count = u32::try_from(feature.geometry.len())
+        .map_err(|_| ErrorWithStaticMessage::new("Feature has too many vertices to save in the binary cache file"))?;
+    buffer.extend_from_slice(&vertex_count.to_le_bytes());
+    for vertex in feature.geometry.iter() {
+        buffer.extend_from_slice(&vertex.x.to_le_bytes());
+        buffer.extend_from_slice(&vertex.y.to_le_bytes());
+    }
+    match &feature.measures {
+        Some(measures) if measures.len() == feature.geometry.len() => {
+            buffer.push(1);
+            for measure in measures.iter() {
+                buffer.extend_from_slice(&measure.to_le_bytes());
+            }
+        },
+        _ => buffer.push(0),
+    }
+    Ok(())
+}
+
+pub fn encode_layer(layer: &Layer) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let metadata = serde_json::to_vec(&LayerMetadataRef {
+        source_metadata: &layer.source_metadata,
+        downloaded: &layer.downloaded,
+        spatial_reference: &layer.spatial_reference,
+        cache_metadata: &layer.cache_metadata,
+    })?;
+
+    let mut records: Vec<u8> = Vec::new();
+    let mut offsets: Vec<u64> = Vec::with_capacity(layer.features.len() + 1);
+    for feature in layer.features.iter() {
+        offsets.push(records.len() as u64);
+        write_feature(&mut records, feature)?;
+    }
+    offsets.push(records.len() as u64);
+
+    let mut buffer = Vec::with_capacity(HEADER_LENGTH + metadata.len() + offsets.len() * 8 + records.len());
+    buffer.extend_from_slice(MAGIC);
+    buffer.extend_from_slice(&VERSION.to_le_bytes());
+    buffer.extend_from_slice(&(layer.features.len() as u64).to_le_bytes());
+    buffer.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(&metadata);
+    for offset in offsets {
+        buffer.extend_from_slice(&offset.to_le_bytes());
+    }
+    buffer.extend_from_slice(&records);
+    Ok(buffer)
+}
+
+/// Reads little endian values from a byte slice, failing if the slice ends
+/// early
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], ErrorWithStaticMessage> {
+        let end = self.position.checked_add(length)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(ErrorWithStaticMessage::new("Binary cache file is truncated"))?;
+        let result = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(result)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], ErrorWithStaticMessage> {
+        let mut result = [0u8; N];
+        result.copy_from_slice(self.take(N)?);
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> Result<u8, ErrorWithStaticMessage> {
+        Ok(self.take_array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ErrorWithStaticMessage> {
+        Ok(u16::from_le_bytes(self.take_array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ErrorWithStaticMessage> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ErrorWithStaticMessage> {
+        Ok(u64::from_le_bytes(self.take_array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, ErrorWithStaticMessage> {
+        Ok(f32::from_le_bytes(self.take_array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, ErrorWithStaticMessage> {
+        Ok(f64::from_le_bytes(self.take_array()?))
+    }
+
+    fn string(&mut self, length: usize) -> Result<String, ErrorWithStaticMessage> {
+        std::str::from_utf8(self.take(length)?)
+            .map(|value| value.to_owned())
+            .map_err(|_| ErrorWithStaticMessage::new("Binary cache file contains invalid utf8"))
+    }
+
+    fn optional_f32(&mut self) -> Result<Option<f32>, ErrorWithStaticMessage> {
+        self.f32().map(|value| Some(value).filter(|value| !value.is_nan()))
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, ErrorWithStaticMessage> {
+        match self.u16()? {
+            NONE_LENGTH => Ok(None),
+            length => self.string(length as usize).map(Some),
+        }
+    }
+}
+
+/// A view over a binary cache file (usually memory mapped) which decodes
+/// features on demand
+pub struct BinaryLayerView<B: AsRef<[u8]>> {
+    bytes: B,
+    version: u32,
+    metadata: Range<usize>,
+    offsets_start: usize,
+    records_start: usize,
+    feature_count: usize,
+}
+
+impl<B: AsRef<[u8]>> BinaryLayerView<B> {
+    /// Only the header is checked here. See [BinaryLayerView::summary]
+    pub fn new(bytes: B) -> Result<Self, ErrorWithStaticMessage> {
+        let mut reader = Reader::new(bytes.as_ref());
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ErrorWithStaticMessage::new("Not a binary cache file"));
+        }
+        let version = reader.u32()?;
+        if !(OLDEST_READABLE_VERSION..=VERSION).contains(&version) {
+            return Err(ErrorWithStaticMessage::new("Binary cache file was created by an incompatible version"));
+        }
+        let feature_count = usize::try_from(reader.u64()?)
+            .map_err(|_| ErrorWithStaticMessage::new("Binary cache file has too many features"))?;
+        let metadata_length = reader.u32()? as usize;
+        let metadata_start = reader.position;
+        reader.take(metadata_length)?;
+        let offsets_start = reader.position;
+        let offsets_length = feature_count.checked_add(1)
+            .and_then(|count| count.checked_mul(8))
+            .ok_or(ErrorWithStaticMessage::new("Binary cache file has too many features"))?;
+        reader.take(offsets_length)?;
+        let records_start = reader.position;
+        Ok(Self {
+            bytes,
+            version,
+            metadata: metadata_start..offsets_start,
+            offsets_start,
+            records_start,
+            feature_count,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.feature_count
+    }
+
+    /// Everything except the features
+    pub fn metadata(&self) -> Result<LayerMetadata, serde_json::Error> {
+        serde_json::from_slice(&self.bytes.as_ref()[self.metadata.clone()])
+    }
+
+    fn offset(&self, index: usize) -> Result<usize, ErrorWithStaticMessage> {
+        let mut reader = Reader::new(&self.bytes.as_ref()[self.offsets_start + index * 8..]);
+        usize::try_from(reader.u64()?)
+            .map_err(|_| ErrorWithStaticMessage::new("Binary cache file contains an invalid offset"))
+    }
+
+    /// A reader over the record of the feature at `index`
+    fn record(&self, index: usize) -> Result<Reader<'_>, ErrorWithStaticMessage> {
+        if index >= self.feature_count {
+            return Err(ErrorWithStaticMessage::new("Feature index out of range"));
+        }
+        let start = self.offset(index)?;
+        let end = self.offset(index + 1)?;
+        let record = self.bytes.as_ref()[self.records_start..].get(start..end)
+            .ok_or(ErrorWithStaticMessage::new("Binary cache file contains an invalid offset"))?;
+        Ok(Reader::new(record))
+    }
+
+    /// Everything after the vertices. `None` if the record has no measures
+    fn read_measures(&self, reader: &mut Reader, vertex_count: usize) -> Result<Option<Vec<f64>>, ErrorWithStaticMessage> {
+        let has_measures = self.version >= 4 && reader.u8()? != 0;
+        if !has_measures {
+            return Ok(None);
+        }
+        (0..vertex_count).map(|_| reader.f64()).collect::<Result<Vec<f64>, _>>().map(Some)
+    }
+
+    /// Decode the feature at `index` without reading any other features
+    pub fn feature(&self, index: usize) -> Result<Feature, ErrorWithStaticMessage> {
+        let mut reader = self.record(index)?;
+        let attributes = read_attributes(&mut reader)?;
+        let vertex_count = reader.u32()? as usize;
+        let mut geometry = Vec::with_capacity(vertex_count.min(reader.bytes.len() / 16));
+        for _ in 0..vertex_count {
+            geometry.push(Vector2::new(reader.f64()?, reader.f64()?));
+        }
+        let measures = self.read_measures(&mut reader, vertex_count)?;
+        Ok(Feature { attributes, geometry, measures })
+    }
+
+    /// Read the whole record of the feature at `index`, but only keep what
+    /// the indexes need. If this succeeds, so will [BinaryLayerView::feature]
+    /// for the same index.
+    pub fn summary(&self, index: usize) -> Result<FeatureSummary, ErrorWithStaticMessage> {
+        let mut reader = self.record(index)?;
+        let mut summary = FeatureSummary::new(read_attributes(&mut reader)?);
+        let vertex_count = reader.u32()? as usize;
+        for _ in 0..vertex_count {
+            summary.add_vertex(&Vector2::new(reader.f64()?, reader.f64()?));
+        }
+        self.read_measures(&mut reader, vertex_count)?;
+        Ok(summary)
+    }
+
+    /// Read every feature, so that a damaged file is found when it is opened
+    /// rather than when one of its features is first used
+    pub fn check(&self) -> Result<(), ErrorWithStaticMessage> {
+        (0..self.len()).try_for_each(|index| self.summary(index).map(drop))
+    }
+
+    /// Decode every feature into an owned [Layer]
+    pub fn to_layer(&self) -> Result<Layer, Box<dyn std::error::Error>> {
+        let metadata = self.metadata()?;
+        let features = (0..self.len())
+            .map(|index| self.feature(index))
+            .collect::<Result<Vec<Feature>, _>>()?;
+        Ok(Layer {
+            features,
+            source_metadata: metadata.source_metadata,
+            downloaded: metadata.downloaded,
+            spatial_reference: metadata.spatial_reference,
+            cache_metadata: metadata.cache_metadata,
+        })
+    }
+}
+
+fn read_attributes(reader: &mut Reader) -> Result<Attributes, ErrorWithStaticMessage> {
+    let road_length = reader.u16()? as usize;
+    Ok(Attributes {
+        ROAD: reader.string(road_length)?,
+        CWY: cwy_from_byte(reader.u8()?)?,
+        START_SLK: reader.f32()?,
+        END_SLK: reader.f32()?,
+        NETWORK_TYPE: reader.optional_string()?,
+        ROAD_NAME: reader.optional_string()?,
+        LG_NAME: reader.optional_string()?,
+        RA_NO: reader.optional_string()?,
+        START_TRUE_DIST: reader.optional_f32()?,
+        END_TRUE_DIST: reader.optional_f32()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_layer() -> Layer {
+        Layer {
+            features: vec![
+                Feature::new(
+                    Attributes {
+                        NETWORK_TYPE: Some("State Road".to_owned()),
+                        ROAD_NAME: Some("Albany Highway".to_owned()),
+                        LG_NAME: Some("City of Armadale".to_owned()),
+                        RA_NO: Some("07".to_owned()),
+                        START_TRUE_DIST: Some(0.0),
+                        END_TRUE_DIST: Some(1.52),
+                        ..Attributes::new("H001", Cwy::Left, 0.0, 1.5)
+                    },
+                    &[(115.0, -32.0), (115.01, -32.01)],
+                ),
+                Feature {
+                    measures: Some(vec![2250.0, 2400.0, 3000.0]),
+                    ..Feature::new(
+                        Attributes {
+                            NETWORK_TYPE: Some("Local Road".to_owned()),
+                            ROAD_NAME: Some("".to_owned()),
+                            ..Attributes::new("1110050", Cwy::Single, 2.25, 3.0)
+                        },
+                        &[(116.0, -31.0), (116.1, -31.1), (116.2, -31.2)],
+                    )
+                },
+            ],
+            source_metadata: Some(SourceMetadata {
+                count: 2,
+                last_edit_date: Some(1_690_000_000_000),
+            }),
+            downloaded: CalendarDate::new(2024, 6, 13),
+            spatial_reference: Some(SpatialReference {
+                wkid: Some(4326),
+                latestWkid: Some(4326),
+                wkt: None,
+            }),
+            cache_metadata: Some(CacheMetadata {
+                source_url: "https://a.b/arcgis/rest/services/X/MapServer/17/query".to_owned(),
+                feature_count: 2,
+                content_hash: "0123456789abcdef".to_owned(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_binary_format_round_trip() {
+        let layer = sample_layer();
+        let bytes = encode_layer(&layer).unwrap();
+        assert!(is_binary_format(&bytes));
+
+        let view = BinaryLayerView::new(&bytes).unwrap();
+        assert_eq!(view.len(), 2);
+
+        // features can be read directly, in any order
+        let second = view.feature(1).unwrap();
+        assert_eq!(second.attributes, layer.features[1].attributes);
+        assert_eq!(second.geometry.len(), 3);
+        assert_eq!(second.geometry[2].x, 116.2);
+        assert_eq!(second.measures, layer.features[1].measures);
+        assert!(view.feature(2).is_err());
+
+        let summary = view.summary(1).unwrap();
+        assert_eq!(summary.attributes, layer.features[1].attributes);
+        assert_eq!(summary.vertex_count, 3);
+        let (min, max) = summary.extent.unwrap();
+        assert_eq!((min.x, min.y, max.x, max.y), (116.0, -31.2, 116.2, -31.0));
+        assert!(view.check().is_ok());
+
+        let decoded = view.to_layer().unwrap();
+        assert_eq!(decoded.features.len(), 2);
+        assert_eq!(decoded.features[0].attributes, layer.features[0].attributes);
+        assert_eq!(decoded.features[0].geometry[1].y, -32.01);
+        assert_eq!(decoded.source_metadata, layer.source_metadata);
+        assert_eq!(decoded.downloaded, layer.downloaded);
+        assert_eq!(decoded.spatial_reference, layer.spatial_reference);
+        assert_eq!(decoded.cache_metadata, layer.cache_metadata);
+        assert!(decoded.features[0].measures.is_none());
+    }
+
+    #[test]
+    fn test_binary_format_reads_version_3() {
+        // version 3 records are the same without the trailing measures flag
+        let mut layer = sample_layer();
+        layer.features[1].measures = None;
+        let bytes = encode_layer(&layer).unwrap();
+        let view = BinaryLayerView::new(&bytes).unwrap();
+        let mut records: Vec<u8> = Vec::new();
+        let mut offsets: Vec<u64> = Vec::new();
+        for index in 0..view.len() {
+            offsets.push(records.len() as u64);
+            let (start, end) = (view.offset(index).unwrap(), view.offset(index + 1).unwrap());
+            records.extend_from_slice(&bytes[view.records_start..][start..end - 1]);
+        }
+        offsets.push(records.len() as u64);
+        let mut version_3 = bytes[..view.offsets_start].to_vec();
+        version_3[8..12].copy_from_slice(&3u32.to_le_bytes());
+        for offset in offsets {
+            version_3.extend_from_slice(&offset.to_le_bytes());
+        }
+        version_3.extend_from_slice(&records);
+
+        let decoded = BinaryLayerView::new(&version_3).unwrap().to_layer().unwrap();
+        assert_eq!(decoded.features[1].attributes, layer.features[1].attributes);
+        assert_eq!(decoded.features[1].geometry[2].x, 116.2);
+        assert!(decoded.features[1].measures.is_none());
+    }
+
+    #[test]
+    fn test_binary_format_rejects_bad_input() {
+        let bytes = encode_layer(&sample_layer()).unwrap();
+
+        // truncated
+        let view = BinaryLayerView::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(view.feature(1).is_err());
+        assert!(view.summary(1).is_err());
+        assert!(view.check().is_err());
+        assert!(BinaryLayerView::new(&bytes[..HEADER_LENGTH]).is_err());
+
+        // wrong version
+        let mut wrong_version = bytes.clone();
+        wrong_version[8] = 99;
+        assert!(BinaryLayerView::new(&wrong_version).is_err());
+        wrong_version[8] = 2;
+        assert!(BinaryLayerView::new(&wrong_version).is_err());
+
+        // not a binary file at all
+        assert!(!is_binary_format(b"\x04\x22\x4d\x18"));
+        assert!(BinaryLayerView::new(b"\x04\x22\x4d\x18").is_err());
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize, clap::ValueEnum)]
+#[allow(non_camel_case_types)]
+pub enum CacheFormat {
+    /// LZ4 compressed JSON. Compatible with all versions
+    json,
+    /// Versioned binary format which loads much faster. See `binary_format.rs`
+    binary,
+}
+
+impl CacheFormat {
+    /// Guess the format from the file extension; `.bin` is binary and
+    /// anything else is LZ4 compressed JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("bin") => CacheFormat::binary,
+            _ => CacheFormat::json,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_format_from_path() {
+        assert_eq!(CacheFormat::from_path(Path::new("./data/data.json.lz4")), CacheFormat::json);
+        assert_eq!(CacheFormat::from_path(Path::new("./data/data.bin")), CacheFormat::binary);
+        assert_eq!(CacheFormat::from_path(Path::new("")), CacheFormat::json);
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::Feature;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64 bit FNV-1a. Not cryptographic, but stable between builds and platforms
+/// so that the same data always has the same version.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_str(&mut self, value: &str) {
+        self.write(&(value.len() as u64).to_le_bytes());
+        self.write(value.as_bytes());
+    }
+
+    fn write_optional_str(&mut self, value: &Option<String>) {
+        match value {
+            Some(value) => {
+                self.write(&[1]);
+                self.write_str(value);
+            },
+            None => self.write(&[0]),
+        }
+    }
+}
+
+/// A hash of every attribute, vertex and measure of the features, in order,
+/// as 16 hex digits
+pub fn content_hash(features: &[Feature]) -> String {
+    let mut hasher = Fnv1a(FNV_OFFSET_BASIS);
+    hasher.write(&(features.len() as u64).to_le_bytes());
+    for feature in features {
+        let attributes = &feature.attributes;
+        hasher.write_str(&attributes.ROAD);
+        hasher.write(&[attributes.CWY as u8]);
+        hasher.write(&attributes.START_SLK.to_le_bytes());
+        hasher.write(&attributes.END_SLK.to_le_bytes());
+        hasher.write_optional_str(&attributes.NETWORK_TYPE);
+        hasher.write_optional_str(&attributes.ROAD_NAME);
+        hasher.write_optional_str(&attributes.LG_NAME);
+        hasher.write_optional_str(&attributes.RA_NO);
+        hasher.write(&attributes.START_TRUE_DIST.unwrap_or(f32::NAN).to_le_bytes());
+        hasher.write(&attributes.END_TRUE_DIST.unwrap_or(f32::NAN).to_le_bytes());
+        hasher.write(&(feature.geometry.len() as u64).to_le_bytes());
+        for vertex in feature.geometry.iter() {
+            hasher.write(&vertex.x.to_le_bytes());
+            hasher.write(&vertex.y.to_le_bytes());
+        }
+        match &feature.measures {
+            Some(measures) => {
+                hasher.write(&(measures.len() as u64).to_le_bytes());
+                for measure in measures.iter() {
+                    hasher.write(&measure.to_le_bytes());
+                }
+            },
+            None => hasher.write(&u64::MAX.to_le_bytes()),
+        }
+    }
+    format!("{:016x}", hasher.0)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// Records where the cached data came from, so that it is possible to tell
+/// which version of the network the server is answering from
+pub struct CacheMetadata {
+    /// The query url the features were downloaded from, or the path of the
+    /// local file they were loaded from
+    pub source_url: String,
+    /// Number of features saved. A cache file with a different number of
+    /// features is damaged.
+    pub feature_count: usize,
+    /// See [content_hash]
+    pub content_hash: String,
+}
+
+impl CacheMetadata {
+    /// Describe features which have just been downloaded from `source_url`
+    pub fn new(source_url: &str, features: &[Feature]) -> Self {
+        Self {
+            source_url: source_url.to_owned(),
+            feature_count: features.len(),
+            content_hash: content_hash(features),
+        }
+    }
+
+    /// Fail if the number of features loaded from a cache file does not
+    /// match the number that was saved. The hash is not checked because it is
+    /// slow to compute.
+    pub fn check(&self, feature_count: usize) -> Result<(), ErrorWithStaticMessage> {
+        if feature_count != self.feature_count {
+            return Err(ErrorWithStaticMessage::new(
+                "The data file does not contain the number of features recorded in its metadata",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Attributes, Cwy};
+
+    fn features() -> Vec<Feature> {
+        (0..3)
+            .map(|index| {
+                let attributes = Attributes {
+                    NETWORK_TYPE: Some("State Road".to_owned()),
+                    ..Attributes::new("H001", Cwy::Single, index as f32, index as f32 + 1.0)
+                };
+                Feature::new(attributes, &[(115.0, -32.0 - index as f64), (115.0, -33.0 - index as f64)])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let original = content_hash(&features());
+        assert_eq!(original.len(), 16);
+        assert_eq!(original, content_hash(&features()));
+
+        let mut moved = features();
+        moved[1].geometry[0].x += 0.000001;
+        assert_ne!(content_hash(&moved), original);
+
+        let mut renamed = features();
+        renamed[2].attributes.ROAD_NAME = Some("".to_owned());
+        assert_ne!(content_hash(&renamed), original);
+
+        let mut measured = features();
+        measured[0].measures = Some(vec![0.0, 1000.0]);
+        assert_ne!(content_hash(&measured), original);
+
+        assert_ne!(content_hash(&features()[..2]), original);
+    }
+
+    #[test]
+    fn test_cache_metadata_check() {
+        let metadata = CacheMetadata::new("https://a.b/query", &features());
+        assert_eq!(metadata.feature_count, 3);
+        assert!(metadata.check(features().len()).is_ok());
+        assert!(metadata.check(features().len() - 1).is_err());
+    }
+}
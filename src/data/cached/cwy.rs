@@ -1,4 +1,3 @@
-use super::super::esri_json;
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord)]
@@ -7,13 +6,3 @@ pub enum Cwy {
     Right,
     Single,
 }
-
-impl From<esri_json::EsriCwy> for Cwy {
-    fn from(value: esri_json::EsriCwy) -> Self {
-        match value {
-            esri_json::EsriCwy::Left => Self::Left,
-            esri_json::EsriCwy::Right => Self::Right,
-            esri_json::EsriCwy::Single => Self::Single,
-        }
-    }
-}
@@ -1,7 +1,8 @@
 use serde::{Serialize, Deserialize};
 use nickslinetoolsrust::vector2::Vector2;
+use crate::helpers::ErrorWithStaticMessage;
 use super::{
-    super::esri_json,
+    super::{esri_json, sources, FieldMapping},
     Attributes
 };
 
@@ -9,14 +10,312 @@ use super::{
 pub struct Feature {
     pub attributes: Attributes,
     pub geometry: Vec<Vector2>,
+    /// The `m` value of each vertex, when the data source has them. Used
+    /// to place SLKs along the geometry instead of assuming they are evenly
+    /// spread. Always increasing when present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub measures: Option<Vec<f64>>,
 }
 
+/// What the indexes need to know about a [Feature]. The indexes are built
+/// from these so that a memory mapped cache file does not have to be decoded
+/// in full when it is loaded.
+pub struct FeatureSummary {
+    pub attributes: Attributes,
+    pub vertex_count: usize,
+    /// The lowest and highest `x` and `y` of the vertices, as `(min, max)`.
+    /// `None` if there are no vertices.
+    pub extent: Option<(Vector2, Vector2)>,
+}
+
+impl FeatureSummary {
+    /// A summary of a feature with no vertices yet
+    pub fn new(attributes: Attributes) -> Self {
+        Self { attributes, vertex_count: 0, extent: None }
+    }
+
+    pub fn add_vertex(&mut self, vertex: &Vector2) {
+        self.vertex_count += 1;
+        self.extent = Some(match self.extent {
+            Some((min, max)) => (
+                Vector2::new(min.x.min(vertex.x), min.y.min(vertex.y)),
+                Vector2::new(max.x.max(vertex.x), max.y.max(vertex.y)),
+            ),
+            None => (*vertex, *vertex),
+        });
+    }
+}
+
+/// Measures are only useful if they increase along the path
+fn measures_are_usable(measures: &[f64], vertex_count: usize) -> bool {
+    measures.len() == vertex_count
+        && measures.windows(2).all(|pair| pair[1] >= pair[0])
+        && measures.last() > measures.first()
+}
+
+/// The distance along the geometry to each vertex, in the units of the
+/// coordinates, as a fraction of the total length. `None` if the geometry has
+/// no length.
+fn length_fractions(geometry: &[Vector2]) -> Option<Vec<f64>> {
+    let mut total = 0.0;
+    let mut cumulative = Vec::with_capacity(geometry.len());
+    cumulative.push(0.0);
+    for segment in geometry.windows(2) {
+        total += (segment[1].x - segment[0].x).hypot(segment[1].y - segment[0].y);
+        cumulative.push(total);
+    }
+    if total > 0.0 {
+        Some(cumulative.into_iter().map(|length| length / total).collect())
+    } else {
+        None
+    }
+}
+
+fn measure_fractions(measures: &[f64]) -> Vec<f64> {
+    let (first, last) = (measures[0], measures[measures.len() - 1]);
+    measures.iter().map(|m| (m - first) / (last - first)).collect()
+}
 
-impl From<esri_json::EsriFeature> for Feature {
-    fn from(item: esri_json::EsriFeature) -> Feature {
-        Feature {
-            attributes: item.attributes.into(),
-            geometry: item.geometry.paths[0].clone(),
+/// Map `value` from the scale `from` to the scale `to` by linear
+/// interpolation between matching entries. Both scales must increase from
+/// 0 to 1. Values outside 0 to 1 are returned unchanged.
+fn rescale(from: &[f64], to: &[f64], value: f64) -> f64 {
+    if !(0.0..=1.0).contains(&value) || from.len() < 2 {
+        return value;
+    }
+    let index = from.partition_point(|f| *f < value).clamp(1, from.len() - 1);
+    let (from_start, from_end) = (from[index - 1], from[index]);
+    let (to_start, to_end) = (to[index - 1], to[index]);
+    if from_end <= from_start {
+        return to_start;
+    }
+    to_start + (to_end - to_start) * (value - from_start) / (from_end - from_start)
+}
+
+/// The measure and the length along the geometry of each vertex of a feature,
+/// as fractions of the whole feature. See [Feature::measure_scale]
+pub struct MeasureScale {
+    measures: Vec<f64>,
+    lengths: Vec<f64>,
+}
+
+impl MeasureScale {
+    /// Convert a fraction of the way from the start to the end SLK of the
+    /// feature into a fraction of the length of its geometry
+    pub fn length_fraction(&self, measure_fraction: f64) -> f64 {
+        rescale(&self.measures, &self.lengths, measure_fraction)
+    }
+
+    /// The inverse of [MeasureScale::length_fraction]
+    pub fn measure_fraction(&self, length_fraction: f64) -> f64 {
+        rescale(&self.lengths, &self.measures, length_fraction)
+    }
+}
+
+fn lerp(start: f32, end: f32, fraction: f64) -> f32 {
+    start + (end - start) * fraction as f32
+}
+
+#[cfg(test)]
+impl Feature {
+    /// A feature without measures, for tests. See [Attributes::new]
+    pub fn new(attributes: Attributes, geometry: &[(f64, f64)]) -> Self {
+        Self {
+            attributes,
+            geometry: geometry.iter().map(|&(x, y)| Vector2::new(x, y)).collect(),
+            measures: None,
         }
     }
 }
+
+impl Feature {
+    pub fn summary(&self) -> FeatureSummary {
+        let mut summary = FeatureSummary::new(self.attributes.clone());
+        for vertex in self.geometry.iter() {
+            summary.add_vertex(vertex);
+        }
+        summary
+    }
+
+    /// Convert a feature downloaded from the rest service, reading the
+    /// attributes named by the [FieldMapping]. `has_z` and `has_m` come from
+    /// the feature set.
+    ///
+    /// Multipart polylines are split into one feature per part. The SLKs are
+    /// shared out between the parts using their `m` values if every part has
+    /// them, otherwise in proportion to the length of each part.
+    pub fn from_esri_feature(
+        item: esri_json::EsriFeature,
+        field_mapping: &FieldMapping,
+        has_z: bool,
+        has_m: bool,
+    ) -> Result<Vec<Feature>, ErrorWithStaticMessage> {
+        let fields: sources::Fields = item.attributes
+            .iter()
+            .map(|(key, value)| (key.clone(), sources::FieldValue::from(value)))
+            .collect();
+        let attributes = sources::attributes_from_fields(&fields, field_mapping)?;
+        let mut paths = item.geometry
+            .into_paths(has_z, has_m)
+            .ok_or(ErrorWithStaticMessage::new("Feature geometry has a vertex without an x or y value"))?;
+        for path in paths.iter_mut() {
+            path.measures = path.measures.take().filter(|measures| measures_are_usable(measures, path.vertices.len()));
+        }
+        if paths.len() <= 1 {
+            let path = paths.pop();
+            return Ok(vec![Feature {
+                attributes,
+                geometry: path.as_ref().map_or(Vec::new(), |path| path.vertices.clone()),
+                measures: path.and_then(|path| path.measures),
+            }]);
+        }
+        let part_ranges = Self::part_ranges(&paths);
+        Ok(paths
+            .into_iter()
+            .zip(part_ranges)
+            .map(|(path, (from, to))| {
+                let true_dist = attributes.START_TRUE_DIST.zip(attributes.END_TRUE_DIST);
+                Feature {
+                    attributes: Attributes {
+                        START_SLK: lerp(attributes.START_SLK, attributes.END_SLK, from),
+                        END_SLK: lerp(attributes.START_SLK, attributes.END_SLK, to),
+                        START_TRUE_DIST: true_dist.map(|(start, end)| lerp(start, end, from)),
+                        END_TRUE_DIST: true_dist.map(|(start, end)| lerp(start, end, to)),
+                        ..attributes.clone()
+                    },
+                    geometry: path.vertices,
+                    measures: path.measures,
+                }
+            })
+            .collect())
+    }
+
+    /// The start and end of each part as a fraction of the whole multipart
+    /// feature
+    fn part_ranges(paths: &[esri_json::EsriPath]) -> Vec<(f64, f64)> {
+        let measured: Option<Vec<(f64, f64)>> = paths
+            .iter()
+            .map(|path| path.measures.as_ref().map(|measures| (measures[0], measures[measures.len() - 1])))
+            .collect();
+        if let Some(measured) = measured {
+            let min = measured.iter().map(|range| range.0).fold(f64::INFINITY, f64::min);
+            let max = measured.iter().map(|range| range.1).fold(f64::NEG_INFINITY, f64::max);
+            if max > min {
+                return measured
+                    .into_iter()
+                    .map(|(start, end)| ((start - min) / (max - min), (end - min) / (max - min)))
+                    .collect();
+            }
+        }
+        let lengths: Vec<f64> = paths
+            .iter()
+            .map(|path| path.vertices
+                .windows(2)
+                .map(|segment| (segment[1].x - segment[0].x).hypot(segment[1].y - segment[0].y))
+                .sum())
+            .collect();
+        let total: f64 = lengths.iter().sum();
+        let mut cumulative = 0.0;
+        lengths
+            .iter()
+            .enumerate()
+            .map(|(index, length)| {
+                let range = if total > 0.0 {
+                    (cumulative / total, (cumulative + length) / total)
+                } else {
+                    (index as f64 / paths.len() as f64, (index + 1) as f64 / paths.len() as f64)
+                };
+                cumulative += length;
+                range
+            })
+            .collect()
+    }
+
+    /// The scale between the SLKs and the length of the geometry, worked out
+    /// once so it can be kept. `None` when the measures and lengths are the same; that
+    /// is, when the data source did not provide measures or the geometry has
+    /// no length.
+    pub fn measure_scale(&self) -> Option<MeasureScale> {
+        let measures = self.measures.as_ref()?;
+        Some(MeasureScale {
+            lengths: length_fractions(&self.geometry)?,
+            measures: measure_fractions(measures),
+        })
+    }
+
+    /// Convert a fraction of the length of the geometry of this feature into
+    /// a fraction of the way from its start to its end SLK. These are the same
+    /// unless the data source provided measures.
+    pub fn measure_fraction(&self, length_fraction: f64) -> f64 {
+        match self.measure_scale() {
+            Some(measure_scale) => measure_scale.measure_fraction(length_fraction),
+            None => length_fraction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Cwy;
+
+    fn parse(json: &str) -> esri_json::EsriFeature {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_from_esri_feature_single_part() {
+        let item = parse(r#"{"attributes":{"ROAD":"H001","CWY":"Single","START_SLK":1,"END_SLK":2},"geometry":{"paths":[[[115,-32],[115.01,-32]]]}}"#);
+        let features = Feature::from_esri_feature(item, &FieldMapping::default(), false, false).unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0].attributes.CWY, Cwy::Single);
+        assert_eq!(features[0].geometry.len(), 2);
+        assert!(features[0].measures.is_none());
+    }
+
+    #[test]
+    fn test_from_esri_feature_multipart() {
+        // the second part is three times as long as the first
+        let item = parse(r#"{"attributes":{"ROAD":"H001","CWY":"Single","START_SLK":0,"END_SLK":4,"START_TRUE_DIST":10,"END_TRUE_DIST":14},"geometry":{"paths":[[[0,0],[1,0]],[[1,0],[4,0]]]}}"#);
+        let features = Feature::from_esri_feature(item, &FieldMapping::default(), false, false).unwrap();
+        let ranges: Vec<(f32, f32, Option<f32>)> = features
+            .iter()
+            .map(|feature| (feature.attributes.START_SLK, feature.attributes.END_SLK, feature.attributes.START_TRUE_DIST))
+            .collect();
+        assert_eq!(ranges, vec![(0.0, 1.0, Some(10.0)), (1.0, 4.0, Some(11.0))]);
+
+        // with z and m values the parts are split by m, regardless of length
+        let item = parse(r#"{"attributes":{"ROAD":"H001","CWY":"Single","START_SLK":0,"END_SLK":4},"geometry":{"hasZ":true,"hasM":true,"paths":[[[0,0,5,100],[1,0,5,300]],[[1,0,5,300],[4,0,5,500]]]}}"#);
+        let features = Feature::from_esri_feature(item, &FieldMapping::default(), false, false).unwrap();
+        assert_eq!((features[0].attributes.END_SLK, features[1].attributes.START_SLK), (2.0, 2.0));
+        assert_eq!(features[1].measures, Some(vec![300.0, 500.0]));
+    }
+
+    #[test]
+    fn test_from_esri_feature_unusable_measures() {
+        let item = parse(r#"{"attributes":{"ROAD":"H001","CWY":"Single","START_SLK":0,"END_SLK":1},"geometry":{"paths":[[[0,0,5],[1,0,null]]]}}"#);
+        let features = Feature::from_esri_feature(item, &FieldMapping::default(), false, true).unwrap();
+        assert!(features[0].measures.is_none());
+        let item = parse(r#"{"attributes":{"ROAD":"H001","CWY":"Single","START_SLK":0,"END_SLK":1},"geometry":{"paths":[[[0,0,5],[1,0,2]]]}}"#);
+        let features = Feature::from_esri_feature(item, &FieldMapping::default(), false, true).unwrap();
+        assert!(features[0].measures.is_none());
+        let item = parse(r#"{"attributes":{"ROAD":"H001","CWY":"Single","START_SLK":0,"END_SLK":1},"geometry":{"paths":[[[0,0],[null,0]]]}}"#);
+        assert!(Feature::from_esri_feature(item, &FieldMapping::default(), false, false).is_err());
+    }
+
+    #[test]
+    fn test_feature_length_fraction() {
+        let item = parse(r#"{"attributes":{"ROAD":"H001","CWY":"Single","START_SLK":0,"END_SLK":1},"geometry":{"paths":[[[0,0,0],[1,0,0.75],[2,0,1]]]}}"#);
+        let mut feature = Feature::from_esri_feature(item, &FieldMapping::default(), false, true).unwrap().remove(0);
+        let measure_scale = feature.measure_scale().unwrap();
+        // three quarters of the SLK is on the first half of the line
+        assert_eq!(measure_scale.length_fraction(0.75), 0.5);
+        assert_eq!(measure_scale.length_fraction(0.375), 0.25);
+        assert_eq!(measure_scale.length_fraction(0.875), 0.75);
+        assert_eq!(feature.measure_fraction(0.5), 0.75);
+        assert_eq!(measure_scale.length_fraction(-0.5), -0.5);
+        feature.measures = None;
+        assert!(feature.measure_scale().is_none());
+        assert_eq!(feature.measure_fraction(0.5), 0.5);
+    }
+}
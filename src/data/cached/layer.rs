@@ -1,73 +1,139 @@
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     fs::{
         File,
         create_dir_all,
-        remove_file
+        remove_file,
+        rename
     },
+    io::Write,
     error::Error
 };
 
+use memmap2::Mmap;
+
 use lz_fear;
-use reqwest;
 use serde_json;
 use serde::{Deserialize, Serialize};
 
-use crate::helpers::ErrorWithStaticMessage;
+use crate::helpers::{CalendarDate, ErrorWithStaticMessage};
 use super::{
-    super::esri_json,
-    Feature
+    super::{download, snapshots, sources, DataSourceSettings, DownloadSettings, FieldMapping},
+    binary_format::{self, BinaryLayerView},
+    CacheFormat,
+    CacheMetadata,
+    Feature,
+    SourceMetadata,
+    SpatialReference
 };
 
 #[derive(Serialize, Deserialize, Debug)]
 /// This is the struct / document that will be saved to local storage
 pub struct Layer {
     pub features: Vec<Feature>,
+    /// Describes the rest service at the time the features were downloaded.
+    /// Missing from cache files created by older versions.
+    #[serde(default)]
+    pub source_metadata: Option<SourceMetadata>,
+    /// The day (UTC) the features were downloaded or loaded from the data
+    /// source. Missing from cache files created by older versions.
+    #[serde(default)]
+    pub downloaded: Option<CalendarDate>,
+    /// The coordinate system reported by the rest service. Missing from
+    /// cache files created by older versions and for local files.
+    #[serde(default)]
+    pub spatial_reference: Option<SpatialReference>,
+    /// Where the features came from, how many there are and a hash of their
+    /// content. Missing from cache files created by older versions.
+    #[serde(default)]
+    pub cache_metadata: Option<CacheMetadata>,
+}
+
+/// A binary cache file mapped into memory
+pub type MappedLayer = BinaryLayerView<Mmap>;
+
+/// The data read from a cache file, or fetched by
+/// [Layer::read_or_update_cache_data]
+pub enum CachedLayer {
+    /// Downloaded, or read from a JSON cache file
+    Decoded(Layer),
+    /// A binary cache file. Features are decoded when they are needed
+    Mapped(MappedLayer),
+}
+
+impl CachedLayer {
+    /// Decode every feature
+    pub fn into_layer(self) -> Result<Layer, Box<dyn Error>> {
+        match self {
+            CachedLayer::Decoded(layer) => Ok(layer),
+            CachedLayer::Mapped(mapped_layer) => mapped_layer.to_layer(),
+        }
+    }
 }
 
 impl Layer {
+    /// Download every feature from the rest service. If `partial_directory`
+    /// is given, pages are kept there until the download completes so that a
+    /// failed download can resume.
     pub async fn download_data(
         url:&String,
+        field_mapping:&FieldMapping,
+        download_settings:&DownloadSettings,
+        partial_directory:Option<PathBuf>,
     ) -> Result<Self, Box<dyn Error>> {
         
+        let client = download::RetryingClient::new(download_settings, url)?;
+        let source_metadata = match SourceMetadata::fetch(url, &client).await {
+            Ok(source_metadata) => Some(source_metadata),
+            Err(error_message) => {
+                println!("WARNING: Could not fetch the feature count and last edit date because '{}'", error_message);
+                None
+            }
+        };
+
         let mut document_to_save = Self {
             features: Vec::with_capacity(183_000),
+            source_metadata,
+            downloaded: None,
+            spatial_reference: None,
+            cache_metadata: None,
         };
 
-        let mut offset: usize = 0;
-        const MAX_LOOPS: usize = 500;
-        let mut loop_safety_limit = MAX_LOOPS;
-        loop {
-            loop_safety_limit -= 1;
-            if loop_safety_limit <= 0 {
-                return Err(
-                    Box::new(ErrorWithStaticMessage::new("Download failed. Loop safety limit exceeded."))
-                );
+        let mut collector = sources::FeatureCollector::new();
+        let mut multipart_count: usize = 0;
+        download::download_feature_sets(&client, url, download_settings, partial_directory.as_deref(), |json| {
+            if document_to_save.spatial_reference.is_none() {
+                document_to_save.spatial_reference = json.spatial_reference().cloned();
             }
-
-            let url = format!("{}&resultOffset={}", url, offset);
-            let json: esri_json::EsriFeatureSet = reqwest::get(url).await?.json().await?;
-            if json.features.len()==0 {
-                break;
-            }
-            offset += json.features.len();
-            document_to_save.features.extend(
-                json.features
-                    .into_iter()
-                    .map(|item| item.into()),
-            );
-
-
-            match json.exceededTransferLimit {
-                Some(true) => (),
-                Some(false) | None => break,
+            for item in json.features {
+                match Feature::from_esri_feature(item, field_mapping, json.hasZ, json.hasM) {
+                    Ok(parts) => {
+                        if parts.len() > 1 {
+                            multipart_count += 1;
+                        }
+                        collector.extend(parts);
+                    }
+                    Err(error) => collector.skip(error),
+                }
             }
-        }
+            Ok(())
+        }).await?;
+        document_to_save.features.extend(collector.into_features());
         if document_to_save.features.len()==0{
             return Err(
                 Box::new(ErrorWithStaticMessage::new("Download failed. No features were received."))
             );
         }
+        if multipart_count > 0 {
+            println!("INFO: Split {} multipart features into one feature per part", multipart_count);
+        }
+        match &document_to_save.spatial_reference {
+            Some(spatial_reference) if !spatial_reference.is_wgs84() => println!(
+                "WARNING: The data source reported the spatial reference {:?}. Coordinates are expected to be longitude and latitude (EPSG:4326); add `&outSR=4326` to the data source url",
+                spatial_reference
+            ),
+            _ => (),
+        }
         println!("INFO: Download completed. Sorting data.");
         document_to_save
             .features
@@ -75,37 +141,197 @@ impl Layer {
         Ok(document_to_save)
     }
 
+    /// Load a cache file in either [CacheFormat], decoding every feature.
+    /// See [Layer::open_file]
     pub fn load_data_from_file(file_path:&Path) -> Result<Self, Box<dyn Error>> {
+        Self::open_file(file_path)?.into_layer()
+    }
+
+    /// Open a cache file in either [CacheFormat]. The format is detected from
+    /// the content of the file, not the file name. A binary file stays mapped
+    /// into memory, and its features are only decoded when they are used.
+    /// Fails if any feature can't be read or the number of features does not
+    /// match the [CacheMetadata] saved with them.
+    pub fn open_file(file_path:&Path) -> Result<CachedLayer, Box<dyn Error>> {
         let file = File::open(file_path)?;
-        let lz_frame_reader = lz_fear::framed::LZ4FrameReader::new(file)?;
+        if file.metadata()?.len() == 0 {
+            return Err(Box::new(ErrorWithStaticMessage::new("The data file is empty")));
+        }
+        // Safety: The map is kept for as long as the data is served. New
+        // cache files and snapshots are renamed into place rather than
+        // written over the old file, so the mapped file is never modified
+        // by this program. It is not expected to be modified by any other.
+        let mapped_file = unsafe { Mmap::map(&file)? };
+        if binary_format::is_binary_format(&mapped_file) {
+            let mapped_layer = BinaryLayerView::new(mapped_file)?;
+            mapped_layer.check()?;
+            if let Some(cache_metadata) = &mapped_layer.metadata()?.cache_metadata {
+                cache_metadata.check(mapped_layer.len())?;
+            }
+            return Ok(CachedLayer::Mapped(mapped_layer));
+        }
+        let lz_frame_reader = lz_fear::framed::LZ4FrameReader::new(&mapped_file[..])?;
         let lz_frame_io_reader = lz_frame_reader.into_read();
-        let result = serde_json::from_reader(lz_frame_io_reader)?;
-        Ok(result)
+        let layer:Self = serde_json::from_reader(lz_frame_io_reader)?;
+        if let Some(cache_metadata) = &layer.cache_metadata {
+            cache_metadata.check(layer.features.len())?;
+        }
+        Ok(CachedLayer::Decoded(layer))
+    }
+
+    /// `<file name>.<suffix>` in the same folder as `file_path`. `None` if
+    /// the path has no file name
+    fn sibling_path(file_path:&Path, suffix:&str) -> Option<PathBuf> {
+        let file_name = file_path.file_name()?;
+        Some(file_path.with_file_name(format!("{}.{}", file_name.to_string_lossy(), suffix)))
+    }
+
+    /// Where the previous cache file is kept when a new one is saved
+    pub fn backup_path_for(file_path:&Path) -> Option<PathBuf> {
+        Self::sibling_path(file_path, "bak")
+    }
+
+    fn write_file(file_path:&Path, data:&Self, cache_format:&CacheFormat) -> Result<(), Box<dyn Error>> {
+        let mut file_out = File::create(file_path)?;
+        match cache_format {
+            CacheFormat::binary => {
+                file_out.write_all(&binary_format::encode_layer(data)?)?;
+            },
+            CacheFormat::json => {
+                let res = serde_json::to_vec(&data)?;
+                let compressor = lz_fear::framed::CompressionSettings::default();
+                compressor.compress(&res[..], &file_out)?;
+            }
+        }
+        // make sure the content is on disk before the file is renamed into place
+        file_out.sync_all()?;
+        Ok(())
+    }
+
+    /// Save to a temporary file next to `file_path`, then rename it into
+    /// place, so that `file_path` never holds a partly written file.
+    pub fn save_data_to_file(file_path:&Path, data:&Self, cache_format:&CacheFormat) -> Result<(), Box<dyn Error>> {
+        Self::save_replacing(file_path, data, cache_format, None)
+    }
+
+    /// Like [Layer::save_data_to_file], but if `backup_path` is given the
+    /// file being replaced is moved there instead of being overwritten.
+    fn save_replacing(
+        file_path:&Path,
+        data:&Self,
+        cache_format:&CacheFormat,
+        backup_path:Option<&Path>,
+    ) -> Result<(), Box<dyn Error>> {
+        let temporary_path = Self::sibling_path(file_path, "tmp")
+            .ok_or(ErrorWithStaticMessage::new("The data file path does not include a file name"))?;
+        if let Err(error_message) = Self::write_file(&temporary_path, data, cache_format) {
+            let _ = remove_file(&temporary_path);
+            return Err(error_message);
+        }
+        if let Some(backup_path) = backup_path {
+            if file_path.is_file() {
+                rename(file_path, backup_path)?;
+            }
+        }
+        rename(&temporary_path, file_path)?;
+        Ok(())
     }
 
-    pub fn save_data_to_file(file_path:&Path, data:&Self) -> Result<(), Box<dyn Error>> {
-        let file_out = File::create(file_path)?;
-        let res = serde_json::to_vec(&data)?;
-        let compressor = lz_fear::framed::CompressionSettings::default();
-        let result = compressor.compress(&res[..], &file_out)?;
-        Ok(result)
+    /// Load the previous cache file kept by [Layer::save_replacing], if there
+    /// is one and it is readable
+    fn load_backup(file_path:&Path) -> Option<CachedLayer> {
+        let backup_path = Self::backup_path_for(file_path).filter(|backup_path| backup_path.is_file())?;
+        println!("INFO: Loading the previous data from '{}'.", backup_path.display());
+        match Self::open_file(&backup_path) {
+            Ok(loaded_data) => {
+                println!("WARNING: Serving the previous data. It will be replaced next time fresh data is downloaded");
+                Some(loaded_data)
+            },
+            Err(error_message) => {
+                println!("WARNING: Could not open '{}' because '{}'", backup_path.display(), error_message);
+                None
+            }
+        }
+    }
+
+    /// Download from the data source url, or load it if it is a local file.
+    /// Returns the data and the url or path it came from.
+    async fn fetch_fresh_data(
+        data_source_settings:&DataSourceSettings,
+        path_to_data_cache_file:&Path,
+    ) -> Result<(Self, String), Box<dyn Error>> {
+        let url_to_download_new_data = &data_source_settings.url_to_download_new_data;
+        match sources::local_source_path(url_to_download_new_data) {
+            Some(local_source_path) => {
+                println!("INFO: Loading fresh data from local file '{}'", local_source_path.display());
+                Ok((
+                    sources::load_local_source(&local_source_path, &data_source_settings.field_mapping)?,
+                    local_source_path.display().to_string(),
+                ))
+            },
+            None => {
+                let field_mapping = &data_source_settings.field_mapping;
+                let query_url = field_mapping.query_url(url_to_download_new_data)?;
+                println!("INFO: Downloading fresh data");
+                println!("INFO: Using '{}'", query_url);
+                println!("INFO: Please wait, this can take some time...");
+                let new_data = Self::download_data(
+                    &query_url,
+                    field_mapping,
+                    &data_source_settings.download_settings,
+                    download::partial_directory_for(path_to_data_cache_file),
+                ).await?;
+                Ok((new_data, query_url))
+            }
+        }
     }
 
+    /// Load the cache file, or get fresh data if it can't be loaded or
+    /// `force_update` is set.
+    ///
+    /// The cache file is only replaced once fresh data has been received. The
+    /// file it replaces is kept as a backup. If fresh data can't be got and
+    /// `fallback_on_failure` is set, the cache file or its backup is loaded
+    /// instead; otherwise the error is returned. Use `fallback_on_failure` at
+    /// startup, when serving old data is better than not starting at all, but
+    /// not when reloading, so that a failed reload is reported as a failure.
+    /// With `offline` set in the [DataSourceSettings] fresh data is never
+    /// fetched, and the backup is not loaded either; it fails if the cache
+    /// file can't be loaded.
     pub async fn read_or_update_cache_data (
-        path_to_data_cache_file:&String,
-        url_to_download_new_data:&String,
+        data_source_settings:&DataSourceSettings,
         force_update:&bool,
-    ) -> Result<Self, Box<dyn Error>> {
+        fallback_on_failure:&bool,
+    ) -> Result<CachedLayer, Box<dyn Error>> {
         
-        let path_to_data_cache_file = Path::new(path_to_data_cache_file);
+        let path_to_data_cache_file = Path::new(&data_source_settings.path_to_data_cache_file);
+        let cache_format = data_source_settings.cache_format.unwrap_or_else(|| CacheFormat::from_path(path_to_data_cache_file));
+
+        if *force_update && data_source_settings.offline {
+            return Err(Box::new(ErrorWithStaticMessage::new("Fresh data can not be downloaded because --offline is set")));
+        }
     
+        // A cache file which fails to load is not worth keeping as a backup,
+        // or loading if the download fails
+        let existing_file_is_good = *force_update && path_to_data_cache_file.is_file() && match Self::open_file(path_to_data_cache_file) {
+            Ok(_) => true,
+            Err(error_message) => {
+                println!("WARNING: Could not open the existing data file because '{}'. It will not be kept as a backup.", error_message);
+                false
+            }
+        };
         if !force_update {
             println!("INFO: Loading data from '{}'.", path_to_data_cache_file.display());
-            match Self::load_data_from_file(path_to_data_cache_file) {
+            match Self::open_file(path_to_data_cache_file) {
                 Ok(loaded_data) => return Ok(loaded_data),
+                Err(error_message) if data_source_settings.offline => println!("WARNING: Could not open the specified data file because '{}'.", error_message),
                 Err(error_message) => println!("WARNING: Could not open the specified data file because '{}'. Will try to download fresh data.", error_message),
             }
         }
+
+        if data_source_settings.offline {
+            return Err(Box::new(ErrorWithStaticMessage::new("The data file could not be loaded, and fresh data was not downloaded because --offline is set")));
+        }
     
         if let Some(parent) = path_to_data_cache_file.parent() {
             if !parent.is_dir() {
@@ -118,33 +344,190 @@ impl Layer {
                 }
             }
         }
-    
-        if path_to_data_cache_file.is_file() {
-            println!("WARNING: The file '{}' already exists. Attempting to delete it", path_to_data_cache_file.display());
-            match remove_file(path_to_data_cache_file) {
-                Ok(_) => (),
-                Err(error_message) => {
-                    println!("WARNING: Could not delete the file '{}' because '{}'", path_to_data_cache_file.display(), error_message);
-                }
-            };
-        }
-        
         
-        println!("INFO: Downloading fresh data");
-        println!("INFO: Using '{}'", url_to_download_new_data);
-        println!("INFO: Please wait, this can take some time...");
-        let new_data = Self::download_data(url_to_download_new_data).await?;
+        let (mut new_data, source_url) = match Self::fetch_fresh_data(data_source_settings, path_to_data_cache_file).await {
+            Ok(fresh_data) => fresh_data,
+            Err(error_message) => {
+                println!("WARNING: Could not get fresh data because '{}'", error_message);
+                if !fallback_on_failure {
+                    return Err(error_message);
+                }
+                if existing_file_is_good {
+                    println!("INFO: Loading the existing data from '{}' instead.", path_to_data_cache_file.display());
+                    match Self::open_file(path_to_data_cache_file) {
+                        Ok(loaded_data) => return Ok(loaded_data),
+                        Err(error_message) => println!("WARNING: Could not open the specified data file because '{}'.", error_message),
+                    }
+                }
+                return Self::load_backup(path_to_data_cache_file).ok_or(error_message);
+            }
+        };
+        new_data.downloaded = Some(CalendarDate::today());
+        let cache_metadata = CacheMetadata::new(&source_url, &new_data.features);
+        println!("INFO: Data version is '{}'", cache_metadata.content_hash);
+        new_data.cache_metadata = Some(cache_metadata);
         
         println!("INFO: Saving data to file.");
-        match Self::save_data_to_file(path_to_data_cache_file, &new_data){
+        let backup_path = Self::backup_path_for(path_to_data_cache_file).filter(|_| existing_file_is_good);
+        match Self::save_replacing(path_to_data_cache_file, &new_data, &cache_format, backup_path.as_deref()){
             Ok(_) => (),
             Err(error_message) => {
                 println!("WARNING: Could not save the specified data file '{}' because '{}'", path_to_data_cache_file.display(), error_message);
                 println!("WARNING: Data will be downloaded again next time the program is run");
             }
         }
+
+        if let Some(snapshot_directory) = &data_source_settings.snapshot_directory {
+            match snapshots::save_snapshot(Path::new(snapshot_directory), &new_data) {
+                Ok(snapshot_path) => println!("INFO: Saved snapshot '{}'", snapshot_path.display()),
+                Err(error_message) => println!("WARNING: Could not save a snapshot to '{}' because '{}'", snapshot_directory, error_message),
+            }
+        }
         
     
-        Ok(new_data)
+        Ok(CachedLayer::Decoded(new_data))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use super::super::{Attributes, Cwy};
+    use crate::settings::Settings;
+
+    fn layer(road: &str) -> Layer {
+        let features = vec![Feature::new(Attributes::new(road, Cwy::Single, 0.0, 1.0), &[(115.0, -32.0), (115.01, -32.0)])];
+        Layer {
+            cache_metadata: Some(CacheMetadata::new("test", &features)),
+            features,
+            source_metadata: None,
+            downloaded: None,
+            spatial_reference: None,
+        }
+    }
+
+    fn road(loaded: CachedLayer) -> String {
+        loaded.into_layer().unwrap().features[0].attributes.ROAD.clone()
+    }
+
+    /// A folder of its own for each test. The data source is a local file
+    /// which does not exist, so fetching fresh data always fails
+    fn setup(name: &str, extra_args: &[&str]) -> (PathBuf, DataSourceSettings) {
+        let directory = std::env::temp_dir().join(format!("nicklinref_test_layer_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        create_dir_all(&directory).unwrap();
+        let data_file = directory.join("data.bin");
+        let missing_source = directory.join("missing.geojson");
+        let args = [
+            &[
+                "none.exe",
+                "--data-file", data_file.to_str().unwrap(),
+                "--data-source-url", missing_source.to_str().unwrap(),
+            ],
+            extra_args,
+        ].concat();
+        let data_source_settings = Settings::parse_from(args).get_data_source_settings();
+        (data_file, data_source_settings)
+    }
+
+    #[test]
+    fn test_save_keeps_backup() {
+        let (data_file, _) = setup("save", &[]);
+        let backup_file = Layer::backup_path_for(&data_file).unwrap();
+        Layer::save_replacing(&data_file, &layer("H001"), &CacheFormat::binary, Some(&backup_file)).unwrap();
+        assert!(!backup_file.exists());
+        Layer::save_replacing(&data_file, &layer("H002"), &CacheFormat::binary, Some(&backup_file)).unwrap();
+        assert_eq!(Layer::load_data_from_file(&data_file).unwrap().features[0].attributes.ROAD, "H002");
+        assert_eq!(Layer::load_data_from_file(&backup_file).unwrap().features[0].attributes.ROAD, "H001");
+        assert!(!data_file.with_file_name("data.bin.tmp").exists());
+        std::fs::remove_dir_all(data_file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_open_file() {
+        let (data_file, _) = setup("open", &[]);
+        Layer::save_data_to_file(&data_file, &layer("H001"), &CacheFormat::binary).unwrap();
+        assert!(matches!(Layer::open_file(&data_file).unwrap(), CachedLayer::Mapped(_)));
+        // a damaged feature is found when the file is opened
+        let bytes = std::fs::read(&data_file).unwrap();
+        std::fs::write(&data_file, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(Layer::open_file(&data_file).is_err());
+
+        Layer::save_data_to_file(&data_file, &layer("H002"), &CacheFormat::json).unwrap();
+        assert!(matches!(Layer::open_file(&data_file).unwrap(), CachedLayer::Decoded(_)));
+        std::fs::remove_dir_all(data_file.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    /// A cache file which can't be read is not deleted, and the backup is
+    /// served when fresh data can't be downloaded
+    async fn test_failed_download_falls_back() {
+        let (data_file, data_source_settings) = setup("fallback", &[]);
+        Layer::save_data_to_file(&Layer::backup_path_for(&data_file).unwrap(), &layer("H001"), &CacheFormat::binary).unwrap();
+        std::fs::write(&data_file, b"not a cache file").unwrap();
+        let loaded = Layer::read_or_update_cache_data(&data_source_settings, &false, &true).await.unwrap();
+        assert_eq!(road(loaded), "H001");
+        assert!(data_file.is_file());
+        // but not when reloading
+        assert!(Layer::read_or_update_cache_data(&data_source_settings, &false, &false).await.is_err());
+
+        // with --force-update-data the existing file is loaded instead
+        Layer::save_data_to_file(&data_file, &layer("H002"), &CacheFormat::binary).unwrap();
+        let loaded = Layer::read_or_update_cache_data(&data_source_settings, &true, &true).await.unwrap();
+        assert_eq!(road(loaded), "H002");
+        assert!(Layer::read_or_update_cache_data(&data_source_settings, &true, &false).await.is_err());
+
+        // unless it can't be read
+        std::fs::write(&data_file, b"not a cache file").unwrap();
+        let loaded = Layer::read_or_update_cache_data(&data_source_settings, &true, &true).await.unwrap();
+        assert_eq!(road(loaded), "H001");
+        std::fs::remove_dir_all(data_file.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    /// With --force-update-data a cache file which can't be read is replaced
+    /// without being moved over the backup
+    async fn test_damaged_file_not_kept_as_backup() {
+        let (data_file, data_source_settings) = setup("damaged", &[]);
+        std::fs::write(data_file.with_file_name("missing.geojson"), r#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {"ROAD": "H003", "CWY": "Single", "START_SLK": 0.0, "END_SLK": 1.0},
+                "geometry": {"type": "LineString", "coordinates": [[115.0, -32.0], [115.01, -32.0]]}
+            }]
+        }"#).unwrap();
+        let backup_file = Layer::backup_path_for(&data_file).unwrap();
+        Layer::save_data_to_file(&backup_file, &layer("H001"), &CacheFormat::binary).unwrap();
+        std::fs::write(&data_file, b"not a cache file").unwrap();
+        let loaded = Layer::read_or_update_cache_data(&data_source_settings, &true, &false).await.unwrap();
+        assert_eq!(road(loaded), "H003");
+        assert_eq!(road(Layer::open_file(&backup_file).unwrap()), "H001");
+
+        // a good file is kept
+        Layer::read_or_update_cache_data(&data_source_settings, &true, &false).await.unwrap();
+        assert_eq!(road(Layer::open_file(&backup_file).unwrap()), "H003");
+        std::fs::remove_dir_all(data_file.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_offline() {
+        let (data_file, data_source_settings) = setup("offline", &["--offline"]);
+        assert!(Layer::read_or_update_cache_data(&data_source_settings, &false, &true).await.is_err());
+        assert!(Layer::read_or_update_cache_data(&data_source_settings, &true, &true).await.is_err());
+
+        // the backup is not loaded instead
+        Layer::save_data_to_file(&Layer::backup_path_for(&data_file).unwrap(), &layer("H001"), &CacheFormat::binary).unwrap();
+        assert!(Layer::read_or_update_cache_data(&data_source_settings, &false, &true).await.is_err());
+        std::fs::write(&data_file, b"not a cache file").unwrap();
+        assert!(Layer::read_or_update_cache_data(&data_source_settings, &false, &true).await.is_err());
+        assert!(data_file.is_file());
+
+        Layer::save_data_to_file(&data_file, &layer("H002"), &CacheFormat::binary).unwrap();
+        let loaded = Layer::read_or_update_cache_data(&data_source_settings, &false, &false).await.unwrap();
+        assert_eq!(road(loaded), "H002");
+        std::fs::remove_dir_all(data_file.parent().unwrap()).unwrap();
+    }
+}
@@ -5,7 +5,23 @@ mod attributes;
 pub use attributes::Attributes;
 
 mod layer;
-pub use layer::Layer;
+pub use layer::{CachedLayer, Layer, MappedLayer};
 
 mod feature;
-pub use feature::Feature;
\ No newline at end of file
+pub use feature::{Feature, FeatureSummary, MeasureScale};
+
+mod source_metadata;
+pub use source_metadata::SourceMetadata;
+
+mod spatial_reference;
+pub use spatial_reference::SpatialReference;
+
+/// Where the cached data was downloaded from, and a hash of its content
+mod cache_metadata;
+pub use cache_metadata::{content_hash, CacheMetadata};
+
+mod binary_format;
+pub use binary_format::LayerMetadata;
+
+mod cache_format;
+pub use cache_format::CacheFormat;
\ No newline at end of file
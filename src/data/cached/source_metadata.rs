@@ -0,0 +1,41 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use super::super::{download::{layer_info_url, RetryingClient}, esri_json};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A summary of the data hosted by the rest service, used to decide if the
+/// data has changed since it was last downloaded
+pub struct SourceMetadata {
+    /// Number of features reported by `&returnCountOnly=true`
+    pub count: usize,
+    /// Milliseconds since the unix epoch. Not all services report this.
+    pub last_edit_date: Option<i64>,
+}
+
+impl SourceMetadata {
+    /// Ask the rest service how many features it has and when it was last
+    /// edited, without downloading any features
+    pub async fn fetch(url: &String, client: &RetryingClient) -> Result<Self, Box<dyn Error>> {
+        let count_url = format!("{}&returnCountOnly=true", url);
+        let count_response: esri_json::EsriCountResponse = client.get_json(&count_url).await?;
+
+        // Not all services report editing info. Failing to fetch it is not
+        // an error
+        let last_edit_date = match layer_info_url(url) {
+            Some(info_url) => client
+                .get_json::<esri_json::EsriLayerInfo>(&info_url)
+                .await
+                .ok()
+                .and_then(|info| info.editingInfo)
+                .and_then(|editing_info| editing_info.lastEditDate),
+            None => None,
+        };
+
+        Ok(Self {
+            count: count_response.count,
+            last_edit_date,
+        })
+    }
+}
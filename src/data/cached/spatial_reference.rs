@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+/// The coordinate system reported by the data source. Uses the same field
+/// names as EsriJSON.
+///
+/// See reference
+/// <https://developers.arcgis.com/documentation/common-data-types/geometry-objects.htm#GUID-DFF0E738-5A42-40BC-A811-ACCB5814BABC>
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpatialReference {
+    #[serde(default)]
+    pub wkid: Option<u32>,
+    #[serde(default)]
+    pub latestWkid: Option<u32>,
+    #[serde(default)]
+    pub wkt: Option<String>,
+}
+
+impl SpatialReference {
+    /// True if the coordinates are longitude and latitude in WGS84
+    /// (EPSG:4326), which is what every route assumes
+    pub fn is_wgs84(&self) -> bool {
+        self.latestWkid.or(self.wkid) == Some(4326)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spatial_reference_is_wgs84() {
+        let spatial_reference: SpatialReference = serde_json::from_str(r#"{"wkid":4326,"latestWkid":4326}"#).unwrap();
+        assert!(spatial_reference.is_wgs84());
+        let spatial_reference: SpatialReference = serde_json::from_str(r#"{"wkid":102100,"latestWkid":3857}"#).unwrap();
+        assert!(!spatial_reference.is_wgs84());
+        let spatial_reference: SpatialReference = serde_json::from_str(r#"{"wkt":"GEOGCS[...]"}"#).unwrap();
+        assert!(!spatial_reference.is_wgs84());
+    }
+}
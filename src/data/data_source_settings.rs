@@ -0,0 +1,26 @@
+use super::{cached::CacheFormat, DownloadSettings, FieldMapping};
+
+#[derive(Debug, Clone, PartialEq)]
+/// Everything needed to find, download and cache the road network data
+pub struct DataSourceSettings {
+    /// File path to where the cache data file is/will be stored
+    pub path_to_data_cache_file: String,
+    /// Url of the esri rest service hosting the road network data
+    pub url_to_download_new_data: String,
+    /// Format used when saving the cache file. If `None` the format is
+    /// guessed from the file extension
+    pub cache_format: Option<CacheFormat>,
+    /// How the fields of the data source map onto the cached attributes
+    pub field_mapping: FieldMapping,
+    /// Retries, paging and concurrency used when downloading from the rest
+    /// service
+    pub download_settings: DownloadSettings,
+    /// Folder where a dated copy of each download is kept. If `None` no
+    /// snapshots are saved
+    pub snapshot_directory: Option<String>,
+    /// Refuse to load data with more validation errors than this. If `None`
+    /// data is loaded regardless of errors
+    pub max_data_errors: Option<usize>,
+    /// Never download; only load the cache file, and fail if it can't be read
+    pub offline: bool,
+}
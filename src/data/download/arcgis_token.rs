@@ -0,0 +1,216 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::{super::esri_json::EsriErrorResponse, DownloadSettings};
+
+/// Generated tokens are replaced this long before they expire
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Lifetime asked for when generating a token
+const TOKEN_EXPIRATION_MINUTES: &str = "60";
+
+#[derive(Deserialize)]
+struct GenerateTokenResponse {
+    token: String,
+    /// Milliseconds since the unix epoch
+    expires: Option<i64>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct AuthInfo {
+    tokenServicesUrl: Option<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+/// The response from `.../arcgis/rest/info?f=json`
+struct RestInfo {
+    authInfo: Option<AuthInfo>,
+}
+
+/// The root of the rest api, eg `https://a.b/arcgis/rest` for
+/// `https://a.b/arcgis/rest/services/X/MapServer/17/query?...`
+fn rest_root(url: &str) -> Option<&str> {
+    let path = url.split('?').next()?;
+    let index = path.find("/rest/services")?;
+    Some(&path[..index + "/rest".len()])
+}
+
+#[derive(Clone)]
+struct GeneratedToken {
+    token: String,
+    expires: Option<SystemTime>,
+}
+
+impl GeneratedToken {
+    fn expires_soon(&self) -> bool {
+        matches!(self.expires, Some(expires) if SystemTime::now() + REFRESH_MARGIN >= expires)
+    }
+}
+
+/// Requests tokens from the `generateToken` endpoint of the server, and
+/// requests a new one when the current token is about to expire or is
+/// rejected
+pub struct TokenGenerator {
+    /// If `None`, found from `rest/info` when the first token is generated
+    token_url: Option<String>,
+    rest_root: Option<String>,
+    username: String,
+    password: String,
+    current: Mutex<Option<GeneratedToken>>,
+}
+
+impl TokenGenerator {
+    /// The `tokenServicesUrl` advertised by the server, or else the usual
+    /// location for a stand alone ArcGIS Server
+    async fn discover_token_url(&self, client: &reqwest::Client, rest_root: &str) -> String {
+        let info_url = format!("{}/info?f=json", rest_root);
+        let advertised = match client.get(&info_url).send().await {
+            Ok(response) => response
+                .json::<RestInfo>()
+                .await
+                .ok()
+                .and_then(|info| info.authInfo)
+                .and_then(|auth_info| auth_info.tokenServicesUrl),
+            Err(_) => None,
+        };
+        advertised.unwrap_or_else(|| format!("{}/tokens/generateToken", rest_root.trim_end_matches("/rest")))
+    }
+
+    async fn generate(&self, client: &reqwest::Client) -> Result<GeneratedToken, String> {
+        let token_url = match (&self.token_url, &self.rest_root) {
+            (Some(token_url), _) => token_url.clone(),
+            (None, Some(rest_root)) => self.discover_token_url(client, rest_root).await,
+            (None, None) => return Err("the token url is not known".to_owned()),
+        };
+        println!("INFO: Generating an ArcGIS token for '{}' using '{}'", self.username, token_url);
+        let text = client
+            .post(&token_url)
+            .form(&[
+                ("username", self.username.as_str()),
+                ("password", self.password.as_str()),
+                ("client", "requestip"),
+                ("expiration", TOKEN_EXPIRATION_MINUTES),
+                ("f", "json"),
+            ])
+            .send()
+            .await
+            .map_err(|error| error.to_string())?
+            .text()
+            .await
+            .map_err(|error| error.to_string())?;
+        match serde_json::from_str::<GenerateTokenResponse>(&text) {
+            Ok(response) => Ok(GeneratedToken {
+                token: response.token,
+                expires: response.expires.map(|millis| UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)),
+            }),
+            Err(_) => Err(match serde_json::from_str::<EsriErrorResponse>(&text) {
+                Ok(response) => format!(
+                    "generateToken responded with error {} {}",
+                    response.error.code.unwrap_or_default(),
+                    response.error.message.unwrap_or_default()
+                ),
+                Err(_) => "generateToken returned an unexpected response".to_owned(),
+            }),
+        }
+    }
+}
+
+#[derive(Clone)]
+/// Where the token sent with each request comes from
+pub enum TokenSource {
+    None,
+    Fixed(String),
+    Generated(Arc<TokenGenerator>),
+}
+
+impl TokenSource {
+    pub fn new(settings: &DownloadSettings, data_source_url: &str) -> Result<Self, ErrorWithStaticMessage> {
+        match (&settings.NLR_ARCGIS_TOKEN, &settings.NLR_ARCGIS_USERNAME, &settings.NLR_ARCGIS_PASSWORD) {
+            (Some(token), _, _) => Ok(Self::Fixed(token.clone())),
+            (None, Some(username), Some(password)) => {
+                let rest_root = rest_root(data_source_url).map(str::to_owned);
+                if settings.NLR_ARCGIS_TOKEN_URL.is_none() && rest_root.is_none() {
+                    return Err(ErrorWithStaticMessage::new(
+                        "The token url could not be worked out from the data source url. Set --arcgis-token-url"
+                    ));
+                }
+                Ok(Self::Generated(Arc::new(TokenGenerator {
+                    token_url: settings.NLR_ARCGIS_TOKEN_URL.clone(),
+                    rest_root,
+                    username: username.clone(),
+                    password: password.clone(),
+                    current: Mutex::new(None),
+                })))
+            },
+            (None, Some(_), None) | (None, None, Some(_)) => Err(ErrorWithStaticMessage::new(
+                "--arcgis-username and --arcgis-password must be set together"
+            )),
+            (None, None, None) => Ok(Self::None),
+        }
+    }
+
+    /// The token to add to the next request, generating one if needed
+    pub async fn token(&self, client: &reqwest::Client) -> Result<Option<String>, String> {
+        match self {
+            Self::None => Ok(None),
+            Self::Fixed(token) => Ok(Some(token.clone())),
+            Self::Generated(generator) => {
+                let current = generator.current.lock().unwrap().clone();
+                if let Some(current) = current.filter(|current| !current.expires_soon()) {
+                    return Ok(Some(current.token));
+                }
+                // Several pages may generate a token at the same time. That
+                // is harmless; the last one stored is used from then on
+                let generated = generator.generate(client).await?;
+                let token = generated.token.clone();
+                *generator.current.lock().unwrap() = Some(generated);
+                Ok(Some(token))
+            },
+        }
+    }
+
+    /// Called when the server rejects the token. Returns true if a new token
+    /// will be generated for the next request
+    pub fn invalidate(&self) -> bool {
+        match self {
+            Self::Generated(generator) => {
+                *generator.current.lock().unwrap() = None;
+                true
+            },
+            Self::None | Self::Fixed(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rest_root() {
+        assert_eq!(
+            rest_root("https://a.b/arcgis/rest/services/X/MapServer/17/query?where=1%3D1"),
+            Some("https://a.b/arcgis/rest")
+        );
+        assert_eq!(rest_root("https://a.b/server/rest/services/X/FeatureServer/0/query"), Some("https://a.b/server/rest"));
+        assert_eq!(rest_root("https://a.b/data.json?x=/rest/services"), None);
+    }
+
+    #[test]
+    fn test_generated_token_expiry() {
+        let token = |seconds_from_now: u64| GeneratedToken {
+            token: "x".to_owned(),
+            expires: Some(SystemTime::now() + Duration::from_secs(seconds_from_now)),
+        };
+        assert!(token(10).expires_soon());
+        assert!(!token(3600).expires_soon());
+        assert!(!GeneratedToken { token: "x".to_owned(), expires: None }.expires_soon());
+    }
+}
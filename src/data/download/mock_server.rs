@@ -0,0 +1,215 @@
+//! A small stand-in for an ArcGIS MapServer layer, serving numbered features
+//! from a local port so that downloads can be tested without the network.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+use warp::{http::StatusCode, reply::Response, Filter, Reply};
+
+#[derive(Clone)]
+pub struct MockOptions {
+    pub feature_count: usize,
+    /// The most features returned for one query
+    pub max_record_count: usize,
+    /// Whether `maxRecordCount` is included in the layer description
+    pub advertise_max_record_count: bool,
+    pub supports_pagination: bool,
+    /// Requests without a valid token in the `X-Esri-Authorization` header
+    /// are refused. Tokens are generated for the user name `user` with
+    /// password `secret`
+    pub require_token: bool,
+}
+
+#[derive(Default)]
+pub struct MockState {
+    /// The next this many feature queries fail with `503`
+    pub fail_next: usize,
+    /// Queries with this `resultOffset` fail with `500` until it is cleared
+    pub fail_offset: Option<usize>,
+    /// The parameters of every feature query received
+    pub feature_queries: Vec<HashMap<String, String>>,
+    /// The `X-Esri-Authorization` header of every feature query received
+    pub authorization_headers: Vec<Option<String>>,
+    /// Number of tokens generated so far
+    pub issued_tokens: usize,
+    /// The only token accepted. Clear it to make the server reject the token
+    /// it issued last
+    pub valid_token: Option<String>,
+    pub address: Option<SocketAddr>,
+}
+
+pub struct MockArcGisServer {
+    pub url: String,
+    pub address: SocketAddr,
+    pub state: Arc<Mutex<MockState>>,
+}
+
+fn json_response(value: &serde_json::Value) -> Response {
+    warp::reply::json(value).into_response()
+}
+
+/// The error the rest service returns, with a `200` status, for a missing or
+/// wrong token
+fn token_error(options: &MockOptions, state: &Mutex<MockState>, authorization: &Option<String>) -> Option<Response> {
+    if !options.require_token {
+        return None;
+    }
+    let token = authorization.as_ref().and_then(|authorization| authorization.strip_prefix("Bearer ")).map(str::to_owned);
+    match token {
+        None => Some(json_response(&json!({"error": {"code": 499, "message": "Token Required"}}))),
+        Some(token) if state.lock().unwrap().valid_token.as_ref() != Some(&token) =>
+            Some(json_response(&json!({"error": {"code": 498, "message": "Invalid Token"}}))),
+        Some(_) => None,
+    }
+}
+
+fn generate_token(state: &Mutex<MockState>, form: HashMap<String, String>) -> Response {
+    let field = |key: &str| form.get(key).map(String::as_str);
+    if field("username") != Some("user") || field("password") != Some("secret") || field("f") != Some("json") {
+        return json_response(&json!({"error": {"code": 400, "message": "Unable to generate token."}}));
+    }
+    let mut state = state.lock().unwrap();
+    state.issued_tokens += 1;
+    let token = format!("token-{}", state.issued_tokens);
+    state.valid_token = Some(token.clone());
+    let expires = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() + 3_600_000;
+    json_response(&json!({"token": token, "expires": expires as u64, "ssl": false}))
+}
+
+/// Feature `index` has object id `(index + 1) * 10` and covers SLK `index` to
+/// `index + 1`
+fn feature(index: usize) -> serde_json::Value {
+    let x = 115.0 + index as f64 * 0.01;
+    json!({
+        "attributes": {
+            "OBJECTID": (index + 1) * 10,
+            "ROAD": "H001",
+            "CWY": "Single",
+            "START_SLK": index,
+            "END_SLK": index + 1,
+        },
+        "geometry": {"paths": [[[x, -32.0], [x + 0.01, -32.0]]]},
+    })
+}
+
+/// Read `b` from a where clause like `(1=1) AND (OBJECTID >= a AND OBJECTID <= b)`
+fn where_bound(where_clause: &str, prefix: &str) -> Option<usize> {
+    where_clause
+        .split(prefix)
+        .nth(1)?
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn query(options: &MockOptions, state: &Mutex<MockState>, parameters: HashMap<String, String>, authorization: Option<String>) -> Response {
+    if let Some(response) = token_error(options, state, &authorization) {
+        return response;
+    }
+    let parameter = |key: &str| parameters.get(key).and_then(|value| value.parse::<usize>().ok());
+    if parameters.get("returnCountOnly").map(String::as_str) == Some("true") {
+        return json_response(&json!({"count": options.feature_count}));
+    }
+    if parameters.get("returnIdsOnly").map(String::as_str) == Some("true") {
+        // deliberately not in order
+        let object_ids: Vec<usize> = (0..options.feature_count).rev().map(|index| (index + 1) * 10).collect();
+        return json_response(&json!({"objectIdFieldName": "OBJECTID", "objectIds": object_ids}));
+    }
+
+    let offset = parameter("resultOffset");
+    {
+        let mut state = state.lock().unwrap();
+        state.feature_queries.push(parameters.clone());
+        state.authorization_headers.push(authorization);
+        if state.fail_next > 0 {
+            state.fail_next -= 1;
+            return warp::reply::with_status("Service Unavailable", StatusCode::SERVICE_UNAVAILABLE).into_response();
+        }
+        if offset.is_some() && offset == state.fail_offset {
+            return warp::reply::with_status("Internal Server Error", StatusCode::INTERNAL_SERVER_ERROR).into_response();
+        }
+    }
+    if offset.is_some() && !options.supports_pagination {
+        return json_response(&json!({"error": {"code": 400, "message": "Pagination is not supported."}}));
+    }
+
+    let where_clause = parameters.get("where").cloned().unwrap_or_default();
+    let first = where_bound(&where_clause, "OBJECTID >= ").unwrap_or(0);
+    let last = where_bound(&where_clause, "OBJECTID <= ").unwrap_or(usize::MAX);
+    let matching: Vec<usize> = (0..options.feature_count)
+        .filter(|index| (first..=last).contains(&((index + 1) * 10)))
+        .skip(offset.unwrap_or(0))
+        .collect();
+    let limit = parameter("resultRecordCount").unwrap_or(usize::MAX).min(options.max_record_count);
+    let features: Vec<serde_json::Value> = matching.iter().take(limit).map(|index| feature(*index)).collect();
+    json_response(&json!({
+        "geometryType": "esriGeometryPolyline",
+        "features": features,
+        "exceededTransferLimit": matching.len() > limit,
+    }))
+}
+
+impl MockArcGisServer {
+    /// Must be called from within a tokio runtime
+    pub fn start(options: MockOptions) -> Self {
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        let info_state = state.clone();
+        let rest_info = warp::path!("arcgis" / "rest" / "info")
+            .map(move || {
+                let address = info_state.lock().unwrap().address.unwrap();
+                json_response(&json!({
+                    "authInfo": {
+                        "isTokenBasedSecurity": true,
+                        "tokenServicesUrl": format!("http://{}/arcgis/tokens/generateToken", address),
+                    },
+                }))
+            });
+
+        let token_state = state.clone();
+        let tokens = warp::path!("arcgis" / "tokens" / "generateToken")
+            .and(warp::post())
+            .and(warp::body::form::<HashMap<String, String>>())
+            .map(move |form| generate_token(&token_state, form));
+
+        let info_options = options.clone();
+        let layer_state = state.clone();
+        let layer_info = warp::path!("arcgis" / "rest" / "services" / "Test" / "MapServer" / "0")
+            .and(warp::header::optional::<String>("x-esri-authorization"))
+            .map(move |authorization: Option<String>| {
+                if let Some(response) = token_error(&info_options, &layer_state, &authorization) {
+                    return response;
+                }
+                let mut info = json!({
+                    "objectIdField": "OBJECTID",
+                    "advancedQueryCapabilities": {"supportsPagination": info_options.supports_pagination},
+                });
+                if info_options.advertise_max_record_count {
+                    info["maxRecordCount"] = json!(info_options.max_record_count);
+                }
+                json_response(&info)
+            });
+
+        let query_state = state.clone();
+        let features = warp::path!("arcgis" / "rest" / "services" / "Test" / "MapServer" / "0" / "query")
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::header::optional::<String>("x-esri-authorization"))
+            .map(move |parameters, authorization| query(&options, &query_state, parameters, authorization));
+
+        let routes = rest_info.or(tokens).or(layer_info).or(features);
+        let (address, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        state.lock().unwrap().address = Some(address);
+        Self {
+            url: format!("http://{}/arcgis/rest/services/Test/MapServer/0/query?where=1%3D1&outFields=*&f=json", address),
+            address,
+            state,
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use std::{collections::VecDeque, error::Error, path::Path};
+
+use tokio::task::JoinHandle;
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::esri_json::{EsriCountResponse, EsriFeatureSet, EsriLayerInfo, EsriObjectIdsResponse};
+
+mod download_settings;
+pub use download_settings::{DownloadSettings, PagingMode};
+
+/// Builds and modifies rest service query urls
+mod query_url;
+pub use query_url::{and_where, layer_info_url, with_parameters};
+use query_url::has_parameter;
+
+/// Finds the token sent with requests to secured services
+mod arcgis_token;
+
+mod retrying_client;
+pub use retrying_client::RetryingClient;
+
+mod pages;
+use pages::{plan_object_id_pages, plan_offset_pages, PageRequest};
+
+/// Keeps downloaded pages on disk so that a failed download can resume
+mod partial_download;
+pub use partial_download::partial_directory_for;
+use partial_download::{Manifest, PartialDownload};
+
+#[cfg(test)]
+mod mock_server;
+
+/// Used if neither the settings nor the layer description give a page size
+const DEFAULT_PAGE_SIZE: usize = 1000;
+
+/// The responses received for one [PageRequest]. Usually one, but more if
+/// the service returned fewer features than requested.
+#[derive(Default)]
+struct FetchedPage {
+    texts: Vec<String>,
+    feature_sets: Vec<EsriFeatureSet>,
+}
+
+async fn fetch_page(
+    client: RetryingClient,
+    query_url: String,
+    object_id_field: Option<String>,
+    page: PageRequest,
+) -> Result<FetchedPage, ErrorWithStaticMessage> {
+    let mut fetched = FetchedPage::default();
+    let mut request = page;
+    loop {
+        let url = request.url(&query_url, object_id_field.as_deref())?;
+        let (text, feature_set): (String, EsriFeatureSet) = client.get_json_and_text(&url).await?;
+        let received = feature_set.features.len();
+        let exceeded_transfer_limit = feature_set.exceededTransferLimit == Some(true);
+        fetched.texts.push(text);
+        fetched.feature_sets.push(feature_set);
+        match request {
+            // The service may return fewer features than requested if its
+            // limit is lower than the page size; ask for the rest
+            PageRequest::Offset { offset, count } if received > 0 && received < count => {
+                request = PageRequest::Offset { offset: offset + received, count: count - received };
+            },
+            PageRequest::ObjectIds { count, .. } if received < count && exceeded_transfer_limit => {
+                return Err(ErrorWithStaticMessage::new(
+                    "Download failed. The rest service returned fewer features than requested; try a smaller --download-page-size."
+                ));
+            },
+            _ => return Ok(fetched),
+        }
+    }
+}
+
+/// A page which is either being fetched or was saved by an earlier attempt
+enum PendingPage {
+    Fetching(JoinHandle<Result<FetchedPage, ErrorWithStaticMessage>>),
+    Saved,
+}
+
+/// Pages are fetched concurrently but handed on in order. Requests still in
+/// flight are cancelled if the download stops early.
+struct PageQueue(VecDeque<PendingPage>);
+
+impl Drop for PageQueue {
+    fn drop(&mut self) {
+        for pending_page in self.0.iter() {
+            if let PendingPage::Fetching(handle) = pending_page {
+                handle.abort();
+            }
+        }
+    }
+}
+
+/// Reports progress every 10%
+struct Progress {
+    expected: usize,
+    received: usize,
+    reported_tenths: usize,
+}
+
+impl Progress {
+    fn add(&mut self, count: usize) {
+        self.received += count;
+        let tenths = (self.received * 10 / self.expected.max(1)).min(10);
+        if tenths > self.reported_tenths {
+            self.reported_tenths = tenths;
+            println!("INFO: Downloaded {} of {} features ({}%)", self.received, self.expected, tenths * 10);
+        }
+    }
+}
+
+/// The page size requested in the settings, limited to the `maxRecordCount`
+/// of the layer
+fn choose_page_size(requested: Option<usize>, max_record_count: Option<usize>) -> usize {
+    let max_record_count = max_record_count.filter(|max| *max > 0);
+    match (requested, max_record_count) {
+        (Some(requested), Some(max)) if requested > max => {
+            println!("WARNING: The rest service returns at most {} features per request; using that as the page size instead of {}", max, requested);
+            max
+        },
+        (Some(requested), _) => requested,
+        (None, Some(max)) => max,
+        (None, None) => DEFAULT_PAGE_SIZE,
+    }
+}
+
+/// Download every feature matching `query_url` from the rest service. Each
+/// feature set is passed to `on_feature_set` in the order of the service.
+///
+/// Pages are requested `NLR_DOWNLOAD_CONCURRENCY` at a time, and failed
+/// requests are retried with exponential backoff. If `partial_directory` is
+/// given, pages are saved there as they arrive so that if the download fails
+/// the next attempt only fetches the pages which are missing.
+pub async fn download_feature_sets<F>(
+    client: &RetryingClient,
+    query_url: &str,
+    settings: &DownloadSettings,
+    partial_directory: Option<&Path>,
+    mut on_feature_set: F,
+) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(EsriFeatureSet) -> Result<(), ErrorWithStaticMessage>,
+{
+
+    let layer_info = match layer_info_url(query_url) {
+        Some(info_url) => client.get_json::<EsriLayerInfo>(&info_url).await.unwrap_or_else(|_| {
+            println!("WARNING: Could not read the layer description. Assuming the service supports pagination");
+            EsriLayerInfo::default()
+        }),
+        None => EsriLayerInfo::default(),
+    };
+    let page_size = choose_page_size(
+        settings.NLR_DOWNLOAD_PAGE_SIZE.map(|page_size| page_size as usize),
+        layer_info.maxRecordCount,
+    );
+    let paging = match settings.NLR_DOWNLOAD_PAGING {
+        PagingMode::auto if layer_info.supports_pagination() == Some(false) => {
+            println!("INFO: The rest service does not support pagination. Paging by object id");
+            PagingMode::object_id
+        },
+        PagingMode::auto => PagingMode::offset,
+        paging => paging,
+    };
+
+    let (page_url, object_id_field, pages) = match paging {
+        PagingMode::object_id => {
+            let ids_url = with_parameters(query_url, &[("returnIdsOnly", "true")])?;
+            let response: EsriObjectIdsResponse = client.get_json(&ids_url).await?;
+            let pages = plan_object_id_pages(response.objectIds.unwrap_or_default(), page_size);
+            (query_url.to_owned(), Some(response.objectIdFieldName), pages)
+        },
+        PagingMode::auto | PagingMode::offset => {
+            let count_url = with_parameters(query_url, &[("returnCountOnly", "true")])?;
+            let response: EsriCountResponse = client.get_json(&count_url).await?;
+            // Without an order, the service is free to return overlapping
+            // pages
+            let page_url = match &layer_info.objectIdField {
+                Some(field) if !has_parameter(query_url, "orderByFields") => with_parameters(query_url, &[("orderByFields", field)])?,
+                _ => query_url.to_owned(),
+            };
+            (page_url, None, plan_offset_pages(response.count, page_size))
+        },
+    };
+    if pages.is_empty() {
+        return Err(Box::new(ErrorWithStaticMessage::new("Download failed. No features were received.")));
+    }
+
+    let partial_download = partial_directory.and_then(|directory| PartialDownload::open(directory, &Manifest {
+        query_url: page_url.clone(),
+        object_id_field: object_id_field.clone(),
+        pages: pages.clone(),
+    }));
+    if let Some(partial_download) = &partial_download {
+        let saved_page_count = partial_download.saved_page_count(pages.len());
+        if saved_page_count > 0 {
+            println!("INFO: Resuming an earlier download. {} of {} pages are already saved", saved_page_count, pages.len());
+        }
+    }
+
+    let expected: usize = pages.iter().map(PageRequest::expected_count).sum();
+    let concurrency = settings.NLR_DOWNLOAD_CONCURRENCY.max(1) as usize;
+    println!(
+        "INFO: Downloading {} features in {} pages of up to {}, {} at a time",
+        expected, pages.len(), page_size, concurrency
+    );
+    let mut progress = Progress { expected, received: 0, reported_tenths: 0 };
+    let spawn_fetch = |page: &PageRequest| tokio::spawn(fetch_page(
+        client.clone(),
+        page_url.clone(),
+        object_id_field.clone(),
+        page.clone(),
+    ));
+
+    let mut pass_on = |feature_sets: Vec<EsriFeatureSet>| -> Result<(), ErrorWithStaticMessage> {
+        for feature_set in feature_sets {
+            progress.add(feature_set.features.len());
+            on_feature_set(feature_set)?;
+        }
+        Ok(())
+    };
+
+    let mut queue = PageQueue(VecDeque::with_capacity(concurrency));
+    let mut next_page = 0;
+    for index in 0..pages.len() {
+        while next_page < pages.len() && next_page < index + concurrency {
+            queue.0.push_back(match &partial_download {
+                Some(partial_download) if partial_download.has_page(next_page) => PendingPage::Saved,
+                _ => PendingPage::Fetching(spawn_fetch(&pages[next_page])),
+            });
+            next_page += 1;
+        }
+        let handle = match queue.0.pop_front() {
+            Some(PendingPage::Fetching(handle)) => handle,
+            _ => match partial_download.as_ref().and_then(|partial_download| partial_download.load_page(index)) {
+                Some(feature_sets) => {
+                    pass_on(feature_sets)?;
+                    continue;
+                },
+                // A saved page which cannot be read is fetched again
+                None => spawn_fetch(&pages[index]),
+            },
+        };
+        let fetched = handle
+            .await
+            .map_err(|_| ErrorWithStaticMessage::new("Download failed. A download task stopped unexpectedly."))??;
+        if let Some(partial_download) = &partial_download {
+            partial_download.save_page(index, &fetched.texts);
+        }
+        pass_on(fetched.feature_sets)?;
+    }
+    drop(queue);
+
+    if progress.received != expected {
+        println!(
+            "WARNING: Expected {} features but received {}. The data may have been edited during the download",
+            expected, progress.received
+        );
+    }
+    if let Some(partial_download) = partial_download {
+        partial_download.remove();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use mock_server::{MockArcGisServer, MockOptions};
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[clap(flatten)]
+        download_settings: DownloadSettings,
+    }
+
+    /// Retries are immediate so that the tests are quick
+    fn settings(args: &[&str]) -> DownloadSettings {
+        TestCli::parse_from([&["none.exe", "--download-retry-delay", "1"], args].concat()).download_settings
+    }
+
+    fn options(feature_count: usize, max_record_count: usize) -> MockOptions {
+        MockOptions {
+            feature_count,
+            max_record_count,
+            advertise_max_record_count: true,
+            supports_pagination: true,
+            require_token: false,
+        }
+    }
+
+    /// Returns the `START_SLK` of each feature received, in order
+    async fn download_with(
+        client: &RetryingClient,
+        url: &str,
+        settings: &DownloadSettings,
+        partial_directory: Option<&Path>,
+    ) -> Result<Vec<i64>, Box<dyn Error>> {
+        let mut slks = Vec::new();
+        download_feature_sets(client, url, settings, partial_directory, |feature_set| {
+            slks.extend(feature_set.features.iter().map(|feature| feature.attributes["START_SLK"].as_i64().unwrap()));
+            Ok(())
+        }).await?;
+        Ok(slks)
+    }
+
+    async fn download(
+        server: &MockArcGisServer,
+        settings: &DownloadSettings,
+        partial_directory: Option<&Path>,
+    ) -> Result<Vec<i64>, Box<dyn Error>> {
+        let client = RetryingClient::new(settings, &server.url)?;
+        download_with(&client, &server.url, settings, partial_directory).await
+    }
+
+    fn queried_offsets(server: &MockArcGisServer) -> Vec<String> {
+        server.state.lock().unwrap().feature_queries
+            .iter()
+            .filter_map(|parameters| parameters.get("resultOffset").cloned())
+            .collect()
+    }
+
+    #[test]
+    fn test_choose_page_size() {
+        assert_eq!(choose_page_size(None, None), DEFAULT_PAGE_SIZE);
+        assert_eq!(choose_page_size(None, Some(2000)), 2000);
+        assert_eq!(choose_page_size(Some(500), Some(2000)), 500);
+        assert_eq!(choose_page_size(Some(5000), Some(2000)), 2000);
+        assert_eq!(choose_page_size(Some(5000), Some(0)), 5000);
+    }
+
+    #[tokio::test]
+    async fn test_download_offset_pages_with_retries() {
+        let server = MockArcGisServer::start(options(25, 10));
+        server.state.lock().unwrap().fail_next = 3;
+        let slks = download(&server, &settings(&["--download-concurrency", "3"]), None).await.unwrap();
+        assert_eq!(slks, (0..25).collect::<Vec<i64>>());
+        let mut offsets = queried_offsets(&server);
+        offsets.sort();
+        offsets.dedup();
+        assert_eq!(offsets, vec!["0", "10", "20"]);
+        let queries = &server.state.lock().unwrap().feature_queries;
+        assert!(queries.iter().all(|parameters| parameters.get("orderByFields").map(String::as_str) == Some("OBJECTID")));
+    }
+
+    #[tokio::test]
+    async fn test_download_gives_up() {
+        let server = MockArcGisServer::start(options(25, 10));
+        server.state.lock().unwrap().fail_next = 3;
+        assert!(download(&server, &settings(&["--download-retries", "2", "--download-concurrency", "1"]), None).await.is_err());
+    }
+
+    #[tokio::test]
+    /// If the service returns fewer features than requested, the rest of the
+    /// page is requested
+    async fn test_download_short_pages() {
+        let server = MockArcGisServer::start(MockOptions {
+            advertise_max_record_count: false,
+            ..options(25, 4)
+        });
+        let slks = download(&server, &settings(&[]), None).await.unwrap();
+        assert_eq!(slks, (0..25).collect::<Vec<i64>>());
+        assert_eq!(queried_offsets(&server), vec!["0", "4", "8", "12", "16", "20", "24"]);
+    }
+
+    #[tokio::test]
+    async fn test_download_object_id_pages() {
+        let server = MockArcGisServer::start(MockOptions {
+            supports_pagination: false,
+            ..options(25, 10)
+        });
+        let slks = download(&server, &settings(&["--download-page-size", "6", "--download-concurrency", "2"]), None).await.unwrap();
+        assert_eq!(slks, (0..25).collect::<Vec<i64>>());
+        assert!(queried_offsets(&server).is_empty());
+        assert_eq!(server.state.lock().unwrap().feature_queries.len(), 5);
+
+        // The page size is too big for the service
+        let server = MockArcGisServer::start(MockOptions {
+            advertise_max_record_count: false,
+            ..options(25, 4)
+        });
+        let result = download(&server, &settings(&["--download-paging", "object-id", "--download-page-size", "6"]), None).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_generates_tokens() {
+        let server = MockArcGisServer::start(MockOptions {
+            require_token: true,
+            ..options(25, 10)
+        });
+        let credentials = settings(&["--arcgis-username", "user", "--arcgis-password", "secret"]);
+        let client = RetryingClient::new(&credentials, &server.url).unwrap();
+        let slks = download_with(&client, &server.url, &credentials, None).await.unwrap();
+        assert_eq!(slks.len(), 25);
+        assert_eq!(server.state.lock().unwrap().issued_tokens, 1);
+        {
+            // the token is sent in a header and never in the url
+            let state = server.state.lock().unwrap();
+            assert!(state.feature_queries.iter().all(|parameters| !parameters.contains_key("token")));
+            assert!(state.authorization_headers.iter().all(|header| header.as_deref() == Some("Bearer token-1")));
+        }
+
+        // The token is rejected before it was due to expire; a new one is
+        // generated
+        server.state.lock().unwrap().valid_token = None;
+        let slks = download_with(&client, &server.url, &credentials, None).await.unwrap();
+        assert_eq!(slks.len(), 25);
+        assert_eq!(server.state.lock().unwrap().issued_tokens, 2);
+
+        let fixed_token = settings(&["--arcgis-token", "token-2"]);
+        assert_eq!(download(&server, &fixed_token, None).await.unwrap().len(), 25);
+        assert!(download(&server, &settings(&[]), None).await.is_err());
+        let wrong_password = settings(&["--arcgis-username", "user", "--arcgis-password", "wrong", "--download-retries", "1"]);
+        assert!(download(&server, &wrong_password, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_through_proxy() {
+        let server = MockArcGisServer::start(options(25, 10));
+        // The host does not exist, so this only works if the request goes to
+        // the proxy
+        let url = server.url.replace(&server.address.to_string(), "nicklinref-test.invalid");
+        let proxy = format!("http://{}", server.address);
+        let settings = settings(&["--proxy", &proxy, "--download-retries", "0"]);
+        let client = RetryingClient::new(&settings, &url).unwrap();
+        assert_eq!(download_with(&client, &url, &settings, None).await.unwrap().len(), 25);
+    }
+
+    #[test]
+    fn test_retrying_client_settings() {
+        let url = "https://a.b/arcgis/rest/services/X/MapServer/17/query?where=1%3D1";
+        assert!(RetryingClient::new(&settings(&["--arcgis-username", "user"]), url).is_err());
+        assert!(RetryingClient::new(&settings(&["--arcgis-username", "user", "--arcgis-password", "secret"]), "https://a.b/data/query").is_err());
+        assert!(RetryingClient::new(&settings(&["--ca-cert", "./does/not/exist.pem"]), url).is_err());
+        assert!(RetryingClient::new(&settings(&["--arcgis-username", "user", "--arcgis-password", "secret"]), url).is_ok());
+    }
+
+    #[tokio::test]
+    /// A download which fails part way through resumes from the last page
+    /// saved instead of starting again
+    async fn test_download_resumes() {
+        let directory = std::env::temp_dir().join(format!("nicklinref_test_download_{}", std::process::id()));
+        let server = MockArcGisServer::start(options(25, 5));
+        server.state.lock().unwrap().fail_offset = Some(15);
+        let settings = settings(&["--download-retries", "1", "--download-concurrency", "2"]);
+        assert!(download(&server, &settings, Some(&directory)).await.is_err());
+        assert!(directory.join("page_000002.json").is_file());
+
+        {
+            let mut state = server.state.lock().unwrap();
+            state.fail_offset = None;
+            state.feature_queries.clear();
+        }
+        let slks = download(&server, &settings, Some(&directory)).await.unwrap();
+        assert_eq!(slks, (0..25).collect::<Vec<i64>>());
+        let offsets = queried_offsets(&server);
+        assert!(!offsets.contains(&"0".to_string()));
+        assert!(offsets.contains(&"15".to_string()));
+        assert!(!directory.exists());
+    }
+}
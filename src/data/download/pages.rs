@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::query_url::{and_where, with_parameters};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+/// One request for a page of features
+pub enum PageRequest {
+    /// `count` features starting at `offset`, in the order of the service
+    Offset { offset: usize, count: usize },
+    /// The `count` features with object ids from `first` to `last` inclusive
+    ObjectIds { first: i64, last: i64, count: usize },
+}
+
+impl PageRequest {
+    /// The number of features the service should return
+    pub fn expected_count(&self) -> usize {
+        match self {
+            Self::Offset { count, .. } | Self::ObjectIds { count, .. } => *count,
+        }
+    }
+
+    /// `object_id_field` is only needed for [PageRequest::ObjectIds]
+    pub fn url(&self, query_url: &str, object_id_field: Option<&str>) -> Result<String, ErrorWithStaticMessage> {
+        match self {
+            Self::Offset { offset, count } => with_parameters(query_url, &[
+                ("resultOffset", &offset.to_string()),
+                ("resultRecordCount", &count.to_string()),
+            ]),
+            Self::ObjectIds { first, last, .. } => {
+                let field = object_id_field
+                    .ok_or(ErrorWithStaticMessage::new("The rest service did not name its object id field"))?;
+                and_where(query_url, &format!("{0} >= {1} AND {0} <= {2}", field, first, last))
+            },
+        }
+    }
+}
+
+pub fn plan_offset_pages(count: usize, page_size: usize) -> Vec<PageRequest> {
+    (0..count)
+        .step_by(page_size)
+        .map(|offset| PageRequest::Offset { offset, count: page_size.min(count - offset) })
+        .collect()
+}
+
+pub fn plan_object_id_pages(mut object_ids: Vec<i64>, page_size: usize) -> Vec<PageRequest> {
+    object_ids.sort_unstable();
+    object_ids.dedup();
+    object_ids
+        .chunks(page_size)
+        .map(|chunk| PageRequest::ObjectIds {
+            first: chunk[0],
+            last: chunk[chunk.len() - 1],
+            count: chunk.len(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_pages() {
+        assert_eq!(plan_offset_pages(5, 2), vec![
+            PageRequest::Offset { offset: 0, count: 2 },
+            PageRequest::Offset { offset: 2, count: 2 },
+            PageRequest::Offset { offset: 4, count: 1 },
+        ]);
+        assert!(plan_offset_pages(0, 2).is_empty());
+        assert_eq!(plan_object_id_pages(vec![30, 10, 20, 40, 20], 3), vec![
+            PageRequest::ObjectIds { first: 10, last: 30, count: 3 },
+            PageRequest::ObjectIds { first: 40, last: 40, count: 1 },
+        ]);
+    }
+
+    #[test]
+    fn test_page_url() {
+        let url = "https://a.b/MapServer/17/query?where=1%3D1&f=json";
+        assert_eq!(
+            PageRequest::Offset { offset: 4, count: 2 }.url(url, None).unwrap(),
+            "https://a.b/MapServer/17/query?where=1%3D1&f=json&resultOffset=4&resultRecordCount=2"
+        );
+        let page = PageRequest::ObjectIds { first: 10, last: 30, count: 3 };
+        assert_eq!(
+            page.url(url, Some("OBJECTID")).unwrap(),
+            "https://a.b/MapServer/17/query?where=%281%3D1%29+AND+%28OBJECTID+%3E%3D+10+AND+OBJECTID+%3C%3D+30%29&f=json"
+        );
+        assert!(page.url(url, None).is_err());
+    }
+}
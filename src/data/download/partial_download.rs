@@ -0,0 +1,106 @@
+use std::{
+    fs::{create_dir_all, read_to_string, remove_dir_all, rename, write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{super::esri_json::EsriFeatureSet, PageRequest};
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// Describes the download the saved pages belong to. Pages are only reused
+/// if the next attempt would request exactly the same pages.
+pub struct Manifest {
+    pub query_url: String,
+    pub object_id_field: Option<String>,
+    pub pages: Vec<PageRequest>,
+}
+
+/// The folder used to keep downloaded pages for the cache file at
+/// `cache_file`, eg `./data/data.json.lz4.partial`
+pub fn partial_directory_for(cache_file: &Path) -> Option<PathBuf> {
+    let file_name = cache_file.file_name()?;
+    Some(cache_file.with_file_name(format!("{}.partial", file_name.to_string_lossy())))
+}
+
+/// Pages saved to disk as they are downloaded, so that a failed download can
+/// resume from where it stopped instead of starting again
+pub struct PartialDownload {
+    directory: PathBuf,
+}
+
+impl PartialDownload {
+    /// Open `directory`, keeping the pages saved by an earlier attempt if its
+    /// manifest matches. Returns `None` if the directory cannot be used, in
+    /// which case pages are not saved.
+    pub fn open(directory: &Path, manifest: &Manifest) -> Option<Self> {
+        let partial_download = Self { directory: directory.to_owned() };
+        let manifest_path = directory.join(MANIFEST_FILE_NAME);
+        let earlier_manifest = read_to_string(&manifest_path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Manifest>(&text).ok());
+        if earlier_manifest.as_ref() == Some(manifest) {
+            return Some(partial_download);
+        }
+        if directory.exists() {
+            if let Err(error_message) = remove_dir_all(directory) {
+                println!("WARNING: Could not clear '{}' because '{}'. Pages will not be saved", directory.display(), error_message);
+                return None;
+            }
+        }
+        let result = create_dir_all(directory)
+            .map_err(|error| error.to_string())
+            .and_then(|_| serde_json::to_vec(manifest).map_err(|error| error.to_string()))
+            .and_then(|bytes| write(&manifest_path, bytes).map_err(|error| error.to_string()));
+        match result {
+            Ok(_) => Some(partial_download),
+            Err(error_message) => {
+                println!("WARNING: Could not create '{}' because '{}'. Pages will not be saved", directory.display(), error_message);
+                None
+            }
+        }
+    }
+
+    fn page_path(&self, index: usize) -> PathBuf {
+        self.directory.join(format!("page_{:06}.json", index))
+    }
+
+    pub fn has_page(&self, index: usize) -> bool {
+        self.page_path(index).is_file()
+    }
+
+    /// Number of pages saved by an earlier attempt
+    pub fn saved_page_count(&self, page_count: usize) -> usize {
+        (0..page_count).filter(|index| self.has_page(*index)).count()
+    }
+
+    /// A page is saved as a JSON array of the responses it took to fetch it.
+    /// Returns `None` if the page was not saved or can no longer be read.
+    pub fn load_page(&self, index: usize) -> Option<Vec<EsriFeatureSet>> {
+        let text = read_to_string(self.page_path(index)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    /// Failing to save a page is not an error; it will be downloaded again if
+    /// the download has to resume
+    pub fn save_page(&self, index: usize, responses: &[String]) {
+        let path = self.page_path(index);
+        // Written under another name first so that a page interrupted
+        // part way through writing is never mistaken for a complete one
+        let temporary_path = path.with_extension("tmp");
+        let result = write(&temporary_path, format!("[{}]", responses.join(",")))
+            .and_then(|_| rename(&temporary_path, &path));
+        if let Err(error_message) = result {
+            println!("WARNING: Could not save '{}' because '{}'", path.display(), error_message);
+        }
+    }
+
+    /// Called once the download has completed
+    pub fn remove(self) {
+        if let Err(error_message) = remove_dir_all(&self.directory) {
+            println!("WARNING: Could not remove '{}' because '{}'", self.directory.display(), error_message);
+        }
+    }
+}
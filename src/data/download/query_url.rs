@@ -0,0 +1,87 @@
+use crate::helpers::ErrorWithStaticMessage;
+
+/// Convert a query url like `.../MapServer/17/query?where=...` into the url of
+/// the layer description `.../MapServer/17?f=json`
+pub fn layer_info_url(url: &str) -> Option<String> {
+    let path = url.split('?').next()?;
+    let layer_path = path.trim_end_matches('/').strip_suffix("/query")?;
+    Some(format!("{}?f=json", layer_path))
+}
+
+fn parse(url: &str) -> Result<reqwest::Url, ErrorWithStaticMessage> {
+    reqwest::Url::parse(url)
+        .map_err(|_| ErrorWithStaticMessage::new("Could not parse the data source url"))
+}
+
+/// True if the query string of `url` contains `key`
+pub fn has_parameter(url: &str, key: &str) -> bool {
+    matches!(parse(url), Ok(parsed) if parsed.query_pairs().any(|(existing, _)| existing == key))
+}
+
+/// Set query string parameters, replacing any existing values of the same
+/// keys
+pub fn with_parameters(url: &str, parameters: &[(&str, &str)]) -> Result<String, ErrorWithStaticMessage> {
+    let mut parsed = parse(url)?;
+    let mut pairs: Vec<(String, String)> = parsed
+        .query_pairs()
+        .into_owned()
+        .filter(|(key, _)| !parameters.iter().any(|(replaced, _)| key == replaced))
+        .collect();
+    pairs.extend(parameters.iter().map(|(key, value)| (key.to_string(), value.to_string())));
+    parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    Ok(parsed.to_string())
+}
+
+/// Combine `condition` with the `where=` parameter using AND
+pub fn and_where(url: &str, condition: &str) -> Result<String, ErrorWithStaticMessage> {
+    let mut parsed = parse(url)?;
+    let mut pairs: Vec<(String, String)> = parsed.query_pairs().into_owned().collect();
+    match pairs.iter_mut().find(|(key, _)| key == "where") {
+        Some((_, existing)) => *existing = format!("({}) AND ({})", existing, condition),
+        None => pairs.push(("where".to_owned(), condition.to_owned())),
+    }
+    parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    Ok(parsed.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_info_url() {
+        assert_eq!(
+            layer_info_url("https://a.b/arcgis/rest/services/X/MapServer/17/query?where=1%3D1&f=json"),
+            Some("https://a.b/arcgis/rest/services/X/MapServer/17?f=json".to_string())
+        );
+        assert_eq!(
+            layer_info_url("https://a.b/arcgis/rest/services/X/MapServer/17/query/?f=json"),
+            Some("https://a.b/arcgis/rest/services/X/MapServer/17?f=json".to_string())
+        );
+        assert_eq!(layer_info_url("https://a.b/data.json"), None);
+    }
+
+    #[test]
+    fn test_with_parameters() {
+        let url = "https://a.b/MapServer/17/query?where=1%3D1&resultOffset=5&f=json";
+        assert_eq!(
+            with_parameters(url, &[("resultOffset", "10"), ("resultRecordCount", "2")]).unwrap(),
+            "https://a.b/MapServer/17/query?where=1%3D1&f=json&resultOffset=10&resultRecordCount=2"
+        );
+        assert!(has_parameter(url, "f"));
+        assert!(!has_parameter(url, "orderByFields"));
+        assert!(with_parameters("not a url", &[]).is_err());
+    }
+
+    #[test]
+    fn test_and_where() {
+        assert_eq!(
+            and_where("https://a.b/MapServer/17/query?where=1%3D1&f=json", "OBJECTID >= 1").unwrap(),
+            "https://a.b/MapServer/17/query?where=%281%3D1%29+AND+%28OBJECTID+%3E%3D+1%29&f=json"
+        );
+        assert_eq!(
+            and_where("https://a.b/MapServer/17/query?f=json", "OBJECTID >= 1").unwrap(),
+            "https://a.b/MapServer/17/query?f=json&where=OBJECTID+%3E%3D+1"
+        );
+    }
+}
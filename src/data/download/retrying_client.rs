@@ -0,0 +1,207 @@
+use std::{error::Error, fs::read, time::Duration};
+
+use serde::de::DeserializeOwned;
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::{
+    super::esri_json::EsriErrorResponse,
+    arcgis_token::TokenSource,
+    DownloadSettings
+};
+
+/// The longest wait between attempts, no matter how many have failed
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Why a single attempt failed, and whether it is worth trying again
+struct Failure {
+    message: String,
+    retry: bool,
+}
+
+impl Failure {
+    fn retryable(message: String) -> Self {
+        Self { message, retry: true }
+    }
+}
+
+/// Split a PEM file into one string per certificate
+fn pem_certificates(text: &str) -> Vec<String> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    text.split(BEGIN)
+        .skip(1)
+        .filter_map(|rest| rest.find(END).map(|end| format!("{}{}{}", BEGIN, &rest[..end], END)))
+        .collect()
+}
+
+/// Read a PEM file, which may hold several certificates, or a DER file
+fn read_certificates(path: &str) -> Result<Vec<reqwest::Certificate>, Box<dyn Error>> {
+    let bytes = read(path)?;
+    let pem_blocks = pem_certificates(&String::from_utf8_lossy(&bytes));
+    if pem_blocks.is_empty() {
+        return Ok(vec![reqwest::Certificate::from_der(&bytes)?]);
+    }
+    let mut certificates = Vec::with_capacity(pem_blocks.len());
+    for block in pem_blocks {
+        certificates.push(reqwest::Certificate::from_pem(block.as_bytes())?);
+    }
+    Ok(certificates)
+}
+
+fn build_http_client(settings: &DownloadSettings) -> Result<reqwest::Client, Box<dyn Error>> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy) = &settings.NLR_PROXY {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    for path in &settings.NLR_CA_CERT {
+        let certificates = read_certificates(path).map_err(|error_message| {
+            println!("WARNING: Could not read the certificates in '{}' because '{}'", path, error_message);
+            ErrorWithStaticMessage::new("Could not read a file given by --ca-cert")
+        })?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+#[derive(Clone)]
+/// Makes GET requests to the rest service, retrying failed requests with
+/// exponential backoff. Adds the ArcGIS token to each request in the
+/// `X-Esri-Authorization` header and uses the proxy and extra root
+/// certificates from the settings. Cheap to clone; clones share a connection
+/// pool and token.
+pub struct RetryingClient {
+    client: reqwest::Client,
+    token_source: TokenSource,
+    retries: u32,
+    first_delay: Duration,
+}
+
+impl RetryingClient {
+    /// `data_source_url` is used to find where to generate tokens
+    pub fn new(settings: &DownloadSettings, data_source_url: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            client: build_http_client(settings)?,
+            token_source: TokenSource::new(settings, data_source_url)?,
+            retries: settings.NLR_DOWNLOAD_RETRIES,
+            first_delay: Duration::from_millis(settings.NLR_DOWNLOAD_RETRY_DELAY),
+        })
+    }
+
+    /// How long to wait after the failed attempt numbered `attempt`,
+    /// counting from zero
+    fn delay(&self, attempt: u32) -> Duration {
+        2u32
+            .checked_pow(attempt)
+            .and_then(|factor| self.first_delay.checked_mul(factor))
+            .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+    }
+
+    async fn get_once<T: DeserializeOwned>(&self, url: &str) -> Result<(String, T), Failure> {
+        let token = self.token_source
+            .token(&self.client)
+            .await
+            .map_err(|message| Failure::retryable(format!("a token could not be generated because {}", message)))?;
+        // The token is sent in a header rather than as `token=` so that it
+        // never appears in a url, which is logged when a request fails
+        let mut request = self.client.get(url);
+        if let Some(token) = token {
+            request = request.header("X-Esri-Authorization", format!("Bearer {}", token));
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|error| Failure::retryable(error.without_url().to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            // Other client errors will fail the same way next time
+            let retry = status.is_server_error()
+                || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                || status == reqwest::StatusCode::REQUEST_TIMEOUT;
+            return Err(Failure { message: format!("HTTP status {}", status), retry });
+        }
+        let text = response
+            .text()
+            .await
+            .map_err(|error| Failure::retryable(error.without_url().to_string()))?;
+        match serde_json::from_str(&text) {
+            Ok(parsed) => Ok((text, parsed)),
+            // The rest service reports most failures, including timeouts
+            // on its side, as an error object with a 200 status
+            Err(error) => Err(match serde_json::from_str::<EsriErrorResponse>(&text) {
+                Ok(response) => {
+                    let code = response.error.code.unwrap_or_default();
+                    let message = format!(
+                        "the rest service responded with error {} {}",
+                        code,
+                        response.error.message.unwrap_or_default()
+                    );
+                    match code {
+                        // 498 is an invalid or expired token and 499 is a
+                        // missing token. Only worth retrying with a new token
+                        498 | 499 => Failure { message, retry: self.token_source.invalidate() },
+                        _ => Failure::retryable(message),
+                    }
+                },
+                Err(_) => Failure::retryable(format!("the response could not be parsed: {}", error)),
+            }),
+        }
+    }
+
+    /// Fetch and parse `url`, also returning the text of the response
+    pub async fn get_json_and_text<T: DeserializeOwned>(&self, url: &str) -> Result<(String, T), ErrorWithStaticMessage> {
+        let mut attempt = 0;
+        loop {
+            match self.get_once(url).await {
+                Ok(result) => return Ok(result),
+                Err(failure) if failure.retry && attempt < self.retries => {
+                    let delay = self.delay(attempt);
+                    attempt += 1;
+                    println!(
+                        "WARNING: Request to '{}' failed because '{}'. Retry {} of {} in {} ms",
+                        url, failure.message, attempt, self.retries, delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                },
+                Err(failure) => {
+                    println!("WARNING: Request to '{}' failed because '{}'. Giving up", url, failure.message);
+                    return Err(ErrorWithStaticMessage::new("Download failed. A request to the rest service failed after retrying."));
+                }
+            }
+        }
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, ErrorWithStaticMessage> {
+        Ok(self.get_json_and_text(url).await?.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let client = RetryingClient {
+            client: reqwest::Client::new(),
+            token_source: TokenSource::None,
+            retries: 5,
+            first_delay: Duration::from_millis(500),
+        };
+        let delays: Vec<u128> = (0..4).map(|attempt| client.delay(attempt).as_millis()).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 4000]);
+        assert_eq!(client.delay(10), MAX_RETRY_DELAY);
+        assert_eq!(client.delay(40), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_pem_certificates() {
+        let text = "junk\n-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n";
+        assert_eq!(pem_certificates(text), vec![
+            "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----",
+            "-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----",
+        ]);
+        assert!(pem_certificates("not a pem file").is_empty());
+    }
+}
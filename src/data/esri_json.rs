@@ -1,37 +1,71 @@
 use nickslinetoolsrust::vector2::Vector2;
 use serde::{Deserialize};
 
-#[derive(Deserialize, Debug, Clone, Copy)]
-pub enum EsriCwy {
-    Left,
-    Right,
-    Single,
-}
+use super::cached::SpatialReference;
 
-#[derive(Deserialize, Debug, Clone)]
-#[allow(non_snake_case)]
-pub struct EsriAttributes {
-    pub ROAD: String,
-    pub CWY: EsriCwy,
-    pub START_SLK: f32,
-    pub END_SLK: f32,
-}
+/// One vertex; `[x, y]`, `[x, y, z]`, `[x, y, m]` or `[x, y, z, m]` depending
+/// on `hasZ` and `hasM`. Missing `z` and `m` values are `null`.
+pub type EsriCoordinate = Vec<Option<f64>>;
 
+#[allow(non_snake_case)]
 #[derive(Deserialize, Debug)]
 pub struct EsriPolylineGeometry {
-    pub paths: [Vec<Vector2>; 1],
-    // #[serde(default)]
-    // pub hasZ:bool;
-    // #[serde(default)]
-    // pub hasZ:bool;
-    // /// https://developers.arcgis.com/documentation/common-data-types/geometry-objects.htm#GUID-DFF0E738-5A42-40BC-A811-ACCB5814BABC
-    // pub spatialReference: ??? 
+    /// Usually one path, but multipart polylines have several
+    pub paths: Vec<Vec<EsriCoordinate>>,
+    /// Overrides `hasZ` of the [EsriFeatureSet] if present
+    #[serde(default)]
+    pub hasZ: Option<bool>,
+    /// Overrides `hasM` of the [EsriFeatureSet] if present
+    #[serde(default)]
+    pub hasM: Option<bool>,
+    #[serde(default)]
+    pub spatialReference: Option<SpatialReference>,
+}
+
+/// A path converted to plain vertices. `measures` is `None` unless every
+/// vertex has an `m` value.
+#[derive(Debug)]
+pub struct EsriPath {
+    pub vertices: Vec<Vector2>,
+    pub measures: Option<Vec<f64>>,
+}
+
+impl EsriPolylineGeometry {
+    /// Read the `x`, `y` and (if `has_m`) `m` values of each path. The `z`
+    /// values are ignored. `has_z` and `has_m` are the defaults from the
+    /// [EsriFeatureSet].
+    pub fn into_paths(self, has_z: bool, has_m: bool) -> Option<Vec<EsriPath>> {
+        let has_z = self.hasZ.unwrap_or(has_z);
+        let has_m = self.hasM.unwrap_or(has_m);
+        let m_index = if has_z { 3 } else { 2 };
+        self.paths
+            .into_iter()
+            .map(|path| {
+                let vertices = path
+                    .iter()
+                    .map(|coordinate| match coordinate[..] {
+                        [Some(x), Some(y), ..] => Some(Vector2::new(x, y)),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<Vector2>>>()?;
+                let measures = if has_m {
+                    path.iter()
+                        .map(|coordinate| coordinate.get(m_index).copied().flatten().filter(|m| m.is_finite()))
+                        .collect::<Option<Vec<f64>>>()
+                } else {
+                    None
+                };
+                Some(EsriPath { vertices, measures })
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct EsriFeature {
     pub geometry: EsriPolylineGeometry,
-    pub attributes: EsriAttributes,
+    /// The field names depend on the layer, see [FieldMapping](super::FieldMapping)
+    pub attributes: serde_json::Map<String, serde_json::Value>,
 }
 
 #[allow(non_camel_case_types)]
@@ -58,16 +92,94 @@ pub struct EsriFeatureSet {
     pub features: Vec<EsriFeature>,
     pub exceededTransferLimit: Option<bool>,
 
-    // #[serde(default)]
-    // pub hasZ:bool;
+    #[serde(default)]
+    pub hasZ: bool,
 
-    // #[serde(default)]
-    // pub hasZ:bool;
+    #[serde(default)]
+    pub hasM: bool,
 
     // pub fields: ???
 
-    // /// if not present, must assume the spatialReference of first feature.
-    // /// If not set on first feature then it is UnknownCoordinateSystem.
-    // /// https://developers.arcgis.com/documentation/common-data-types/geometry-objects.htm#GUID-DFF0E738-5A42-40BC-A811-ACCB5814BABC
-    // pub spatialReference: ??? 
+    /// if not present, must assume the spatialReference of first feature.
+    /// If not set on first feature then it is UnknownCoordinateSystem.
+    #[serde(default)]
+    pub spatialReference: Option<SpatialReference>,
+}
+
+impl EsriFeatureSet {
+    /// The spatial reference of the feature set, or else of its first feature
+    pub fn spatial_reference(&self) -> Option<&SpatialReference> {
+        self.spatialReference.as_ref().or_else(|| {
+            self.features.first().and_then(|feature| feature.geometry.spatialReference.as_ref())
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+/// The response to a query with `&returnCountOnly=true`
+pub struct EsriCountResponse {
+    pub count: usize,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+/// The response to a query with `&returnIdsOnly=true`
+pub struct EsriObjectIdsResponse {
+    pub objectIdFieldName: String,
+    /// `null` when no features match
+    pub objectIds: Option<Vec<i64>>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+/// The body returned with a `200 OK` status when a query fails
+pub struct EsriErrorResponse {
+    pub error: EsriError,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EsriError {
+    pub code: Option<i64>,
+    pub message: Option<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+pub struct EsriEditingInfo {
+    /// Milliseconds since the unix epoch
+    pub lastEditDate: Option<i64>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+pub struct EsriAdvancedQueryCapabilities {
+    /// Whether `resultOffset` and `resultRecordCount` are supported
+    pub supportsPagination: Option<bool>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug, Default)]
+/// A small part of the response from a MapServer or FeatureServer layer
+/// endpoint such as `.../MapServer/17?f=json`
+///
+/// See reference
+/// <https://developers.arcgis.com/rest/services-reference/enterprise/layer-feature-service/>
+pub struct EsriLayerInfo {
+    pub editingInfo: Option<EsriEditingInfo>,
+    /// The most features the service returns for one query
+    #[serde(default)]
+    pub maxRecordCount: Option<usize>,
+    #[serde(default)]
+    pub objectIdField: Option<String>,
+    #[serde(default)]
+    pub advancedQueryCapabilities: Option<EsriAdvancedQueryCapabilities>,
+}
+
+impl EsriLayerInfo {
+    /// `None` if the service does not say
+    pub fn supports_pagination(&self) -> Option<bool> {
+        self.advancedQueryCapabilities
+            .as_ref()
+            .and_then(|capabilities| capabilities.supportsPagination)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,273 @@
+use clap::Args;
+use serde::Deserialize;
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::{cached::Cwy, download::{and_where, with_parameters}};
+
+// Describes how the fields of the data source map onto `Attributes`. The
+// defaults match the Main Roads Western Australia road network.
+// This is a plain comment rather than a doc comment because clap would use a
+// doc comment as the `about` text of the whole program.
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize, Clone, Args, PartialEq)]
+#[command(next_help_heading="Field Mapping")]
+pub struct FieldMapping {
+
+    #[clap(
+        long="field-road",
+        env="NLR_FIELD_ROAD",
+        default_value = "ROAD",
+    )]
+    /// Name of the field containing the road number
+    pub NLR_FIELD_ROAD: String,
+
+    #[clap(
+        long="field-cwy",
+        env="NLR_FIELD_CWY",
+        default_value = "CWY",
+    )]
+    /// Name of the field containing the carriageway
+    pub NLR_FIELD_CWY: String,
+
+    #[clap(
+        long="field-start-slk",
+        env="NLR_FIELD_START_SLK",
+        default_value = "START_SLK",
+    )]
+    /// Name of the field containing the SLK at the start of each feature
+    pub NLR_FIELD_START_SLK: String,
+
+    #[clap(
+        long="field-end-slk",
+        env="NLR_FIELD_END_SLK",
+        default_value = "END_SLK",
+    )]
+    /// Name of the field containing the SLK at the end of each feature
+    pub NLR_FIELD_END_SLK: String,
+
+    #[clap(
+        long="field-network-type",
+        env="NLR_FIELD_NETWORK_TYPE",
+        default_value = "NETWORK_TYPE",
+    )]
+    /// Name of the optional field containing the network type (eg State Road).
+    /// Set to an empty string to ignore the field
+    pub NLR_FIELD_NETWORK_TYPE: String,
+
+    #[clap(
+        long="field-road-name",
+        env="NLR_FIELD_ROAD_NAME",
+        default_value = "ROAD_NAME",
+    )]
+    /// Name of the optional field containing the road name
+    pub NLR_FIELD_ROAD_NAME: String,
+
+    #[clap(
+        long="field-lg-name",
+        env="NLR_FIELD_LG_NAME",
+        default_value = "LG_NAME",
+    )]
+    /// Name of the optional field containing the local government name
+    pub NLR_FIELD_LG_NAME: String,
+
+    #[clap(
+        long="field-ra-no",
+        env="NLR_FIELD_RA_NO",
+        default_value = "RA_NO",
+    )]
+    /// Name of the optional field containing the region number
+    pub NLR_FIELD_RA_NO: String,
+
+    #[clap(
+        long="field-start-true-dist",
+        env="NLR_FIELD_START_TRUE_DIST",
+        default_value = "START_TRUE_DIST",
+    )]
+    /// Name of the optional field containing the true distance at the start of
+    /// each feature
+    pub NLR_FIELD_START_TRUE_DIST: String,
+
+    #[clap(
+        long="field-end-true-dist",
+        env="NLR_FIELD_END_TRUE_DIST",
+        default_value = "END_TRUE_DIST",
+    )]
+    /// Name of the optional field containing the true distance at the end of
+    /// each feature
+    pub NLR_FIELD_END_TRUE_DIST: String,
+
+    #[clap(
+        long="cwy-left",
+        env="NLR_CWY_LEFT",
+        value_delimiter=',',
+        default_value = "Left,L",
+    )]
+    /// Comma separated values of the carriageway field meaning Left
+    pub NLR_CWY_LEFT: Vec<String>,
+
+    #[clap(
+        long="cwy-right",
+        env="NLR_CWY_RIGHT",
+        value_delimiter=',',
+        default_value = "Right,R",
+    )]
+    /// Comma separated values of the carriageway field meaning Right
+    pub NLR_CWY_RIGHT: Vec<String>,
+
+    #[clap(
+        long="cwy-single",
+        env="NLR_CWY_SINGLE",
+        value_delimiter=',',
+        default_value = "Single,S",
+    )]
+    /// Comma separated values of the carriageway field meaning Single
+    pub NLR_CWY_SINGLE: Vec<String>,
+
+    #[clap(
+        long="source-where",
+        env="NLR_SOURCE_WHERE",
+    )]
+    /// Extra condition combined with the `where=` parameter of the data source
+    /// url using AND. For GeoPackage files it is used as an SQL WHERE clause
+    pub NLR_SOURCE_WHERE: Option<String>,
+}
+
+impl FieldMapping {
+    /// Values are compared without regard to case or surrounding whitespace
+    pub fn cwy_from_value(&self, value: &str) -> Option<Cwy> {
+        let value = value.trim();
+        let matches = |values: &Vec<String>| values
+            .iter()
+            .any(|item| item.trim().eq_ignore_ascii_case(value));
+        if matches(&self.NLR_CWY_LEFT) {
+            Some(Cwy::Left)
+        } else if matches(&self.NLR_CWY_RIGHT) {
+            Some(Cwy::Right)
+        } else if matches(&self.NLR_CWY_SINGLE) {
+            Some(Cwy::Single)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the `NLR_SOURCE_WHERE` condition, if one is set
+    pub fn source_where(&self) -> Option<&str> {
+        self.NLR_SOURCE_WHERE
+            .as_deref()
+            .map(str::trim)
+            .filter(|condition| !condition.is_empty())
+    }
+
+    /// The mapped field names joined by commas for the `outFields=` parameter.
+    /// Optional fields set to an empty string are left out.
+    pub fn out_fields(&self) -> String {
+        [
+            &self.NLR_FIELD_ROAD,
+            &self.NLR_FIELD_CWY,
+            &self.NLR_FIELD_START_SLK,
+            &self.NLR_FIELD_END_SLK,
+            &self.NLR_FIELD_NETWORK_TYPE,
+            &self.NLR_FIELD_ROAD_NAME,
+            &self.NLR_FIELD_LG_NAME,
+            &self.NLR_FIELD_RA_NO,
+            &self.NLR_FIELD_START_TRUE_DIST,
+            &self.NLR_FIELD_END_TRUE_DIST,
+        ]
+            .iter()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Set the `outFields=` parameter of a rest service query url to the
+    /// mapped fields, and add the `NLR_SOURCE_WHERE` condition to its
+    /// `where=` parameter if one is set.
+    pub fn query_url(&self, url: &str) -> Result<String, ErrorWithStaticMessage> {
+        let url = with_parameters(url, &[("outFields", &self.out_fields())])?;
+        match self.source_where() {
+            Some(condition) => and_where(&url, condition),
+            None => Ok(url),
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(clap::Parser)]
+struct TestCli {
+    #[clap(flatten)]
+    field_mapping: FieldMapping,
+}
+
+#[cfg(test)]
+impl FieldMapping {
+    /// Parse the mapping from command line arguments, as the server would
+    pub fn from_args(args: &[&str]) -> Self {
+        use clap::Parser;
+        TestCli::parse_from([&["none.exe"], args].concat()).field_mapping
+    }
+}
+
+/// The mapping for the Main Roads Western Australia road network, which is
+/// used when no options are given
+#[cfg(test)]
+impl Default for FieldMapping {
+    fn default() -> Self {
+        Self::from_args(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_mapping_cwy_from_value() {
+        let defaults = FieldMapping::default();
+        assert_eq!(defaults.cwy_from_value("Left"), Some(Cwy::Left));
+        assert_eq!(defaults.cwy_from_value(" r "), Some(Cwy::Right));
+        assert_eq!(defaults.cwy_from_value("Single"), Some(Cwy::Single));
+        assert_eq!(defaults.cwy_from_value("1"), None);
+
+        let custom = FieldMapping::from_args(&["--cwy-left", "1,LHS", "--cwy-right", "2", "--cwy-single", "0"]);
+        assert_eq!(custom.cwy_from_value("lhs"), Some(Cwy::Left));
+        assert_eq!(custom.cwy_from_value("2"), Some(Cwy::Right));
+        assert_eq!(custom.cwy_from_value("0"), Some(Cwy::Single));
+        assert_eq!(custom.cwy_from_value("Left"), None);
+    }
+
+    #[test]
+    fn test_field_mapping_out_fields() {
+        assert_eq!(
+            FieldMapping::default().out_fields(),
+            "ROAD,CWY,START_SLK,END_SLK,NETWORK_TYPE,ROAD_NAME,LG_NAME,RA_NO,START_TRUE_DIST,END_TRUE_DIST"
+        );
+        let custom = FieldMapping::from_args(&[
+            "--field-road", "ROAD_ID", "--field-cwy", "CWAY", "--field-start-slk", "FROM_KM", "--field-end-slk", "TO_KM",
+            "--field-network-type", "", "--field-road-name", "", "--field-lg-name", "", "--field-ra-no", "",
+            "--field-start-true-dist", "", "--field-end-true-dist", "",
+        ]);
+        assert_eq!(custom.out_fields(), "ROAD_ID,CWAY,FROM_KM,TO_KM");
+    }
+
+    #[test]
+    fn test_field_mapping_query_url() {
+        let url = "https://a.b/MapServer/17/query?where=1%3D1&outFields=*&f=json";
+        let out_fields = "outFields=ROAD%2CCWY%2CSTART_SLK%2CEND_SLK%2CNETWORK_TYPE%2CROAD_NAME%2CLG_NAME%2CRA_NO%2CSTART_TRUE_DIST%2CEND_TRUE_DIST";
+        assert_eq!(
+            FieldMapping::default().query_url(url).unwrap(),
+            format!("https://a.b/MapServer/17/query?where=1%3D1&f=json&{}", out_fields)
+        );
+
+        let mapping = FieldMapping::from_args(&["--source-where", "NETWORK_TYPE='State Road'"]);
+        assert_eq!(
+            mapping.query_url(url).unwrap(),
+            format!("https://a.b/MapServer/17/query?where=%281%3D1%29+AND+%28NETWORK_TYPE%3D%27State+Road%27%29&f=json&{}", out_fields)
+        );
+        assert_eq!(
+            mapping.query_url("https://a.b/MapServer/17/query?f=json").unwrap(),
+            format!("https://a.b/MapServer/17/query?f=json&{}&where=NETWORK_TYPE%3D%27State+Road%27", out_fields)
+        );
+        assert!(mapping.query_url("not a url").is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use serde::Serialize;
+
+use crate::helpers::CalendarDate;
+
+use super::IndexedData;
+
+#[derive(Serialize, Debug)]
+/// Which version of the network is being served, returned by the `/status`
+/// route
+pub struct DataStatus<'a> {
+    /// See [IndexedData::data_version]
+    pub data_version: &'a str,
+    /// The query url or local file the data came from. `None` for cache
+    /// files created by older versions
+    pub source_url: Option<&'a str>,
+    /// The day (UTC) the data was downloaded
+    pub downloaded: Option<&'a CalendarDate>,
+    /// Number of features reported by the rest service when the data was
+    /// downloaded. Multipart features are split, so this may be less than
+    /// `feature_count`
+    pub source_feature_count: Option<usize>,
+    /// Milliseconds since the unix epoch, if the rest service reports it
+    pub last_edit_date: Option<i64>,
+    pub feature_count: usize,
+    pub road_count: usize,
+    /// See the `/diagnostics` route
+    pub error_count: usize,
+    pub warning_count: usize,
+}
+
+impl<'a> DataStatus<'a> {
+    pub fn new(indexed_data: &'a IndexedData) -> Self {
+        let cache_metadata = indexed_data.cache_metadata();
+        let source_metadata = indexed_data.source_metadata();
+        Self {
+            data_version: indexed_data.data_version(),
+            source_url: cache_metadata.map(|cache_metadata| cache_metadata.source_url.as_str()),
+            downloaded: indexed_data.downloaded(),
+            source_feature_count: source_metadata.map(|source_metadata| source_metadata.count),
+            last_edit_date: source_metadata.and_then(|source_metadata| source_metadata.last_edit_date),
+            feature_count: indexed_data.feature_count(),
+            road_count: indexed_data.road_count(),
+            error_count: indexed_data.validation().error_count,
+            warning_count: indexed_data.validation().warning_count,
+        }
+    }
+}
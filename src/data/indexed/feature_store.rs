@@ -0,0 +1,95 @@
+use std::{error::Error, sync::OnceLock};
+
+use crate::helpers::ErrorWithStaticMessage;
+
+use super::{
+    measured_line::MeasuredLine,
+    super::cached::{content_hash, CachedLayer, Feature, FeatureSummary, LayerMetadata, MappedLayer},
+};
+
+/// The features being served, each with its [MeasuredLine]
+pub enum FeatureStore {
+    /// Downloaded, or read from a JSON cache file
+    Decoded {
+        features: Vec<Feature>,
+        /// One for each of `features`, in the same order
+        measured_lines: Vec<MeasuredLine>,
+    },
+    /// A memory mapped binary cache file. Each feature is decoded through
+    /// the offsets table the first time it is used, then kept.
+    Mapped {
+        mapped_layer: MappedLayer,
+        decoded: Vec<OnceLock<Box<(Feature, MeasuredLine)>>>,
+    },
+}
+
+impl FeatureStore {
+    /// Split a [CachedLayer] into its features and everything else
+    pub fn new(cached_layer: CachedLayer) -> Result<(Self, LayerMetadata), Box<dyn Error>> {
+        match cached_layer {
+            CachedLayer::Decoded(layer) => {
+                let measured_lines = layer.features.iter().map(MeasuredLine::new).collect();
+                let metadata = LayerMetadata {
+                    source_metadata: layer.source_metadata,
+                    downloaded: layer.downloaded,
+                    spatial_reference: layer.spatial_reference,
+                    cache_metadata: layer.cache_metadata,
+                };
+                Ok((FeatureStore::Decoded { features: layer.features, measured_lines }, metadata))
+            },
+            CachedLayer::Mapped(mapped_layer) => {
+                let metadata = mapped_layer.metadata()?;
+                let decoded = (0..mapped_layer.len()).map(|_| OnceLock::new()).collect();
+                Ok((FeatureStore::Mapped { mapped_layer, decoded }, metadata))
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            FeatureStore::Decoded { features, .. } => features.len(),
+            FeatureStore::Mapped { mapped_layer, .. } => mapped_layer.len(),
+        }
+    }
+
+    /// The feature at `index` and its [MeasuredLine]. Panics if `index` is
+    /// out of range.
+    pub fn get(&self, index: usize) -> (&Feature, &MeasuredLine) {
+        match self {
+            FeatureStore::Decoded { features, measured_lines } => (&features[index], &measured_lines[index]),
+            FeatureStore::Mapped { mapped_layer, decoded } => {
+                let (feature, measured_line) = &**decoded[index].get_or_init(|| {
+                    let feature = mapped_layer.feature(index).expect("The data file was checked when it was opened");
+                    let measured_line = MeasuredLine::new(&feature);
+                    Box::new((feature, measured_line))
+                });
+                (feature, measured_line)
+            },
+        }
+    }
+
+    /// Every feature, in order
+    pub fn iter(&self) -> impl Iterator<Item = &Feature> {
+        (0..self.len()).map(|index| self.get(index).0)
+    }
+
+    /// See [content_hash]. Decodes every feature of a mapped file without
+    /// keeping them.
+    pub fn content_hash(&self) -> Result<String, Box<dyn Error>> {
+        match self {
+            FeatureStore::Decoded { features, .. } => Ok(content_hash(features)),
+            FeatureStore::Mapped { mapped_layer, .. } => Ok(content_hash(&mapped_layer.to_layer()?.features)),
+        }
+    }
+
+    /// What the indexes need to know about each feature, in order. Features
+    /// of a mapped file are not kept.
+    pub fn summaries(&self) -> Result<Vec<FeatureSummary>, ErrorWithStaticMessage> {
+        match self {
+            FeatureStore::Decoded { features, .. } => Ok(features.iter().map(Feature::summary).collect()),
+            FeatureStore::Mapped { mapped_layer, .. } => (0..mapped_layer.len())
+                .map(|index| mapped_layer.summary(index))
+                .collect(),
+        }
+    }
+}
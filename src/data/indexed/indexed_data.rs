@@ -1,147 +1,325 @@
 use std::error::Error;
-use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
+use nickslinetoolsrust::vector2::Vector2;
+
 use crate::{
-    helpers::ErrorWithStaticMessage,
-    filters::query_parameters::RequestedCwy
+    helpers::{CalendarDate, ErrorWithStaticMessage},
+    filters::query_parameters::{Measure, RequestedCwy}
 };
 
 use super::{
-    RoadDataByCwy,
-    super::cached::{
-        Layer,
-        Feature,
+    feature_store::FeatureStore,
+    road_data_by_cwy::{CwyData, RoadDataByCwy},
+    measured_line::MeasuredLine,
+    spatial_index::{BoundingBox, SpatialIndex},
+    road_search_index::{RoadSearchIndex, RoadSummary},
+    validation::ValidationReport,
+    data_status::DataStatus,
+    super::{
+        DataSourceSettings,
+        cached::{
+            CacheMetadata,
+            CachedLayer,
+            Cwy,
+            Layer,
+            LayerMetadata,
+            Feature,
+            FeatureSummary,
+            SourceMetadata,
+        }
     }
 };
 
 
-pub type LookupMap = HashMap<char, HashMap<String, RoadDataByCwy>>;
+/// The features of each road number, by carriageway
+pub type RoadIndex = HashMap<String, RoadDataByCwy>;
 
 pub struct IndexedData {
-    pub(super) data:Layer,
-    pub(super) index:LookupMap
+    pub(super) features:FeatureStore,
+    /// Everything in the [Layer] except the features
+    pub(super) metadata:LayerMetadata,
+    pub(super) index:RoadIndex,
+    pub(super) spatial_index:SpatialIndex,
+    pub(super) road_search_index:RoadSearchIndex,
+    pub(super) validation:ValidationReport,
+    pub(super) data_version:String,
 }
 
 impl IndexedData {
 
     /// Load existing data from the cache file path, or try to download data 
-    /// and save it to the cache file path
+    /// and save it to the cache file path. See
+    /// [Layer::read_or_update_cache_data] for `fallback_on_failure`.
     pub async fn load(
-        path_to_data_cache_file:&String,
-        url_to_download_new_data:&String,
+        data_source_settings:&DataSourceSettings,
         force_update:&bool,
+        fallback_on_failure:&bool,
     ) -> Result<Self, Box<dyn Error>>{
-        let data = Layer::read_or_update_cache_data(path_to_data_cache_file,url_to_download_new_data,force_update).await?;
-        let index = Self::index_data(&data)?;
+        let data = Layer::read_or_update_cache_data(data_source_settings, force_update, fallback_on_failure).await?;
+        let indexed_data = Self::from_cached_layer(data)?;
+        if let Some(max_data_errors) = data_source_settings.max_data_errors {
+            if indexed_data.validation.error_count > max_data_errors {
+                println!(
+                    "WARNING: The data has {} errors which is more than the {} allowed by --max-data-errors",
+                    indexed_data.validation.error_count,
+                    max_data_errors
+                );
+                return Err(Box::new(ErrorWithStaticMessage::new("The data has more errors than allowed by --max-data-errors")));
+            }
+        }
+        Ok(indexed_data)
+    }
+
+    /// Build the indexes for a layer which has already been decoded
+    #[cfg(test)]
+    pub fn from_layer(data:Layer) -> Result<Self, Box<dyn Error>>{
+        Self::from_cached_layer(CachedLayer::Decoded(data))
+    }
+
+    /// Build the indexes for a cache file which has been opened. The features
+    /// of a mapped file are read to build the indexes, but are only kept once
+    /// a query needs them.
+    pub fn from_cached_layer(data:CachedLayer) -> Result<Self, Box<dyn Error>>{
+        let (features, metadata) = FeatureStore::new(data)?;
+        // cache files created by older versions have no hash saved in them
+        let data_version = match &metadata.cache_metadata {
+            Some(cache_metadata) => cache_metadata.content_hash.clone(),
+            None => features.content_hash()?,
+        };
+        let summaries = features.summaries()?;
+        let index = Self::index_data(&summaries)?;
+        let spatial_index = SpatialIndex::new(&summaries);
+        let road_search_index = RoadSearchIndex::new(&summaries);
+        let validation = ValidationReport::new(&summaries);
+        validation.log_summary();
         return Ok(Self{
-            data,
-            index
+            features,
+            metadata,
+            index,
+            spatial_index,
+            road_search_index,
+            validation,
+            data_version,
         })
     }
 
 
-    pub fn query(&self, road_name:&String, cwy:&RequestedCwy) -> Result<impl Iterator<Item = &Feature>, ErrorWithStaticMessage> {
+    /// The number of features in the underlying [Layer]
+    pub fn feature_count(&self) -> usize {
+        self.features.len()
+    }
+
+    /// The number of distinct road numbers
+    pub fn road_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// A hash of the features which changes whenever the data does. Returned
+    /// in the `X-Data-Version` and `ETag` headers.
+    pub fn data_version(&self) -> &str {
+        &self.data_version
+    }
+
+    /// Where the data was downloaded from, and a hash of its content. `None`
+    /// for cache files created by older versions
+    pub fn cache_metadata(&self) -> Option<&CacheMetadata> {
+        self.metadata.cache_metadata.as_ref()
+    }
+
+    /// Summarise the data for the `/status` route
+    pub fn status(&self) -> DataStatus<'_> {
+        DataStatus::new(self)
+    }
+
+    /// Every feature of the underlying [Layer], sorted by road number
+    pub fn features(&self) -> impl Iterator<Item = &Feature> {
+        self.features.iter()
+    }
+
+    /// Problems found in the data when it was loaded
+    pub fn validation(&self) -> &ValidationReport {
+        &self.validation
+    }
+
+    /// Describes the rest service at the time the data was downloaded
+    pub fn source_metadata(&self) -> Option<&SourceMetadata> {
+        self.metadata.source_metadata.as_ref()
+    }
+
+    /// The day the underlying [Layer] was downloaded, if known
+    pub fn downloaded(&self) -> Option<&CalendarDate> {
+        self.metadata.downloaded.as_ref()
+    }
+
+    /// The features of the requested carriageways of a road which touch
+    /// `from` to `to` (inclusive) in the given [Measure]. Found by binary
+    /// search; the caller still needs to check each feature for an exact
+    /// match. Each feature comes with its [MeasuredLine]. Features come in
+    /// the order of the [RequestedCwy], then in the order of the data.
+    pub fn query_range<'a>(
+        &'a self,
+        road_name:&String,
+        cwy:&RequestedCwy,
+        measure:&'a Measure,
+        from:f32,
+        to:f32,
+    ) -> Result<impl Iterator<Item = (&'a Feature, &'a MeasuredLine)>, ErrorWithStaticMessage> {
         let road_data_by_cwy = self.get_road_by_cwy(road_name)?;
         let feature_iterator = cwy
             .into_iter()
-            .filter_map(|cwy|{
-                if let Some(indexes) = road_data_by_cwy[&cwy]{
-                    Some(&self.data.features[indexes.0..indexes.1])
-                }else{
-                    None
-                }
-            })
-            .flatten();
+            .filter_map(move |cwy| road_data_by_cwy[&cwy].as_ref())
+            .flat_map(move |cwy_data| cwy_data.interval_index(measure).overlapping(from, to))
+            .map(move |feature_index| self.features.get(feature_index));
         Ok(feature_iterator)
     }
 
+    /// The features of each carriageway recorded for a road, in the order
+    /// Left, Right, Single. Carriageways which the road does not have are
+    /// left out.
+    pub fn query_by_cwy(&self, road_name:&String) -> Result<Vec<(Cwy, Vec<&Feature>)>, ErrorWithStaticMessage> {
+        let road_data_by_cwy = self.get_road_by_cwy(road_name)?;
+        Ok([Cwy::Left, Cwy::Right, Cwy::Single]
+            .into_iter()
+            .filter_map(|cwy| road_data_by_cwy[&cwy].as_ref().map(|cwy_data| (
+                cwy,
+                (cwy_data.range.0..cwy_data.range.1).map(|feature_index| self.features.get(feature_index).0).collect()
+            )))
+            .collect())
+    }
+
+    /// Returns every feature whose bounding box intersects the box spanning
+    /// from `min` to `max` (in the same coordinates as the feature geometry)
+    pub fn query_bounding_box(&self, min:Vector2, max:Vector2) -> impl Iterator<Item = &Feature> {
+        self.spatial_index
+            .query(&BoundingBox{min, max})
+            .into_iter()
+            .map(|feature_index| self.features.get(feature_index).0)
+    }
+
+    /// Find up to `limit` roads by road number or road name. See
+    /// [RoadSearchIndex::search]
+    pub fn search_roads(&self, search:&str, limit:usize) -> Vec<&RoadSummary> {
+        self.road_search_index.search(search, limit)
+    }
+
     fn get_road_by_cwy(&self, road_name:&String) -> Result<&RoadDataByCwy, ErrorWithStaticMessage> {
-        
-        // try to get the first letter of the road name
-        let first_letter = road_name.chars().next().ok_or(
-            ErrorWithStaticMessage::new("Road Lookup Failed. First letter of 'road' did not match any in lookup table.")
-        )?;
-        
-        // lookup the list of roads that start with that letter
-        let roads_with_first_letter = self.index.get(&first_letter).ok_or(
-            ErrorWithStaticMessage::new("Road Lookup Failed. Could not get first letter of road.")
-        )?;
-
-        // find the matching road
-        roads_with_first_letter.get(road_name).ok_or(
-            ErrorWithStaticMessage::new("Road Lookup Failed. 'road' not found in second level lookup table.")
+        self.index.get(road_name).ok_or(
+            ErrorWithStaticMessage::new("Road Lookup Failed. 'road' not found in lookup table.")
         )
     }
 
-    /// TODO: this function builds a LookupMap, but it is based on some
-    ///       potentially incorrect assumptions about hash table performance.
-    ///       In practice it is working just fine, but there is probably a
-    ///       simpler way to do this. Nested Hash tables probably don't perform any 
-    ///       better than a flat one.
-    pub(super) fn index_data(layer: &Layer) -> Result<LookupMap, Box<dyn std::error::Error>> {
+    /// Find the run of features belonging to each road and carriageway, and
+    /// index each run by SLK and true distance. The features are sorted by
+    /// road and carriageway so each run is normally the only one; if the same
+    /// road and carriageway appears again later (see the `not_contiguous`
+    /// diagnostic) only the last run can be queried.
+    pub(super) fn index_data(features: &[FeatureSummary]) -> Result<RoadIndex, Box<dyn std::error::Error>> {
         println!("INFO: Indexing data");
-        let mut map_from_first_letter: LookupMap = HashMap::new(); // map_from_first_letter_to_roads
-
-        let (mut previous_road, mut previous_cwy, mut first_letter) = match layer.features.iter().next()
-        {
-            Some(first_feature) => (
-                &first_feature.attributes.ROAD,
-                &first_feature.attributes.CWY,
-                match layer.features[0].attributes.ROAD.chars().next() {
-                    Some(fl) => fl,
-                    None => ' ',
-                },
-            ),
-            None => {
-                return Err(Box::new(ErrorWithStaticMessage::new(
-                    "Zero features received by perform_analysis()",
-                )))
-            }
-        };
-
-        let mut current_slice_start = 0; // inclusive of that index
-        let mut map_from_road_number = map_from_first_letter.entry(first_letter).or_default();
-        let mut i: usize = 1;
-
-        while i < layer.features.len() {
-            let feature = &layer.features[i];
-
-            let current_feature_is_new_road = previous_road != &feature.attributes.ROAD;
-            let current_feature_is_different_cwy = previous_cwy != &feature.attributes.CWY;
-            if current_feature_is_new_road || current_feature_is_different_cwy {
-                // the into() function on the next line is doing magic that I don't quite understand. Maybe its better than my previous solution which was .clone() ?
-                match map_from_road_number.entry(previous_road.into()) {
-                    Entry::Vacant(e) => {
-                        e.insert(RoadDataByCwy::new_from_cwy(
-                            previous_cwy,
-                            (current_slice_start, i),
-                        ));
-                    }
-                    Entry::Occupied(mut e) => {
-                        e.insert(
-                            e.get().with_updated_cwy(previous_cwy, (current_slice_start, i)),
-                        );
-                    }
-                }
-                current_slice_start = i;
-            }
+        if features.is_empty() {
+            return Err(Box::new(ErrorWithStaticMessage::new(
+                "Zero features received by perform_analysis()",
+            )));
+        }
 
-            if current_feature_is_new_road {
-                let new_first_letter = match feature.attributes.ROAD.chars().next() {
-                    Some(fl) => fl,
-                    None => ' ',
-                };
-                if new_first_letter != first_letter {
-                    first_letter = new_first_letter;
-                    map_from_road_number = map_from_first_letter.entry(first_letter).or_default();
-                }
+        let mut index: RoadIndex = HashMap::new();
+        let mut run_start = 0;
+        for i in 1..=features.len() {
+            let run_start_attributes = &features[run_start].attributes;
+            let run_continues = features.get(i).is_some_and(|feature| {
+                feature.attributes.ROAD == run_start_attributes.ROAD
+                    && feature.attributes.CWY == run_start_attributes.CWY
+            });
+            if !run_continues {
+                index
+                    .entry(run_start_attributes.ROAD.clone())
+                    .or_default()
+                    .set_cwy(&run_start_attributes.CWY, CwyData::new(features, (run_start, i)));
+                run_start = i;
             }
-            previous_road = &feature.attributes.ROAD;
-            previous_cwy = &feature.attributes.CWY;
-            i += 1;
         }
 
-        Ok(map_from_first_letter)
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::cached::{Attributes, CacheFormat};
+
+    fn feature(road: &str, cwy: Cwy, start_slk: f32, end_slk: f32) -> Feature {
+        Feature::new(Attributes::new(road, cwy, start_slk, end_slk), &[(115.0 + start_slk as f64 * 0.01, -32.0), (115.0 + end_slk as f64 * 0.01, -32.0)])
+    }
+
+    fn found(indexed_data: &IndexedData, road: &str, cwy: &RequestedCwy, from: f32, to: f32) -> Vec<(Cwy, f32)> {
+        indexed_data
+            .query_range(&road.to_owned(), cwy, &Measure::slk, from, to)
+            .unwrap()
+            .map(|(feature, _)| (feature.attributes.CWY, feature.attributes.START_SLK))
+            .collect()
+    }
+
+    #[test]
+    fn test_query_range() {
+        let indexed_data = IndexedData::from_layer(Layer {
+            features: vec![
+                feature("H001", Cwy::Left, 0.0, 1.0),
+                feature("H001", Cwy::Left, 1.0, 2.0),
+                feature("H001", Cwy::Right, 1.0, 2.0),
+                feature("H001", Cwy::Right, 0.0, 1.0),
+                feature("H002", Cwy::Single, 0.0, 3.0),
+                // the last road and carriageway in the data
+                feature("H003", Cwy::Single, 0.0, 1.0),
+            ],
+            source_metadata: None,
+            downloaded: None,
+            spatial_reference: None,
+            cache_metadata: None,
+        }).unwrap();
+
+        assert_eq!(indexed_data.road_count(), 3);
+        assert_eq!(found(&indexed_data, "H001", &RequestedCwy::L, 1.5, 1.6), vec![(Cwy::Left, 1.0)]);
+        // in the order of the requested carriageways, then the data
+        assert_eq!(
+            found(&indexed_data, "H001", &RequestedCwy::LRS, f32::NEG_INFINITY, f32::INFINITY),
+            vec![(Cwy::Left, 0.0), (Cwy::Left, 1.0), (Cwy::Right, 1.0), (Cwy::Right, 0.0)]
+        );
+        assert_eq!(found(&indexed_data, "H001", &RequestedCwy::S, 0.0, 2.0), vec![]);
+        assert_eq!(found(&indexed_data, "H002", &RequestedCwy::LRS, 2.0, 2.0), vec![(Cwy::Single, 0.0)]);
+        assert_eq!(found(&indexed_data, "H003", &RequestedCwy::S, 0.5, 0.5), vec![(Cwy::Single, 0.0)]);
+        assert!(indexed_data.query_range(&"H004".to_owned(), &RequestedCwy::S, &Measure::slk, 0.0, 1.0).is_err());
+    }
+
+    #[test]
+    /// A binary cache file stays mapped, and each feature is only decoded
+    /// once a query needs it
+    fn test_mapped_features_decoded_on_demand() {
+        let layer = || Layer {
+            features: vec![
+                feature("H001", Cwy::Left, 0.0, 1.0),
+                feature("H001", Cwy::Left, 1.0, 2.0),
+                feature("H002", Cwy::Single, 0.0, 3.0),
+            ],
+            source_metadata: None,
+            downloaded: None,
+            spatial_reference: None,
+            cache_metadata: None,
+        };
+        let path = std::env::temp_dir().join(format!("nicklinref_test_mapped_{}.bin", std::process::id()));
+        Layer::save_data_to_file(&path, &layer(), &CacheFormat::binary).unwrap();
+        let indexed_data = IndexedData::from_cached_layer(Layer::open_file(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let decoded_count = || match &indexed_data.features {
+            FeatureStore::Mapped { decoded, .. } => decoded.iter().filter(|feature| feature.get().is_some()).count(),
+            FeatureStore::Decoded { .. } => panic!("A binary cache file should be mapped"),
+        };
+
+        assert_eq!(indexed_data.feature_count(), 3);
+        assert_eq!(indexed_data.road_count(), 2);
+        assert_eq!(decoded_count(), 0);
+        assert_eq!(found(&indexed_data, "H001", &RequestedCwy::L, 1.5, 1.6), vec![(Cwy::Left, 1.0)]);
+        assert_eq!(decoded_count(), 1);
+        assert_eq!(indexed_data.data_version(), IndexedData::from_layer(layer()).unwrap().data_version());
     }
 }
@@ -0,0 +1,155 @@
+use crate::filters::query_parameters::Measure;
+
+use super::super::cached::Attributes;
+
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    start: f32,
+    end: f32,
+    feature_index: usize,
+}
+
+/// The features of one road and carriageway sorted by where they start, so
+/// that the features touching a range of SLK (or true distance) are found by
+/// binary search instead of checking every feature of the road.
+///
+/// Features can overlap and are not always in order in the data, so the
+/// furthest end of all the intervals up to each position is also kept. It
+/// never decreases, which means the first interval that could reach the start
+/// of the range can be found by binary search too.
+pub struct IntervalIndex {
+    /// Sorted by `start`. Reversed features are stored with `start` and `end`
+    /// swapped. Features with a `NaN` measure are left out.
+    intervals: Vec<Interval>,
+    /// The greatest `end` of `intervals[..=i]`
+    furthest_end: Vec<f32>,
+}
+
+impl IntervalIndex {
+    /// Index the features at `first_index..` of the layer by `measure`.
+    /// Features without a value for the measure are left out.
+    pub fn new<'a>(features: impl IntoIterator<Item = &'a Attributes>, first_index: usize, measure: &Measure) -> Self {
+        Self::from_indexed(
+            features.into_iter().enumerate().map(|(offset, attributes)| (first_index + offset, attributes)),
+            measure,
+        )
+    }
+
+    /// Like [IntervalIndex::new], for features which are not next to each
+    /// other in the layer. Each feature comes with the index to return for it.
+    pub fn from_indexed<'a>(features: impl IntoIterator<Item = (usize, &'a Attributes)>, measure: &Measure) -> Self {
+        let mut intervals: Vec<Interval> = features
+            .into_iter()
+            .filter_map(|(feature_index, attributes)| {
+                let (start, end) = measure.feature_range(attributes)?;
+                if start.is_nan() || end.is_nan() {
+                    return None;
+                }
+                Some(Interval {
+                    start: start.min(end),
+                    end: start.max(end),
+                    feature_index,
+                })
+            })
+            .collect();
+        intervals.sort_by(|a, b| a.start.total_cmp(&b.start));
+        let furthest_end = intervals
+            .iter()
+            .scan(f32::NEG_INFINITY, |furthest, interval| {
+                *furthest = furthest.max(interval.end);
+                Some(*furthest)
+            })
+            .collect();
+        Self { intervals, furthest_end }
+    }
+
+    /// Indexes of the features which touch `from` to `to` (inclusive), in the
+    /// order they appear in the layer. The caller still needs to check each
+    /// feature; this only narrows down which ones to look at.
+    pub fn overlapping(&self, from: f32, to: f32) -> Vec<usize> {
+        let last = self.intervals.partition_point(|interval| interval.start <= to);
+        let first = self.furthest_end[..last].partition_point(|end| *end < from);
+        let mut feature_indexes: Vec<usize> = self.intervals[first..last]
+            .iter()
+            .filter(|interval| interval.end >= from)
+            .map(|interval| interval.feature_index)
+            .collect();
+        feature_indexes.sort_unstable();
+        feature_indexes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::cached::Cwy;
+
+    fn feature(start_slk: f32, end_slk: f32, true_dist: Option<(f32, f32)>) -> Attributes {
+        Attributes {
+            START_TRUE_DIST: true_dist.map(|true_dist| true_dist.0),
+            END_TRUE_DIST: true_dist.map(|true_dist| true_dist.1),
+            ..Attributes::new("H001", Cwy::Single, start_slk, end_slk)
+        }
+    }
+
+    #[test]
+    fn test_interval_index_overlapping() {
+        let features = vec![
+            feature(2.0, 3.0, None),
+            feature(0.0, 1.0, None),
+            // long feature overlapping everything after it
+            feature(1.0, 10.0, None),
+            feature(3.0, 4.0, None),
+            // reversed
+            feature(6.0, 5.0, None),
+            feature(f32::NAN, 7.0, None),
+        ];
+        let index = IntervalIndex::new(&features, 100, &Measure::slk);
+        assert_eq!(index.overlapping(0.5, 0.5), vec![101]);
+        // touching counts; the caller decides if touching is enough
+        assert_eq!(index.overlapping(1.0, 1.0), vec![101, 102]);
+        assert_eq!(index.overlapping(2.5, 3.5), vec![100, 102, 103]);
+        assert_eq!(index.overlapping(5.5, 5.6), vec![102, 104]);
+        assert_eq!(index.overlapping(8.0, 20.0), vec![102]);
+        assert!(index.overlapping(10.5, 20.0).is_empty());
+        assert!(index.overlapping(-2.0, -1.0).is_empty());
+        assert!(index.overlapping(f32::NAN, 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_interval_index_true_dist() {
+        let features = vec![
+            feature(0.0, 1.0, Some((0.0, 1.1))),
+            feature(1.0, 2.0, None),
+            feature(2.0, 3.0, Some((2.1, 3.1))),
+        ];
+        let index = IntervalIndex::new(&features, 0, &Measure::true_dist);
+        assert_eq!(index.overlapping(0.0, 5.0), vec![0, 2]);
+        assert_eq!(index.overlapping(1.05, 1.05), vec![0]);
+        assert!(index.overlapping(1.5, 2.0).is_empty());
+    }
+
+    #[test]
+    /// Every feature found by checking them all one by one is also found by
+    /// the index
+    fn test_interval_index_matches_linear_scan() {
+        let features: Vec<Attributes> = (0..200)
+            .map(|i| {
+                let start = ((i * 37) % 101) as f32 * 0.1;
+                let length = ((i * 13) % 7) as f32 * 0.3;
+                feature(start, start + length, None)
+            })
+            .collect();
+        let index = IntervalIndex::new(&features, 0, &Measure::slk);
+        for from in (0..120).map(|i| i as f32 * 0.1) {
+            let to = from + 0.25;
+            let expected: Vec<usize> = features
+                .iter()
+                .enumerate()
+                .filter(|(_, attributes)| attributes.END_SLK >= from && attributes.START_SLK <= to)
+                .map(|(i, _)| i)
+                .collect();
+            assert_eq!(index.overlapping(from, to), expected);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use nickslinetoolsrust::vector2::Vector2;
+
+use super::super::cached::{Feature, MeasureScale};
+
+/// The distance along `geometry` to each vertex, in the units of the
+/// coordinates
+fn cumulative_lengths(geometry: &[Vector2]) -> Vec<f64> {
+    let mut total = 0.0;
+    let mut lengths = Vec::with_capacity(geometry.len());
+    if !geometry.is_empty() {
+        lengths.push(0.0);
+    }
+    for segment in geometry.windows(2) {
+        total += (segment[1].x - segment[0].x).hypot(segment[1].y - segment[0].y);
+        lengths.push(total);
+    }
+    lengths
+}
+
+fn lerp(a: &Vector2, b: &Vector2, fraction: f64) -> Vector2 {
+    Vector2::new(a.x + (b.x - a.x) * fraction, a.y + (b.y - a.y) * fraction)
+}
+
+/// The lengths of one feature, worked out when the data is loaded so that
+/// `/line`, `/point` and batch requests can cut, interpolate and find the
+/// direction of its geometry without measuring every segment again. The
+/// vertexes themselves are not copied; each method takes the geometry of the
+/// feature this was made from.
+pub struct MeasuredLine {
+    /// The distance along the geometry to each vertex, in the units of the
+    /// coordinates. The last is the total length.
+    lengths: Vec<f64>,
+    /// `None` unless the data source provided measures. See
+    /// [Feature::measure_scale]
+    measure_scale: Option<MeasureScale>,
+}
+
+impl MeasuredLine {
+    pub fn new(feature: &Feature) -> Self {
+        Self {
+            lengths: cumulative_lengths(&feature.geometry),
+            measure_scale: feature.measure_scale(),
+        }
+    }
+
+    /// Measure a line which is not a feature, such as an offset line
+    pub fn from_geometry(geometry: &[Vector2]) -> Self {
+        Self {
+            lengths: cumulative_lengths(geometry),
+            measure_scale: None,
+        }
+    }
+
+    fn length(&self) -> f64 {
+        self.lengths.last().copied().unwrap_or(0.0)
+    }
+
+    /// The segment containing the point `length` along the line, as the
+    /// index of its first vertex and the fraction of the way along it. `None`
+    /// if the line has less than two vertexes.
+    fn locate(&self, length: f64) -> Option<(usize, f64)> {
+        if self.lengths.len() < 2 {
+            return None;
+        }
+        let end = self.lengths.partition_point(|vertex_length| *vertex_length < length).clamp(1, self.lengths.len() - 1);
+        let (start_length, end_length) = (self.lengths[end - 1], self.lengths[end]);
+        let fraction = if end_length > start_length {
+            (length - start_length) / (end_length - start_length)
+        } else {
+            0.0
+        };
+        Some((end - 1, fraction))
+    }
+
+    /// The part of `geometry` between two fractions of its length. `None` if
+    /// the part has no length.
+    pub fn cut(&self, geometry: &[Vector2], length_fraction_start: f64, length_fraction_end: f64) -> Option<Vec<Vector2>> {
+        let length = self.length();
+        let start = length_fraction_start.clamp(0.0, 1.0) * length;
+        let end = length_fraction_end.clamp(0.0, 1.0) * length;
+        if !(start < end) {
+            return None;
+        }
+        let (start_index, start_fraction) = self.locate(start)?;
+        let (end_index, end_fraction) = self.locate(end)?;
+        let mut vertexes = Vec::with_capacity(end_index - start_index + 2);
+        vertexes.push(lerp(&geometry[start_index], &geometry[start_index + 1], start_fraction));
+        vertexes.extend(
+            (start_index + 1..=end_index)
+                .filter(|index| self.lengths[*index] > start && self.lengths[*index] < end)
+                .map(|index| geometry[index])
+        );
+        vertexes.push(lerp(&geometry[end_index], &geometry[end_index + 1], end_fraction));
+        Some(vertexes)
+    }
+
+    /// The point a fraction of the way along `geometry`, and the direction
+    /// of the segment it is on in radians anti-clockwise from east
+    pub fn interpolate(&self, geometry: &[Vector2], length_fraction: f64) -> Option<(Vector2, f64)> {
+        let (index, fraction) = self.locate(length_fraction.clamp(0.0, 1.0) * self.length())?;
+        let (a, b) = (&geometry[index], &geometry[index + 1]);
+        Some((lerp(a, b, fraction), (b.y - a.y).atan2(b.x - a.x)))
+    }
+
+    /// Convert a fraction of the way from the start to the end SLK of the
+    /// feature this was made from into a fraction of the length of its
+    /// geometry. See [MeasureScale::length_fraction]
+    pub fn length_fraction(&self, measure_fraction: f64) -> f64 {
+        match &self.measure_scale {
+            Some(measure_scale) => measure_scale.length_fraction(measure_fraction),
+            None => measure_fraction,
+        }
+    }
+
+    /// The same as [Feature::measure_fraction] for the feature this was made
+    /// from
+    pub fn measure_fraction(&self, length_fraction: f64) -> f64 {
+        match &self.measure_scale {
+            Some(measure_scale) => measure_scale.measure_fraction(length_fraction),
+            None => length_fraction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::cached::{Attributes, Cwy};
+
+    fn feature(measures: Option<Vec<f64>>) -> Feature {
+        Feature {
+            measures,
+            ..Feature::new(Attributes::new("H001", Cwy::Single, 0.0, 1.0), &[(0.0, 0.0), (1.0, 0.0), (3.0, 0.0)])
+        }
+    }
+
+    fn tuples(vertexes: Vec<Vector2>) -> Vec<(f64, f64)> {
+        let round = |value: f64| (value * 1e9).round() / 1e9;
+        vertexes.iter().map(|vertex| (round(vertex.x), round(vertex.y))).collect()
+    }
+
+    #[test]
+    fn test_measured_line_length_fraction() {
+        let measured_line = MeasuredLine::new(&feature(None));
+        for fraction in [-0.5, 0.0, 0.2, 0.8, 0.9, 1.0, 1.5] {
+            assert_eq!(measured_line.length_fraction(fraction), fraction);
+            assert_eq!(measured_line.measure_fraction(fraction), fraction);
+        }
+        let feature = feature(Some(vec![0.0, 800.0, 1000.0]));
+        let measured_line = MeasuredLine::new(&feature);
+        for fraction in [-0.5, 0.0, 0.2, 0.8, 0.9, 1.0, 1.5] {
+            assert_eq!(measured_line.measure_fraction(fraction), feature.measure_fraction(fraction));
+        }
+        assert!((measured_line.length_fraction(0.8) - 1.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_measured_line_cut() {
+        let feature = feature(None);
+        let measured_line = MeasuredLine::new(&feature);
+        let cut = |start, end| measured_line.cut(&feature.geometry, start, end).map(tuples);
+        assert_eq!(cut(0.0, 1.0), Some(vec![(0.0, 0.0), (1.0, 0.0), (3.0, 0.0)]));
+        assert_eq!(cut(-1.0, 2.0), Some(vec![(0.0, 0.0), (1.0, 0.0), (3.0, 0.0)]));
+        assert_eq!(cut(0.0, 0.5), Some(vec![(0.0, 0.0), (1.0, 0.0), (1.5, 0.0)]));
+        assert_eq!(cut(0.1, 0.2), Some(vec![(0.3, 0.0), (0.6, 0.0)]));
+        // a cut exactly at a vertex does not repeat it
+        assert_eq!(cut(1.0 / 3.0, 1.0), Some(vec![(1.0, 0.0), (3.0, 0.0)]));
+        assert_eq!(cut(0.5, 0.5), None);
+        assert_eq!(cut(0.6, 0.5), None);
+        assert!(MeasuredLine::from_geometry(&feature.geometry[..1]).cut(&feature.geometry[..1], 0.0, 1.0).is_none());
+    }
+
+    #[test]
+    fn test_measured_line_interpolate() {
+        let geometry = vec![Vector2::new(0.0, 0.0), Vector2::new(1.0, 0.0), Vector2::new(1.0, 1.0)];
+        let measured_line = MeasuredLine::from_geometry(&geometry);
+        let interpolate = |fraction| measured_line.interpolate(&geometry, fraction).map(|(vertex, direction)| (vertex.x, vertex.y, direction));
+        assert_eq!(interpolate(0.0), Some((0.0, 0.0, 0.0)));
+        assert_eq!(interpolate(0.25), Some((0.5, 0.0, 0.0)));
+        assert_eq!(interpolate(0.75), Some((1.0, 0.5, std::f64::consts::FRAC_PI_2)));
+        assert_eq!(interpolate(1.0), Some((1.0, 1.0, std::f64::consts::FRAC_PI_2)));
+        assert!(MeasuredLine::from_geometry(&[]).interpolate(&[], 0.5).is_none());
+    }
+}
@@ -1,5 +1,27 @@
 mod indexed_data;
 pub use indexed_data::IndexedData;
 
+/// The features being served, either decoded or in a memory mapped file
+mod feature_store;
+
 mod road_data_by_cwy;
-use road_data_by_cwy::RoadDataByCwy;
\ No newline at end of file
+
+/// Finds the features of a road and carriageway within an SLK range by
+/// binary search
+mod interval_index;
+pub(super) use interval_index::IntervalIndex;
+
+/// Feature geometry with its segment lengths worked out in advance
+mod measured_line;
+pub use measured_line::MeasuredLine;
+
+mod spatial_index;
+
+mod road_search_index;
+
+mod validation;
+
+mod data_status;
+
+mod shared_indexed_data;
+pub use shared_indexed_data::SharedIndexedData;
\ No newline at end of file
@@ -1,46 +1,61 @@
 use std::ops::Index;
+
+use crate::filters::query_parameters::Measure;
+
 use super::{
-    super::cached::Cwy
+	interval_index::IntervalIndex,
+	super::cached::{Cwy, FeatureSummary}
 };
 
-
-#[allow(non_snake_case)]
-pub struct RoadDataByCwy {
-	pub Left: Option<(usize, usize)>,
-	pub Right: Option<(usize, usize)>,
-	pub Single: Option<(usize, usize)>,
+/// The features of one carriageway of a road
+pub struct CwyData {
+	/// `start..end` within the features of the layer
+	pub range: (usize, usize),
+	slk: IntervalIndex,
+	true_dist: IntervalIndex,
 }
 
-impl RoadDataByCwy {
-	pub fn new(
-		l: Option<(usize, usize)>,
-		r: Option<(usize, usize)>,
-		s: Option<(usize, usize)>,
-	) -> Self {
+impl CwyData {
+	/// `range` selects the features of the carriageway from all the
+	/// `features` of the layer
+	pub fn new(features: &[FeatureSummary], range: (usize, usize)) -> Self {
+		let cwy_attributes = || features[range.0..range.1].iter().map(|feature| &feature.attributes);
 		Self {
-			Left: l,
-			Right: r,
-			Single: s,
+			range,
+			slk: IntervalIndex::new(cwy_attributes(), range.0, &Measure::slk),
+			true_dist: IntervalIndex::new(cwy_attributes(), range.0, &Measure::true_dist),
 		}
 	}
-	pub fn new_from_cwy(cwy: &Cwy, range: (usize, usize)) -> Self {
-		match cwy {
-			Cwy::Left => Self::new(Some(range), None, None),
-			Cwy::Right => Self::new(None, Some(range), None),
-			Cwy::Single => Self::new(None, None, Some(range)),
+
+	pub fn interval_index(&self, measure: &Measure) -> &IntervalIndex {
+		match measure {
+			Measure::slk => &self.slk,
+			Measure::true_dist => &self.true_dist,
 		}
 	}
-	pub fn with_updated_cwy(&self, cwy: &Cwy, range: (usize, usize)) -> Self {
+}
+
+#[allow(non_snake_case)]
+#[derive(Default)]
+pub struct RoadDataByCwy {
+	pub Left: Option<CwyData>,
+	pub Right: Option<CwyData>,
+	pub Single: Option<CwyData>,
+}
+
+impl RoadDataByCwy {
+	/// Replaces anything already recorded for `cwy`
+	pub fn set_cwy(&mut self, cwy: &Cwy, cwy_data: CwyData) {
 		match cwy {
-			Cwy::Left => Self::new(Some(range), self.Right, self.Single),
-			Cwy::Right => Self::new(self.Left, Some(range), self.Single),
-			Cwy::Single => Self::new(self.Left, self.Right, Some(range)),
+			Cwy::Left => self.Left = Some(cwy_data),
+			Cwy::Right => self.Right = Some(cwy_data),
+			Cwy::Single => self.Single = Some(cwy_data),
 		}
 	}
 }
 
 impl Index<&Cwy> for RoadDataByCwy {
-	type Output = Option<(usize, usize)>;
+	type Output = Option<CwyData>;
 	fn index(&self, index: &Cwy) -> &Self::Output {
 		match index {
 			Cwy::Left => &self.Left,
@@ -0,0 +1,262 @@
+use serde::Serialize;
+
+use super::super::cached::{Cwy, FeatureSummary};
+
+/// Describes one road, as returned by the `/roads` route
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RoadSummary {
+    pub road: String,
+    /// Every distinct road name recorded for this road. Usually one, but
+    /// empty if the data source has no road names.
+    pub road_name: Vec<String>,
+    pub cwy: Vec<Cwy>,
+    pub slk_from: f32,
+    pub slk_to: f32,
+}
+
+struct SearchEntry {
+    summary: RoadSummary,
+    uppercase_road: String,
+    normalised_road: String,
+    lowercase_road_names: Vec<String>,
+}
+
+/// The text of a search in each of the forms compared by
+/// [SearchEntry::score], so that it is only converted once per search
+struct SearchText {
+    uppercase: String,
+    normalised: String,
+    lowercase: String,
+}
+
+/// Precomputed list of every road so that type-ahead searches do not have to
+/// visit every feature of the data.
+pub struct RoadSearchIndex {
+    entries: Vec<SearchEntry>,
+}
+
+/// Uppercase, and drop leading zeros from the first group of digits so that
+/// `h1`, `H01` and `H001` are all treated as the same road number
+fn normalise_road_number(road: &str) -> String {
+    let road = road.trim().to_uppercase();
+    let digits_start = road.find(|c: char| c.is_ascii_digit()).unwrap_or(road.len());
+    let (prefix, rest) = road.split_at(digits_start);
+    let digits_end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let (digits, suffix) = rest.split_at(digits_end);
+    let trimmed_digits = match digits.trim_start_matches('0') {
+        "" if !digits.is_empty() => "0",
+        trimmed => trimmed,
+    };
+    format!("{}{}{}", prefix, trimmed_digits, suffix)
+}
+
+/// Levenshtein distance counted in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous_row[j] + usize::from(a_char != *b_char);
+            row.push(substitution.min(previous_row[j + 1] + 1).min(row[j] + 1));
+        }
+        previous_row = row;
+    }
+    previous_row[b.len()]
+}
+
+/// Number of typing mistakes tolerated by the fuzzy match. Short searches
+/// must match exactly, otherwise almost everything would match.
+fn allowed_edits(search: &str) -> usize {
+    match search.chars().count() {
+        0..=2 => 0,
+        3..=6 => 1,
+        _ => 2,
+    }
+}
+
+impl SearchEntry {
+    /// Lower scores are better matches. `None` means no match.
+    fn score(&self, search: &SearchText) -> Option<u8> {
+        let normalised_search = search.normalised.as_str();
+        let lowercase_search = search.lowercase.as_str();
+        if self.uppercase_road == search.uppercase || self.normalised_road == normalised_search {
+            return Some(0);
+        }
+        if self.uppercase_road.starts_with(&search.uppercase) || self.normalised_road.starts_with(normalised_search) {
+            return Some(1);
+        }
+        let names = &self.lowercase_road_names;
+        if names.iter().any(|name| name.starts_with(lowercase_search)
+            || name.split_whitespace().any(|word| word.starts_with(lowercase_search)))
+        {
+            return Some(2);
+        }
+        if names.iter().any(|name| name.contains(lowercase_search)) {
+            return Some(3);
+        }
+        let road_edits = allowed_edits(normalised_search);
+        let name_edits = allowed_edits(lowercase_search);
+        let similar_road = road_edits > 0
+            && edit_distance(&self.normalised_road, normalised_search) <= road_edits;
+        let similar_name = name_edits > 0 && names
+            .iter()
+            .flat_map(|name| name.split_whitespace())
+            .any(|word| edit_distance(word, lowercase_search) <= name_edits);
+        if similar_road || similar_name {
+            return Some(4);
+        }
+        None
+    }
+}
+
+impl RoadSearchIndex {
+    /// Expects the features of the layer to be sorted by road number
+    pub fn new(features: &[FeatureSummary]) -> Self {
+        println!("INFO: Building road search index");
+        let mut entries: Vec<SearchEntry> = Vec::new();
+        for feature in features.iter() {
+            let attributes = &feature.attributes;
+            let entry = match entries.last_mut() {
+                Some(entry) if entry.summary.road == attributes.ROAD => entry,
+                _ => {
+                    entries.push(SearchEntry {
+                        summary: RoadSummary {
+                            road: attributes.ROAD.clone(),
+                            road_name: Vec::new(),
+                            cwy: Vec::new(),
+                            slk_from: attributes.START_SLK,
+                            slk_to: attributes.END_SLK,
+                        },
+                        uppercase_road: attributes.ROAD.to_uppercase(),
+                        normalised_road: normalise_road_number(&attributes.ROAD),
+                        lowercase_road_names: Vec::new(),
+                    });
+                    entries.last_mut().unwrap()
+                }
+            };
+            let summary = &mut entry.summary;
+            summary.slk_from = summary.slk_from.min(attributes.START_SLK);
+            summary.slk_to = summary.slk_to.max(attributes.END_SLK);
+            if !summary.cwy.contains(&attributes.CWY) {
+                summary.cwy.push(attributes.CWY);
+            }
+            if let Some(road_name) = &attributes.ROAD_NAME {
+                if !summary.road_name.contains(road_name) {
+                    summary.road_name.push(road_name.clone());
+                    entry.lowercase_road_names.push(road_name.to_lowercase());
+                }
+            }
+        }
+        for entry in entries.iter_mut() {
+            entry.summary.cwy.sort();
+        }
+        Self { entries }
+    }
+
+    /// Find roads by road number or road name. Exact road numbers are listed
+    /// first, then road numbers starting with `search`, then matching road
+    /// names, and finally near misses.
+    pub fn search(&self, search: &str, limit: usize) -> Vec<&RoadSummary> {
+        let search = search.trim();
+        if search.is_empty() {
+            return Vec::new();
+        }
+        let search = SearchText {
+            uppercase: search.to_uppercase(),
+            normalised: normalise_road_number(search),
+            lowercase: search.to_lowercase(),
+        };
+        let mut matches: Vec<(u8, &RoadSummary)> = self.entries
+            .iter()
+            .filter_map(|entry| entry
+                .score(&search)
+                .map(|score| (score, &entry.summary))
+            )
+            .collect();
+        matches.sort_by(|(a_score, a), (b_score, b)| {
+            a_score.cmp(b_score)
+                .then(a.road.len().cmp(&b.road.len()))
+                .then(a.road.cmp(&b.road))
+        });
+        matches.into_iter().take(limit).map(|(_, summary)| summary).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Attributes;
+
+    fn feature(road: &str, cwy: Cwy, slk: (f32, f32), road_name: Option<&str>) -> FeatureSummary {
+        FeatureSummary::new(Attributes {
+            ROAD_NAME: road_name.map(str::to_owned),
+            ..Attributes::new(road, cwy, slk.0, slk.1)
+        })
+    }
+
+    fn sample_index() -> RoadSearchIndex {
+        RoadSearchIndex::new(&[
+            feature("1110050", Cwy::Single, (0.0, 0.4), Some("Hay Street")),
+            feature("H001", Cwy::Left, (0.0, 2.0), Some("Albany Highway")),
+            feature("H001", Cwy::Right, (0.0, 2.0), Some("Albany Highway")),
+            feature("H001", Cwy::Single, (2.0, 5.5), Some("Albany Highway")),
+            feature("H010", Cwy::Single, (0.0, 3.0), Some("Albany Highway Service Road")),
+            feature("H015", Cwy::Single, (0.0, 9.0), Some("Stirling Highway")),
+        ])
+    }
+
+    #[test]
+    fn test_normalise_road_number() {
+        assert_eq!(normalise_road_number("h1"), "H1");
+        assert_eq!(normalise_road_number(" H001 "), "H1");
+        assert_eq!(normalise_road_number("H000"), "H0");
+        assert_eq!(normalise_road_number("1110050"), "1110050");
+        assert_eq!(normalise_road_number("M012A"), "M12A");
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("albany", "albany"), 0);
+        assert_eq!(edit_distance("albny", "albany"), 1);
+        assert_eq!(edit_distance("stirlnig", "stirling"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_road_search_summary() {
+        let index = sample_index();
+        let result = index.search("H1", 10);
+        assert_eq!(result[0], &RoadSummary {
+            road: "H001".to_owned(),
+            road_name: vec!["Albany Highway".to_owned()],
+            cwy: vec![Cwy::Left, Cwy::Right, Cwy::Single],
+            slk_from: 0.0,
+            slk_to: 5.5,
+        });
+    }
+
+    #[test]
+    fn test_road_search_order() {
+        let index = sample_index();
+        let roads = |search: &str| index
+            .search(search, 10)
+            .into_iter()
+            .map(|summary| summary.road.as_str())
+            .collect::<Vec<&str>>();
+        // exact match first, then road numbers with that prefix
+        assert_eq!(roads("H01"), vec!["H001", "H010", "H015"]);
+        assert_eq!(roads("h00"), vec!["H001"]);
+        assert_eq!(roads("111"), vec!["1110050"]);
+        // road names, by the start of a word and then anywhere in the name
+        assert_eq!(roads("albany"), vec!["H001", "H010"]);
+        assert_eq!(roads("service"), vec!["H010"]);
+        assert_eq!(roads("ay st"), vec!["1110050"]);
+        // typing mistakes
+        assert_eq!(roads("stirlnig"), vec!["H015"]);
+        assert_eq!(roads("albny"), vec!["H001", "H010"]);
+        assert!(roads("").is_empty());
+        assert!(roads("zz").is_empty());
+        assert_eq!(index.search("H", 2).len(), 2);
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
+
+use crate::helpers::{CalendarDate, ErrorWithStaticMessage};
+
+use super::{
+    IndexedData,
+    super::{DataSourceSettings, Snapshots}
+};
+
+/// A handle to the [IndexedData] currently being served, which can be
+/// replaced while the server is running.
+///
+/// Each request takes its own `Arc` clone of the current data with
+/// [SharedIndexedData::current]. When the data is replaced, requests that are
+/// already in flight keep using the old data until they finish, and the old
+/// data is dropped when the last of them completes.
+///
+/// Queries with an `as_of=` date older than the current data are answered
+/// from [Snapshots] instead.
+pub struct SharedIndexedData {
+    current: RwLock<Arc<IndexedData>>,
+    snapshots: Snapshots,
+    reload_in_progress: AtomicBool,
+}
+
+/// Returned by [SharedIndexedData::try_begin_reload]. Marks the reload as
+/// finished when dropped.
+pub struct ReloadGuard<'a> {
+    reload_in_progress: &'a AtomicBool,
+}
+
+impl Drop for ReloadGuard<'_> {
+    fn drop(&mut self) {
+        self.reload_in_progress.store(false, Ordering::SeqCst);
+    }
+}
+
+impl SharedIndexedData {
+    pub fn new(indexed_data: IndexedData, snapshots: Snapshots) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(indexed_data)),
+            snapshots,
+            reload_in_progress: AtomicBool::new(false),
+        }
+    }
+
+    /// Get the data that new requests should be answered from
+    pub fn current(&self) -> Arc<IndexedData> {
+        // a poisoned lock still holds a valid Arc, so carry on regardless
+        self.current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Get the data that a query with an optional `as_of=` date should be
+    /// answered from. The current data is used if `as_of` is today or later,
+    /// or the current data was downloaded on or before `as_of`. Otherwise the
+    /// newest snapshot taken on or before `as_of` is used. `None` if there is
+    /// no such snapshot, or the download date of the current data is not
+    /// known, since the current data may not match the network on that date.
+    pub async fn as_of(&self, as_of: &Option<CalendarDate>) -> Result<Option<Arc<IndexedData>>, ErrorWithStaticMessage> {
+        let current = self.current();
+        match as_of {
+            None => Ok(Some(current)),
+            Some(as_of) if *as_of >= CalendarDate::today() => Ok(Some(current)),
+            Some(as_of) if current.downloaded().is_some_and(|downloaded| downloaded <= as_of) => Ok(Some(current)),
+            Some(_) if current.downloaded().is_none() => Ok(None),
+            Some(as_of) => self.snapshots.load_as_of(as_of).await,
+        }
+    }
+
+    /// Atomically swap in new data. Returns the data that was replaced.
+    pub fn replace(&self, indexed_data: IndexedData) -> Arc<IndexedData> {
+        let mut current = self
+            .current
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        std::mem::replace(&mut *current, Arc::new(indexed_data))
+    }
+
+    /// Load fresh data and swap it in for new requests. The previous data
+    /// continues to be served if anything goes wrong.
+    ///
+    /// When `force_update` is false the cache file is read; otherwise fresh
+    /// data is downloaded. Unlike at startup, a failed download is never
+    /// replaced by the cache file or its backup. Returns the number of
+    /// features now being served.
+    pub async fn reload(
+        &self,
+        data_source_settings: &DataSourceSettings,
+        force_update: bool,
+    ) -> Result<usize, &'static str> {
+        let _guard = self
+            .try_begin_reload()
+            .ok_or("Reload failed. Another reload is already in progress.")?;
+
+        let new_data = match IndexedData::load(data_source_settings, &force_update, &false).await {
+            Ok(new_data) => new_data,
+            Err(error_message) => {
+                println!("WARNING: Reload failed because '{}'. Continuing to serve the previous data.", error_message);
+                return Err("Reload failed. Continuing to serve the previous data.");
+            }
+        };
+
+        let feature_count = new_data.feature_count();
+        self.replace(new_data);
+        // a snapshot of the new data may have just been saved
+        self.snapshots.rescan();
+        println!("INFO: Reload completed. Now serving {} features.", feature_count);
+        Ok(feature_count)
+    }
+
+    /// Returns `None` if another reload is already running. Otherwise the
+    /// returned guard must be held for the duration of the reload.
+    pub fn try_begin_reload(&self) -> Option<ReloadGuard<'_>> {
+        match self.reload_in_progress.compare_exchange(
+            false,
+            true,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => Some(ReloadGuard {
+                reload_in_progress: &self.reload_in_progress,
+            }),
+            Err(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::{Attributes, Cwy, Feature, Layer};
+
+    fn downloaded_on(downloaded: Option<CalendarDate>) -> SharedIndexedData {
+        let indexed_data = IndexedData::from_layer(Layer {
+            features: vec![Feature::new(Attributes::new("H001", Cwy::Single, 0.0, 1.0), &[(115.0, -32.0), (115.01, -32.0)])],
+            source_metadata: None,
+            downloaded,
+            spatial_reference: None,
+            cache_metadata: None,
+        }).unwrap();
+        SharedIndexedData::new(indexed_data, Snapshots::open(&None))
+    }
+
+    #[tokio::test]
+    async fn test_as_of() {
+        let shared_data = downloaded_on(CalendarDate::new(2024, 6, 13));
+        assert!(shared_data.as_of(&None).await.unwrap().is_some());
+        assert!(shared_data.as_of(&CalendarDate::new(2024, 6, 13)).await.unwrap().is_some());
+        assert!(shared_data.as_of(&CalendarDate::new(2999, 1, 1)).await.unwrap().is_some());
+        // older than the current data, and there are no snapshots
+        assert!(shared_data.as_of(&CalendarDate::new(2024, 6, 12)).await.unwrap().is_none());
+
+        // without a download date only today or later can be answered
+        let shared_data = downloaded_on(None);
+        assert!(shared_data.as_of(&None).await.unwrap().is_some());
+        assert!(shared_data.as_of(&Some(CalendarDate::today())).await.unwrap().is_some());
+        assert!(shared_data.as_of(&CalendarDate::new(2024, 6, 13)).await.unwrap().is_none());
+    }
+}
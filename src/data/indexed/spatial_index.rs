@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use nickslinetoolsrust::vector2::Vector2;
+
+use super::super::cached::FeatureSummary;
+
+/// Width and height of each grid cell in degrees. Roughly 1km in Western
+/// Australia; most features are much shorter than this so they are usually
+/// recorded in only one or two cells.
+const CELL_SIZE_DEGREES: f64 = 0.01;
+
+/// An axis aligned bounding box stored as `(min, max)`
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingBox {
+    pub min: Vector2,
+    pub max: Vector2,
+}
+
+impl BoundingBox {
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+    }
+}
+
+/// A uniform grid over the geometry of every feature of a layer. Each cell
+/// records the indexes of the features whose bounding box touches that cell,
+/// so a search near a point only needs to inspect a handful of cells instead
+/// of every feature in the network.
+pub struct SpatialIndex {
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    bounding_boxes: Vec<Option<BoundingBox>>,
+}
+
+fn cell_coordinate(value: f64) -> i32 {
+    (value / CELL_SIZE_DEGREES).floor() as i32
+}
+
+impl SpatialIndex {
+    pub fn new(features: &[FeatureSummary]) -> Self {
+        println!("INFO: Building spatial index");
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        let bounding_boxes: Vec<Option<BoundingBox>> = features
+            .iter()
+            .map(|feature| feature.extent.map(|(min, max)| BoundingBox { min, max }))
+            .collect();
+
+        for (feature_index, bounding_box) in bounding_boxes.iter().enumerate() {
+            if let Some(bounding_box) = bounding_box {
+                for cell_x in cell_coordinate(bounding_box.min.x)..=cell_coordinate(bounding_box.max.x) {
+                    for cell_y in cell_coordinate(bounding_box.min.y)..=cell_coordinate(bounding_box.max.y) {
+                        cells.entry((cell_x, cell_y)).or_default().push(feature_index);
+                    }
+                }
+            }
+        }
+
+        Self { cells, bounding_boxes }
+    }
+
+    /// Returns the index of every feature whose bounding box intersects the
+    /// `search_box`. Each index is returned once, in ascending order.
+    pub fn query(&self, search_box: &BoundingBox) -> Vec<usize> {
+        let mut result: Vec<usize> = Vec::new();
+        for cell_x in cell_coordinate(search_box.min.x)..=cell_coordinate(search_box.max.x) {
+            for cell_y in cell_coordinate(search_box.min.y)..=cell_coordinate(search_box.max.y) {
+                if let Some(feature_indexes) = self.cells.get(&(cell_x, cell_y)) {
+                    result.extend(feature_indexes.iter().filter(|&&feature_index| {
+                        match &self.bounding_boxes[feature_index] {
+                            Some(bounding_box) => bounding_box.intersects(search_box),
+                            None => false,
+                        }
+                    }));
+                }
+            }
+        }
+        result.sort_unstable();
+        result.dedup();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::{Attributes, Cwy, Feature};
+
+    fn feature(road: &str, geometry: Vec<(f64, f64)>) -> Feature {
+        Feature::new(Attributes::new(road, Cwy::Single, 0.0, 1.0), &geometry)
+    }
+
+    #[test]
+    fn test_spatial_index_query() {
+        let features: Vec<FeatureSummary> = [
+            feature("A", vec![(115.800, -32.000), (115.805, -32.000)]),
+            feature("B", vec![(115.900, -32.000), (115.950, -32.050)]),
+            feature("C", vec![(116.500, -31.000), (116.501, -31.001)]),
+        ].iter().map(Feature::summary).collect();
+        let index = SpatialIndex::new(&features);
+
+        let near_a = index.query(&BoundingBox {
+            min: Vector2::new(115.801, -32.001),
+            max: Vector2::new(115.802, -31.999),
+        });
+        assert_eq!(near_a, vec![0]);
+
+        // B spans several cells and must only be reported once
+        let near_b = index.query(&BoundingBox {
+            min: Vector2::new(115.89, -32.06),
+            max: Vector2::new(115.96, -31.99),
+        });
+        assert_eq!(near_b, vec![1]);
+
+        let nowhere = index.query(&BoundingBox {
+            min: Vector2::new(110.0, -20.0),
+            max: Vector2::new(110.1, -19.9),
+        });
+        assert!(nowhere.is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use super::super::cached::{Attributes, Cwy, FeatureSummary};
+
+/// SLKs closer than half a metre are treated as equal, so that rounding in
+/// the data source is not reported as a gap or overlap
+const SLK_TOLERANCE_KM: f32 = 0.0005;
+
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub enum Severity {
+    /// Queries touching this feature may return wrong or missing geometry
+    error,
+    /// Probably intended by the data source, but worth knowing about
+    warning,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub enum IssueKind {
+    /// The road and carriageway already appeared earlier in the data with
+    /// other features in between. Only one of the runs can be found by
+    /// queries.
+    not_contiguous,
+    /// `END_SLK` is less than `START_SLK`
+    reversed_slk,
+    /// The geometry has fewer than two vertices
+    missing_geometry,
+    /// Covers SLKs already covered by another feature of the same carriageway
+    overlap,
+    /// `START_SLK` is equal to `END_SLK`
+    zero_length,
+    /// Starts at a lower SLK than the feature before it in the same carriageway
+    out_of_order,
+    /// SLKs between two features of the same carriageway are not covered
+    gap,
+}
+
+const ALL_ISSUE_KINDS: [IssueKind; 7] = [
+    IssueKind::not_contiguous,
+    IssueKind::reversed_slk,
+    IssueKind::missing_geometry,
+    IssueKind::overlap,
+    IssueKind::zero_length,
+    IssueKind::out_of_order,
+    IssueKind::gap,
+];
+
+impl IssueKind {
+    pub fn severity(&self) -> Severity {
+        use IssueKind::*;
+        match self {
+            not_contiguous | reversed_slk | missing_geometry | overlap => Severity::error,
+            zero_length | out_of_order | gap => Severity::warning,
+        }
+    }
+}
+
+/// One problem found in the data
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct ValidationIssue {
+    pub kind: IssueKind,
+    pub severity: Severity,
+    pub road: String,
+    pub cwy: Cwy,
+    /// The SLK range affected. For a `gap` this is the range not covered
+    pub slk_from: f32,
+    pub slk_to: f32,
+    /// Position of the feature in the loaded data. For a `gap` it is the
+    /// feature after the gap
+    pub feature_index: usize,
+}
+
+/// The result of checking the loaded data for problems which would otherwise
+/// silently produce odd output. Returned by the `/diagnostics` route.
+#[derive(Serialize, Debug, Default)]
+pub struct ValidationReport {
+    pub error_count: usize,
+    pub warning_count: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn new(features: &[FeatureSummary]) -> Self {
+        println!("INFO: Validating data");
+        let mut report = Self::default();
+        let mut seen: BTreeSet<(&str, Cwy)> = BTreeSet::new();
+        let mut run_start = 0;
+        for index in 1..=features.len() {
+            let run_continues = index < features.len()
+                && features[index].attributes.ROAD == features[run_start].attributes.ROAD
+                && features[index].attributes.CWY == features[run_start].attributes.CWY;
+            if run_continues {
+                continue;
+            }
+            let first = &features[run_start].attributes;
+            if !seen.insert((first.ROAD.as_str(), first.CWY)) {
+                report.push(IssueKind::not_contiguous, first, run_start, None);
+            }
+            report.check_carriageway(features, run_start, index);
+            run_start = index;
+        }
+        report
+    }
+
+    fn push(&mut self, kind: IssueKind, attributes: &Attributes, feature_index: usize, slk_range: Option<(f32, f32)>) {
+        let (slk_from, slk_to) = slk_range.unwrap_or((attributes.START_SLK, attributes.END_SLK));
+        match kind.severity() {
+            Severity::error => self.error_count += 1,
+            Severity::warning => self.warning_count += 1,
+        }
+        self.issues.push(ValidationIssue {
+            kind,
+            severity: kind.severity(),
+            road: attributes.ROAD.clone(),
+            cwy: attributes.CWY,
+            slk_from,
+            slk_to,
+            feature_index,
+        });
+    }
+
+    /// Check the features from `start` to `end` which all have the same road
+    /// and carriageway
+    fn check_carriageway(&mut self, features: &[FeatureSummary], start: usize, end: usize) {
+        for index in start..end {
+            let feature = &features[index];
+            let attributes = &feature.attributes;
+            if attributes.END_SLK < attributes.START_SLK {
+                self.push(IssueKind::reversed_slk, attributes, index, None);
+            } else if attributes.END_SLK == attributes.START_SLK {
+                self.push(IssueKind::zero_length, attributes, index, None);
+            }
+            if feature.vertex_count < 2 {
+                self.push(IssueKind::missing_geometry, attributes, index, None);
+            }
+            if index > start && attributes.START_SLK < features[index - 1].attributes.START_SLK - SLK_TOLERANCE_KM {
+                self.push(IssueKind::out_of_order, attributes, index, None);
+            }
+        }
+
+        // Gaps and overlaps are checked in SLK order, so that features which
+        // are merely out of order are not also reported as gaps
+        let mut by_slk: Vec<usize> = (start..end)
+            .filter(|&index| features[index].attributes.END_SLK > features[index].attributes.START_SLK)
+            .collect();
+        by_slk.sort_by(|&a, &b| features[a].attributes.START_SLK.total_cmp(&features[b].attributes.START_SLK));
+        let mut covered_to: Option<f32> = None;
+        for index in by_slk {
+            let attributes = &features[index].attributes;
+            let (slk_from, slk_to) = (attributes.START_SLK, attributes.END_SLK);
+            match covered_to {
+                Some(covered) if slk_from < covered - SLK_TOLERANCE_KM =>
+                    self.push(IssueKind::overlap, attributes, index, Some((slk_from, slk_to.min(covered)))),
+                Some(covered) if slk_from > covered + SLK_TOLERANCE_KM =>
+                    self.push(IssueKind::gap, attributes, index, Some((covered, slk_from))),
+                _ => (),
+            }
+            covered_to = Some(covered_to.map_or(slk_to, |covered| covered.max(slk_to)));
+        }
+    }
+
+    /// Print the number of issues of each kind
+    pub fn log_summary(&self) {
+        if self.issues.is_empty() {
+            println!("INFO: Validation found no problems with the data");
+            return;
+        }
+        let counts: Vec<String> = ALL_ISSUE_KINDS
+            .iter()
+            .map(|kind| (kind, self.issues.iter().filter(|issue| issue.kind == *kind).count()))
+            .filter(|(_, count)| *count > 0)
+            .map(|(kind, count)| format!("{:?}: {}", kind, count))
+            .collect();
+        let level = if self.error_count > 0 { "WARNING" } else { "INFO" };
+        println!(
+            "{}: Validation found {} errors and {} warnings ({}). See the /diagnostics route for details",
+            level,
+            self.error_count,
+            self.warning_count,
+            counts.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Feature;
+
+    fn feature(road: &str, cwy: Cwy, slk: (f32, f32)) -> Feature {
+        Feature::new(Attributes::new(road, cwy, slk.0, slk.1), &[(115.0, -32.0), (115.01, -32.0)])
+    }
+
+    fn validate(features: Vec<Feature>) -> ValidationReport {
+        ValidationReport::new(&features.iter().map(Feature::summary).collect::<Vec<_>>())
+    }
+
+    fn kinds(report: &ValidationReport) -> Vec<(IssueKind, usize)> {
+        report.issues.iter().map(|issue| (issue.kind, issue.feature_index)).collect()
+    }
+
+    #[test]
+    fn test_validation_clean() {
+        let report = validate(vec![
+            feature("H001", Cwy::Left, (0.0, 1.0)),
+            feature("H001", Cwy::Left, (1.0, 2.0)),
+            feature("H001", Cwy::Single, (2.0003, 3.0)),
+            feature("H002", Cwy::Single, (0.0, 1.0)),
+        ]);
+        assert!(report.issues.is_empty());
+        assert_eq!((report.error_count, report.warning_count), (0, 0));
+        assert!(validate(Vec::new()).issues.is_empty());
+    }
+
+    #[test]
+    fn test_validation_issues() {
+        let mut no_geometry = feature("H002", Cwy::Single, (2.0, 3.0));
+        no_geometry.geometry.truncate(1);
+        let report = validate(vec![
+            feature("H001", Cwy::Single, (1.0, 2.0)),
+            feature("H001", Cwy::Single, (0.0, 1.0)),
+            feature("H001", Cwy::Single, (1.5, 2.5)),
+            feature("H001", Cwy::Single, (3.0, 3.0)),
+            feature("H001", Cwy::Single, (4.0, 3.5)),
+            feature("H002", Cwy::Single, (0.0, 1.0)),
+            no_geometry,
+            feature("H001", Cwy::Single, (5.0, 6.0)),
+        ]);
+        assert_eq!(kinds(&report), vec![
+            (IssueKind::out_of_order, 1),
+            (IssueKind::zero_length, 3),
+            (IssueKind::reversed_slk, 4),
+            (IssueKind::overlap, 2),
+            (IssueKind::missing_geometry, 6),
+            (IssueKind::gap, 6),
+            (IssueKind::not_contiguous, 7),
+        ]);
+        assert_eq!((report.error_count, report.warning_count), (4, 3));
+        let overlap = &report.issues[3];
+        assert_eq!((overlap.slk_from, overlap.slk_to), (1.5, 2.0));
+        let gap = &report.issues[5];
+        assert_eq!((gap.road.as_str(), gap.slk_from, gap.slk_to), ("H002", 1.0, 2.0));
+    }
+}
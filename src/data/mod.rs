@@ -5,8 +5,36 @@ mod esri_json;
 /// This is the data we keep in memory and save/load from disk
 pub mod cached;
 
+/// Loaders for network data stored in local GeoJSON, FlatGeobuf or GeoPackage files
+mod sources;
+
+mod data_source_settings;
+pub use data_source_settings::DataSourceSettings;
+
+/// Describes which fields of the data source hold the road, carriageway and SLK
+mod field_mapping;
+pub use field_mapping::FieldMapping;
+
+/// Downloads features from the rest service in pages, with retries
+pub mod download;
+pub use download::DownloadSettings;
+
 /// This is a wrapper around the cached data which lets us query it really fast.
 mod indexed;
 pub use indexed::{
-    IndexedData
-};
\ No newline at end of file
+    IndexedData,
+    MeasuredLine,
+    SharedIndexedData
+};
+
+/// Dated copies of previously downloaded data, for `as_of=` queries
+mod snapshots;
+pub use snapshots::Snapshots;
+
+/// Compares two versions of the road network
+mod network_diff;
+pub use network_diff::{compare_files, DiffFormat, NetworkDiff};
+
+/// Periodically checks the rest service for new data
+mod background_refresh;
+pub use background_refresh::spawn_background_refresh;
\ No newline at end of file
@@ -0,0 +1,392 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    path::Path,
+};
+
+use nickslinetoolsrust::vector2::Vector2;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::filters::query_parameters::Measure;
+use crate::helpers::distance_metres;
+use super::{
+    cached::{Cwy, Feature, Layer},
+    indexed::IntervalIndex,
+};
+
+/// Changes in SLK smaller than this (half a metre) are ignored. SLKs are
+/// stored as f32 so are not always exactly equal.
+const SLK_TOLERANCE_KM: f32 = 0.0005;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize, Default, clap::ValueEnum)]
+#[allow(non_camel_case_types)]
+pub enum DiffFormat {
+    /// A FeatureCollection with one feature per change
+    #[default]
+    geojson,
+    /// One row per change, without geometry
+    csv,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub enum ChangeKind {
+    road_added,
+    road_removed,
+    cwy_added,
+    cwy_removed,
+    /// The lowest or highest SLK of a carriageway changed
+    extent_changed,
+    /// Part of a carriageway moved further than the tolerance
+    geometry_shifted,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Change {
+    pub change: ChangeKind,
+    pub road: String,
+    /// `None` when a whole road was added or removed
+    pub cwy: Option<Cwy>,
+    pub old_slk_from: Option<f32>,
+    pub old_slk_to: Option<f32>,
+    pub new_slk_from: Option<f32>,
+    pub new_slk_to: Option<f32>,
+    /// The furthest that any SLK moved, in metres. Only for `geometry_shifted`
+    pub max_shift_m: Option<f64>,
+    /// The new geometry, or the old geometry if it was removed
+    #[serde(skip)]
+    pub geometry: Vec<Vec<Vector2>>,
+}
+
+/// A feature with the distance along it to each vertex, worked out once so
+/// that finding where an SLK is does not measure the feature again
+struct MeasuredFeature<'a> {
+    feature: &'a Feature,
+    /// Metres from the start of the feature to each vertex
+    lengths: Vec<f64>,
+}
+
+impl<'a> MeasuredFeature<'a> {
+    fn new(feature: &'a Feature) -> Self {
+        Self { feature, lengths: cumulative_lengths(&feature.geometry) }
+    }
+
+    /// The SLK of each vertex, assuming SLK increases evenly along the feature
+    fn vertex_slks(&self) -> Vec<f32> {
+        let (start, end) = (self.feature.attributes.START_SLK, self.feature.attributes.END_SLK);
+        let total = self.lengths.last().copied().unwrap_or(0.0);
+        if total <= 0.0 {
+            return Vec::new();
+        }
+        self.lengths
+            .iter()
+            .map(|length| start + (end - start) * (length / total) as f32)
+            .collect()
+    }
+
+    fn point_at_slk(&self, slk: f32) -> Option<Vector2> {
+        let (start, end) = (self.feature.attributes.START_SLK, self.feature.attributes.END_SLK);
+        if end <= start || slk < start || slk > end {
+            return None;
+        }
+        point_at_fraction(&self.feature.geometry, &self.lengths, ((slk - start) / (end - start)) as f64)
+    }
+}
+
+type FeaturesByCwy<'a> = BTreeMap<Cwy, Vec<&'a MeasuredFeature<'a>>>;
+
+fn group_by_road<'a>(features: &'a [MeasuredFeature<'a>]) -> BTreeMap<&'a str, FeaturesByCwy<'a>> {
+    let mut roads: BTreeMap<&str, FeaturesByCwy> = BTreeMap::new();
+    for measured in features {
+        roads
+            .entry(measured.feature.attributes.ROAD.as_str())
+            .or_default()
+            .entry(measured.feature.attributes.CWY)
+            .or_default()
+            .push(measured);
+    }
+    roads
+}
+
+/// The lowest and highest SLK of some features
+fn extent(features: &[&MeasuredFeature]) -> Option<(f32, f32)> {
+    features.iter().fold(None, |result, measured| {
+        let (start, end) = (measured.feature.attributes.START_SLK, measured.feature.attributes.END_SLK);
+        Some(match result {
+            Some((from, to)) => (start.min(from), end.max(to)),
+            None => (start, end),
+        })
+    })
+}
+
+fn cumulative_lengths(geometry: &[Vector2]) -> Vec<f64> {
+    let mut total = 0.0;
+    let mut lengths = Vec::with_capacity(geometry.len());
+    lengths.push(0.0);
+    for segment in geometry.windows(2) {
+        total += distance_metres(&segment[0], &segment[1]);
+        lengths.push(total);
+    }
+    lengths
+}
+
+/// The point `fraction` of the way along `geometry`, measured by length.
+/// `lengths` are the [cumulative_lengths] of `geometry`.
+fn point_at_fraction(geometry: &[Vector2], lengths: &[f64], fraction: f64) -> Option<Vector2> {
+    let target = lengths.last()? * fraction.clamp(0.0, 1.0);
+    let index = lengths.partition_point(|length| *length < target).clamp(1, lengths.len().max(2) - 1);
+    let (a, b) = (geometry.get(index - 1)?, geometry.get(index)?);
+    let segment_length = lengths[index] - lengths[index - 1];
+    if segment_length <= 0.0 {
+        return Some(*a);
+    }
+    let t = (target - lengths[index - 1]) / segment_length;
+    Some(Vector2::new(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t))
+}
+
+/// The furthest distance in metres between where an SLK is in `measured` and
+/// where the same SLK was in `old_features`. SLKs are sampled at the vertices
+/// of both. `old_index` indexes `old_features` by SLK. Returns `None` if none
+/// of the SLKs existed before.
+fn max_shift_metres(measured: &MeasuredFeature, old_features: &[&MeasuredFeature], old_index: &IntervalIndex) -> Option<f64> {
+    let (start, end) = (measured.feature.attributes.START_SLK, measured.feature.attributes.END_SLK);
+    let overlapping: Vec<&MeasuredFeature> = old_index
+        .overlapping(start, end)
+        .into_iter()
+        .map(|index| old_features[index])
+        .collect();
+    let old_vertex_slks = overlapping
+        .iter()
+        .flat_map(|old| old.vertex_slks())
+        .filter(|slk| *slk >= start && *slk <= end);
+    measured.vertex_slks()
+        .into_iter()
+        .chain(old_vertex_slks)
+        .filter_map(|slk| {
+            let new_point = measured.point_at_slk(slk)?;
+            let old_point = overlapping.iter().find_map(|old| old.point_at_slk(slk))?;
+            Some(distance_metres(&new_point, &old_point))
+        })
+        .reduce(f64::max)
+}
+
+impl Change {
+    fn new(change: ChangeKind, road: &str, cwy: Option<Cwy>, old: &[&MeasuredFeature], new: &[&MeasuredFeature]) -> Self {
+        let old_extent = extent(old);
+        let new_extent = extent(new);
+        let geometry_source = if new.is_empty() { old } else { new };
+        Self {
+            change,
+            road: road.to_owned(),
+            cwy,
+            old_slk_from: old_extent.map(|extent| extent.0),
+            old_slk_to: old_extent.map(|extent| extent.1),
+            new_slk_from: new_extent.map(|extent| extent.0),
+            new_slk_to: new_extent.map(|extent| extent.1),
+            max_shift_m: None,
+            geometry: geometry_source.iter().map(|measured| measured.feature.geometry.clone()).collect(),
+        }
+    }
+}
+
+fn compare_cwy(road: &str, cwy: Cwy, old: &[&MeasuredFeature], new: &[&MeasuredFeature], tolerance_m: f64, changes: &mut Vec<Change>) {
+    if let (Some(old_extent), Some(new_extent)) = (extent(old), extent(new)) {
+        if (old_extent.0 - new_extent.0).abs() > SLK_TOLERANCE_KM
+            || (old_extent.1 - new_extent.1).abs() > SLK_TOLERANCE_KM
+        {
+            changes.push(Change::new(ChangeKind::extent_changed, road, Some(cwy), old, new));
+        }
+    }
+    let old_index = IntervalIndex::from_indexed(
+        old.iter().map(|measured| &measured.feature.attributes).enumerate(),
+        &Measure::slk,
+    );
+    for measured in new {
+        match max_shift_metres(measured, old, &old_index) {
+            Some(shift) if shift > tolerance_m => changes.push(Change {
+                max_shift_m: Some(shift),
+                ..Change::new(ChangeKind::geometry_shifted, road, Some(cwy), &[], &[measured])
+            }),
+            _ => (),
+        }
+    }
+}
+
+/// The differences between two versions of the road network
+pub struct NetworkDiff {
+    pub changes: Vec<Change>,
+}
+
+impl NetworkDiff {
+    /// Compare road by road and carriageway by carriageway. Geometry which
+    /// moved more than `tolerance_m` metres is reported as shifted.
+    pub fn compare<'a>(
+        old: impl IntoIterator<Item = &'a Feature>,
+        new: impl IntoIterator<Item = &'a Feature>,
+        tolerance_m: f64,
+    ) -> Self {
+        let old_measured: Vec<MeasuredFeature> = old.into_iter().map(MeasuredFeature::new).collect();
+        let new_measured: Vec<MeasuredFeature> = new.into_iter().map(MeasuredFeature::new).collect();
+        let old_roads = group_by_road(&old_measured);
+        let new_roads = group_by_road(&new_measured);
+        let mut changes = Vec::new();
+        let roads: BTreeSet<&str> = old_roads.keys().chain(new_roads.keys()).copied().collect();
+        for road in roads {
+            match (old_roads.get(road), new_roads.get(road)) {
+                (Some(old_cwys), None) => {
+                    let old: Vec<&MeasuredFeature> = old_cwys.values().flatten().copied().collect();
+                    changes.push(Change::new(ChangeKind::road_removed, road, None, &old, &[]));
+                },
+                (None, Some(new_cwys)) => {
+                    let new: Vec<&MeasuredFeature> = new_cwys.values().flatten().copied().collect();
+                    changes.push(Change::new(ChangeKind::road_added, road, None, &[], &new));
+                },
+                (Some(old_cwys), Some(new_cwys)) => {
+                    let cwys: BTreeSet<Cwy> = old_cwys.keys().chain(new_cwys.keys()).copied().collect();
+                    for cwy in cwys {
+                        match (old_cwys.get(&cwy), new_cwys.get(&cwy)) {
+                            (Some(old), None) => changes.push(Change::new(ChangeKind::cwy_removed, road, Some(cwy), old, &[])),
+                            (None, Some(new)) => changes.push(Change::new(ChangeKind::cwy_added, road, Some(cwy), &[], new)),
+                            (Some(old), Some(new)) => compare_cwy(road, cwy, old, new, tolerance_m, &mut changes),
+                            (None, None) => (),
+                        }
+                    }
+                },
+                (None, None) => (),
+            }
+        }
+        Self { changes }
+    }
+
+    pub fn to_geojson(&self) -> String {
+        let features: Vec<serde_json::Value> = self.changes
+            .iter()
+            .map(|change| json!({
+                "type": "Feature",
+                "properties": change,
+                "geometry": {
+                    "type": "MultiLineString",
+                    "coordinates": change.geometry
+                        .iter()
+                        .map(|line| line.iter().map(|vertex| (vertex.x, vertex.y)).collect::<Vec<_>>())
+                        .collect::<Vec<_>>(),
+                },
+            }))
+            .collect();
+        json!({"type": "FeatureCollection", "features": features}).to_string()
+    }
+
+    pub fn to_csv(&self) -> String {
+        fn optional<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map_or(String::new(), |value| value.to_string())
+        }
+        let mut csv = String::from("change,road,cwy,old_slk_from,old_slk_to,new_slk_from,new_slk_to,max_shift_m\n");
+        for change in self.changes.iter() {
+            let road = if change.road.contains([',', '"', '\n']) {
+                format!("\"{}\"", change.road.replace('"', "\"\""))
+            } else {
+                change.road.clone()
+            };
+            csv += &format!(
+                "{:?},{},{},{},{},{},{},{}\n",
+                change.change,
+                road,
+                change.cwy.map_or(String::new(), |cwy| format!("{:?}", cwy)),
+                optional(&change.old_slk_from),
+                optional(&change.old_slk_to),
+                optional(&change.new_slk_from),
+                optional(&change.new_slk_to),
+                change.max_shift_m.map_or(String::new(), |shift| format!("{:.2}", shift)),
+            );
+        }
+        csv
+    }
+
+    pub fn to_format(&self, format: &DiffFormat) -> String {
+        match format {
+            DiffFormat::geojson => self.to_geojson(),
+            DiffFormat::csv => self.to_csv(),
+        }
+    }
+}
+
+/// Compare two cache files. Used by the `diff` command.
+pub fn compare_files(old_path: &Path, new_path: &Path, tolerance_m: f64, format: &DiffFormat) -> Result<String, Box<dyn Error>> {
+    let old = Layer::load_data_from_file(old_path)?;
+    let new = Layer::load_data_from_file(new_path)?;
+    Ok(NetworkDiff::compare(&old.features, &new.features, tolerance_m).to_format(format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Attributes;
+
+    fn feature(road: &str, cwy: Cwy, slk: (f32, f32), geometry: &[(f64, f64)]) -> Feature {
+        Feature::new(Attributes::new(road, cwy, slk.0, slk.1), geometry)
+    }
+
+    fn kinds(diff: &NetworkDiff) -> Vec<(ChangeKind, &str, Option<Cwy>)> {
+        diff.changes.iter().map(|change| (change.change, change.road.as_str(), change.cwy)).collect()
+    }
+
+    #[test]
+    fn test_point_at_fraction() {
+        let line = [Vector2::new(115.0, -32.0), Vector2::new(115.01, -32.0), Vector2::new(115.03, -32.0)];
+        let lengths = cumulative_lengths(&line);
+        let middle = point_at_fraction(&line, &lengths, 0.5).unwrap();
+        assert!((middle.x - 115.015).abs() < 1e-9);
+        assert_eq!(point_at_fraction(&line, &lengths, 1.0).unwrap().x, 115.03);
+        assert_eq!(point_at_fraction(&line, &lengths, 0.0).unwrap().x, 115.0);
+        assert!(point_at_fraction(&[], &[], 0.5).is_none());
+    }
+
+    #[test]
+    fn test_network_diff() {
+        // 0.001 degrees of latitude is about 111m
+        let old = [
+            feature("H001", Cwy::Single, (0.0, 1.0), &[(115.0, -32.0), (115.01, -32.0)]),
+            feature("H002", Cwy::Left, (0.0, 1.0), &[(116.0, -32.0), (116.01, -32.0)]),
+            feature("H002", Cwy::Right, (0.0, 1.0), &[(116.0, -32.1), (116.01, -32.1)]),
+            feature("H003", Cwy::Single, (0.0, 1.0), &[(117.0, -32.0), (117.01, -32.0)]),
+        ];
+        let new = [
+            feature("H001", Cwy::Single, (0.0, 1.0), &[(115.0, -32.0), (115.005, -32.001), (115.01, -32.0)]),
+            feature("H002", Cwy::Left, (0.0, 1.0), &[(116.0, -32.0), (116.01, -32.0)]),
+            feature("H003", Cwy::Single, (0.0, 1.2), &[(117.0, -32.0), (117.012, -32.0)]),
+            feature("H004", Cwy::Single, (0.0, 1.0), &[(118.0, -32.0), (118.01, -32.0)]),
+        ];
+        let diff = NetworkDiff::compare(&old, &new, 5.0);
+        assert_eq!(kinds(&diff), vec![
+            (ChangeKind::geometry_shifted, "H001", Some(Cwy::Single)),
+            (ChangeKind::cwy_removed, "H002", Some(Cwy::Right)),
+            (ChangeKind::extent_changed, "H003", Some(Cwy::Single)),
+            (ChangeKind::road_added, "H004", None),
+        ]);
+        let shift = diff.changes[0].max_shift_m.unwrap();
+        assert!((shift - 111.0).abs() < 1.0, "shift was {}", shift);
+        assert_eq!(diff.changes[2].old_slk_to, Some(1.0));
+        assert_eq!(diff.changes[2].new_slk_to, Some(1.2));
+
+        // A large tolerance ignores the shift
+        let diff = NetworkDiff::compare(&old, &new, 500.0);
+        assert_eq!(diff.changes.len(), 3);
+
+        let diff = NetworkDiff::compare(&new, &old, 500.0);
+        assert_eq!(diff.changes.last().map(|change| change.change), Some(ChangeKind::road_removed));
+    }
+
+    #[test]
+    fn test_network_diff_output() {
+        let old = [feature("H001", Cwy::Single, (0.0, 1.0), &[(115.0, -32.0), (115.01, -32.0)])];
+        let diff = NetworkDiff::compare(&old, &[], 5.0);
+        assert_eq!(
+            diff.to_csv(),
+            "change,road,cwy,old_slk_from,old_slk_to,new_slk_from,new_slk_to,max_shift_m\nroad_removed,H001,,0,1,,,\n"
+        );
+        let geojson: serde_json::Value = serde_json::from_str(&diff.to_geojson()).unwrap();
+        assert_eq!(geojson["features"][0]["properties"]["change"], "road_removed");
+        assert_eq!(geojson["features"][0]["geometry"]["coordinates"][0][1], json!([115.01, -32.0]));
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock, Weak},
+    time::SystemTime,
+};
+
+use crate::helpers::{CalendarDate, ErrorWithStaticMessage};
+
+use super::{
+    cached::{CacheFormat, Layer},
+    IndexedData,
+};
+
+/// Snapshots are always saved in the binary format because it loads fastest
+const SNAPSHOT_EXTENSION: &str = ".bin";
+
+/// The number of recently used snapshots kept in memory after the queries
+/// using them have finished. Each costs about as much memory as the current
+/// data.
+const RECENTLY_USED_SNAPSHOTS: usize = 2;
+
+fn snapshot_file_name(date: &CalendarDate) -> String {
+    format!("{}{}", date, SNAPSHOT_EXTENSION)
+}
+
+/// Returns the date of a snapshot file named like `2024-06-13.bin`
+fn parse_snapshot_file_name(file_name: &str) -> Option<CalendarDate> {
+    file_name.strip_suffix(SNAPSHOT_EXTENSION)?.parse().ok()
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Save a copy of freshly downloaded data into the snapshot directory, named
+/// by the day it was downloaded. A snapshot from earlier the same day is
+/// replaced.
+pub fn save_snapshot(directory: &Path, layer: &Layer) -> Result<PathBuf, Box<dyn Error>> {
+    let date = layer
+        .downloaded
+        .ok_or(ErrorWithStaticMessage::new("The download date of the data is not known"))?;
+    fs::create_dir_all(directory)?;
+    let path = directory.join(snapshot_file_name(&date));
+    Layer::save_data_to_file(&path, layer, &CacheFormat::binary)?;
+    Ok(path)
+}
+
+struct Snapshot {
+    date: CalendarDate,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// Set while any query or [Snapshots::recently_used] still holds the
+    /// loaded data, so that it is only read once
+    loaded: Mutex<Weak<IndexedData>>,
+}
+
+impl Snapshot {
+    /// Read and index the snapshot unless it is still in memory. This blocks
+    /// while the file is read.
+    fn load(&self) -> Result<Arc<IndexedData>, ErrorWithStaticMessage> {
+        let mut loaded = self
+            .loaded
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(indexed_data) = loaded.upgrade() {
+            return Ok(indexed_data);
+        }
+        println!("INFO: Loading snapshot '{}'", self.path.display());
+        let indexed_data = Layer::open_file(&self.path)
+            .and_then(IndexedData::from_cached_layer)
+            .map_err(|error_message| {
+                println!("WARNING: Could not load the snapshot '{}' because '{}'", self.path.display(), error_message);
+                ErrorWithStaticMessage::new("Unable to load the data for the requested as_of date")
+            })?;
+        let indexed_data = Arc::new(indexed_data);
+        *loaded = Arc::downgrade(&indexed_data);
+        Ok(indexed_data)
+    }
+}
+
+/// Dated copies of previously downloaded data kept in the snapshot
+/// directory. They let queries be answered using the network as it was on an
+/// earlier date, before SLKs were re-baselined.
+///
+/// Snapshots are only read from disk when a query asks for them, and only the
+/// [RECENTLY_USED_SNAPSHOTS] most recently used are kept in memory
+/// afterwards.
+pub struct Snapshots {
+    directory: Option<PathBuf>,
+    /// Sorted from oldest to newest
+    snapshots: RwLock<Vec<Arc<Snapshot>>>,
+    /// Most recently used first
+    recently_used: Mutex<VecDeque<Arc<IndexedData>>>,
+}
+
+impl Snapshots {
+    /// Find the snapshots in `directory`. If `directory` is `None` there are
+    /// no snapshots.
+    pub fn open(directory: &Option<String>) -> Self {
+        let snapshots = Self {
+            directory: directory.as_ref().map(PathBuf::from),
+            snapshots: RwLock::new(Vec::new()),
+            recently_used: Mutex::new(VecDeque::new()),
+        };
+        snapshots.rescan();
+        snapshots
+    }
+
+    /// Look for snapshots which were added or removed since the directory was
+    /// last scanned. Snapshots which are already loaded are reused unless
+    /// their file has been replaced.
+    pub fn rescan(&self) {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return,
+        };
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(error_message) => {
+                println!("WARNING: Could not read the snapshot directory '{}' because '{}'", directory.display(), error_message);
+                return;
+            }
+        };
+        let mut found: Vec<(CalendarDate, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let date = parse_snapshot_file_name(entry.file_name().to_str()?)?;
+                Some((date, entry.path()))
+            })
+            .collect();
+        found.sort_by_key(|(date, _)| *date);
+
+        let mut snapshots = self
+            .snapshots
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let previous = std::mem::take(&mut *snapshots);
+        *snapshots = found
+            .into_iter()
+            .map(|(date, path)| {
+                let modified = modified_time(&path);
+                previous
+                    .iter()
+                    .find(|snapshot| snapshot.date == date && snapshot.modified == modified)
+                    .cloned()
+                    .unwrap_or_else(|| Arc::new(Snapshot {
+                        date,
+                        path,
+                        modified,
+                        loaded: Mutex::new(Weak::new()),
+                    }))
+            })
+            .collect();
+        println!("INFO: Found {} snapshots in '{}'", snapshots.len(), directory.display());
+    }
+
+    /// The newest snapshot taken on or before `as_of`
+    fn find_snapshot(&self, as_of: &CalendarDate) -> Option<Arc<Snapshot>> {
+        self.snapshots
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.date <= *as_of)
+            .cloned()
+    }
+
+    /// Move `indexed_data` to the front of [Snapshots::recently_used],
+    /// dropping the least recently used snapshot if there are too many
+    fn mark_used(&self, indexed_data: &Arc<IndexedData>) {
+        let mut recently_used = self
+            .recently_used
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        recently_used.retain(|used| !Arc::ptr_eq(used, indexed_data));
+        recently_used.push_front(indexed_data.clone());
+        recently_used.truncate(RECENTLY_USED_SNAPSHOTS);
+    }
+
+    /// Get the newest snapshot taken on or before `as_of`, loading it if it
+    /// is not in memory. `None` if there is no such snapshot.
+    pub async fn load_as_of(&self, as_of: &CalendarDate) -> Result<Option<Arc<IndexedData>>, ErrorWithStaticMessage> {
+        let snapshot = match self.find_snapshot(as_of) {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+        // Skip the blocking thread if the snapshot is already in memory
+        let in_memory = snapshot.loaded.try_lock().ok().and_then(|loaded| loaded.upgrade());
+        let indexed_data = match in_memory {
+            Some(indexed_data) => indexed_data,
+            None => tokio::task::spawn_blocking(move || snapshot.load())
+                .await
+                .unwrap_or_else(|_| Err(ErrorWithStaticMessage::new("Unable to load the data for the requested as_of date")))?,
+        };
+        self.mark_used(&indexed_data);
+        Ok(Some(indexed_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::{Attributes, Cwy, Feature};
+
+    #[test]
+    fn test_parse_snapshot_file_name() {
+        assert_eq!(parse_snapshot_file_name("2024-06-13.bin"), CalendarDate::new(2024, 6, 13));
+        assert_eq!(parse_snapshot_file_name(&snapshot_file_name(&CalendarDate::new(2020, 1, 2).unwrap())), CalendarDate::new(2020, 1, 2));
+        assert_eq!(parse_snapshot_file_name("2024-06-13.json.lz4"), None);
+        assert_eq!(parse_snapshot_file_name("data.bin"), None);
+    }
+
+    #[test]
+    fn test_snapshots_find() {
+        let directory = std::env::temp_dir().join(format!("nicklinref_test_snapshots_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for file_name in ["2020-01-01.bin", "2022-07-01.bin", "notes.txt"] {
+            fs::write(directory.join(file_name), b"").unwrap();
+        }
+        let snapshots = Snapshots::open(&Some(directory.to_string_lossy().into_owned()));
+        let date = |text: &str| text.parse::<CalendarDate>().unwrap();
+        let find = |snapshots: &Snapshots, as_of: &str| snapshots.find_snapshot(&date(as_of)).map(|snapshot| snapshot.date);
+        assert_eq!(find(&snapshots, "2019-12-31"), None);
+        assert_eq!(find(&snapshots, "2020-01-01"), Some(date("2020-01-01")));
+        assert_eq!(find(&snapshots, "2022-06-30"), Some(date("2020-01-01")));
+        assert_eq!(find(&snapshots, "2030-01-01"), Some(date("2022-07-01")));
+
+        fs::write(directory.join("2025-03-04.bin"), b"").unwrap();
+        snapshots.rescan();
+        assert_eq!(find(&snapshots, "2030-01-01"), Some(date("2025-03-04")));
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(find(&Snapshots::open(&None), "2030-01-01"), None);
+    }
+
+    #[test]
+    fn test_snapshots_recently_used() {
+        let snapshots = Snapshots::open(&None);
+        let indexed_data = || Arc::new(IndexedData::from_layer(Layer {
+            features: vec![Feature::new(Attributes::new("H001", Cwy::Single, 0.0, 1.0), &[(115.0, -32.0), (115.01, -32.0)])],
+            source_metadata: None,
+            downloaded: None,
+            spatial_reference: None,
+            cache_metadata: None,
+        }).unwrap());
+        let (a, b, c) = (indexed_data(), indexed_data(), indexed_data());
+        snapshots.mark_used(&a);
+        snapshots.mark_used(&b);
+        snapshots.mark_used(&a);
+        snapshots.mark_used(&c);
+        let recently_used = snapshots.recently_used.lock().unwrap();
+        assert_eq!(recently_used.len(), RECENTLY_USED_SNAPSHOTS);
+        assert!(Arc::ptr_eq(&recently_used[0], &c));
+        assert!(Arc::ptr_eq(&recently_used[1], &a));
+        drop(recently_used);
+        // nothing else holds `b` so it is freed
+        let weak_b = Arc::downgrade(&b);
+        drop(b);
+        assert!(weak_b.upgrade().is_none());
+    }
+}
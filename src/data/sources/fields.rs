@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::super::{
+    cached::{Attributes, Cwy},
+    FieldMapping,
+};
+
+/// A single attribute value read from a local data source
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Text(String),
+    Number(f64),
+    Null,
+}
+
+impl From<&serde_json::Value> for FieldValue {
+    fn from(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(text) => Self::Text(text.clone()),
+            serde_json::Value::Number(number) => number.as_f64().map_or(Self::Null, Self::Number),
+            _ => Self::Null,
+        }
+    }
+}
+
+/// The attributes of one feature, keyed by field name
+pub type Fields = HashMap<String, FieldValue>;
+
+/// Field names are matched without regard to case, since some tools
+/// lowercase column names on export
+fn get_field<'a>(fields: &'a Fields, name: &str) -> Option<&'a FieldValue> {
+    fields.get(name).or_else(|| {
+        fields
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    })
+}
+
+fn parse_cwy(value: &FieldValue, field_mapping: &FieldMapping) -> Result<Cwy, ErrorWithStaticMessage> {
+    let text = match value {
+        FieldValue::Text(text) => text.clone(),
+        // Some layers use numeric carriageway codes
+        FieldValue::Number(number) => number.to_string(),
+        FieldValue::Null => return Err(ErrorWithStaticMessage::new("The carriageway field must not be null")),
+    };
+    field_mapping
+        .cwy_from_value(&text)
+        .ok_or(ErrorWithStaticMessage::new("The carriageway field has a value which is not listed in the carriageway value mapping settings"))
+}
+
+fn parse_slk(value: &FieldValue) -> Result<f32, ErrorWithStaticMessage> {
+    let slk = match value {
+        FieldValue::Number(number) => *number as f32,
+        FieldValue::Text(text) => text
+            .trim()
+            .parse::<f32>()
+            .map_err(|_| ErrorWithStaticMessage::new("The start and end SLK fields must be numbers"))?,
+        FieldValue::Null => return Err(ErrorWithStaticMessage::new("The start and end SLK fields must not be null")),
+    };
+    if slk.is_finite() {
+        Ok(slk)
+    } else {
+        Err(ErrorWithStaticMessage::new("The start and end SLK fields must be finite"))
+    }
+}
+
+/// Read an optional text field. Missing, null and empty values are all
+/// treated as `None`, as is a field name set to an empty string.
+fn parse_optional_text(fields: &Fields, name: &str) -> Option<String> {
+    if name.is_empty() {
+        return None;
+    }
+    match get_field(fields, name)? {
+        FieldValue::Text(text) if !text.trim().is_empty() => Some(text.trim().to_owned()),
+        // Some layers store codes such as the region number as numbers
+        FieldValue::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+/// Read an optional number field. Like [parse_optional_text], anything which
+/// is not a finite number is treated as `None`.
+fn parse_optional_number(fields: &Fields, name: &str) -> Option<f32> {
+    if name.is_empty() {
+        return None;
+    }
+    get_field(fields, name).and_then(|value| parse_slk(value).ok())
+}
+
+/// Build [Attributes] from the fields of a feature, using the field names and
+/// carriageway values given by the [FieldMapping]
+pub fn attributes_from_fields(fields: &Fields, field_mapping: &FieldMapping) -> Result<Attributes, ErrorWithStaticMessage> {
+    let road = match get_field(fields, &field_mapping.NLR_FIELD_ROAD) {
+        Some(FieldValue::Text(road)) if !road.is_empty() => road.clone(),
+        Some(_) => return Err(ErrorWithStaticMessage::new("The road number field must be non-empty text")),
+        None => return Err(ErrorWithStaticMessage::new("The road number field is missing. Check the field mapping settings")),
+    };
+    let cwy = parse_cwy(
+        get_field(fields, &field_mapping.NLR_FIELD_CWY)
+            .ok_or(ErrorWithStaticMessage::new("The carriageway field is missing. Check the field mapping settings"))?,
+        field_mapping,
+    )?;
+    let start_slk = parse_slk(
+        get_field(fields, &field_mapping.NLR_FIELD_START_SLK)
+            .ok_or(ErrorWithStaticMessage::new("The start SLK field is missing. Check the field mapping settings"))?,
+    )?;
+    let end_slk = parse_slk(
+        get_field(fields, &field_mapping.NLR_FIELD_END_SLK)
+            .ok_or(ErrorWithStaticMessage::new("The end SLK field is missing. Check the field mapping settings"))?,
+    )?;
+    Ok(Attributes {
+        ROAD: road,
+        CWY: cwy,
+        START_SLK: start_slk,
+        END_SLK: end_slk,
+        NETWORK_TYPE: parse_optional_text(fields, &field_mapping.NLR_FIELD_NETWORK_TYPE),
+        ROAD_NAME: parse_optional_text(fields, &field_mapping.NLR_FIELD_ROAD_NAME),
+        LG_NAME: parse_optional_text(fields, &field_mapping.NLR_FIELD_LG_NAME),
+        RA_NO: parse_optional_text(fields, &field_mapping.NLR_FIELD_RA_NO),
+        START_TRUE_DIST: parse_optional_number(fields, &field_mapping.NLR_FIELD_START_TRUE_DIST),
+        END_TRUE_DIST: parse_optional_number(fields, &field_mapping.NLR_FIELD_END_TRUE_DIST),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(values: Vec<(&str, FieldValue)>) -> Fields {
+        values.into_iter().map(|(key, value)| (key.to_owned(), value)).collect()
+    }
+
+    #[test]
+    fn test_attributes_from_fields() {
+        let attributes = attributes_from_fields(&fields(vec![
+            ("road", FieldValue::Text("H001".to_owned())),
+            ("Cwy", FieldValue::Text("L".to_owned())),
+            ("START_SLK", FieldValue::Number(1.5)),
+            ("END_SLK", FieldValue::Text("2.25".to_owned())),
+            ("NETWORK_TYPE", FieldValue::Text("State Road".to_owned())),
+            ("ROAD_NAME", FieldValue::Text(" Albany Highway ".to_owned())),
+            ("LG_NAME", FieldValue::Null),
+            ("RA_NO", FieldValue::Number(7.0)),
+            ("START_TRUE_DIST", FieldValue::Number(1.25)),
+            ("END_TRUE_DIST", FieldValue::Null),
+            ("OTHER", FieldValue::Null),
+        ]), &FieldMapping::default()).unwrap();
+        assert_eq!(attributes, Attributes {
+            ROAD: "H001".to_owned(),
+            CWY: Cwy::Left,
+            START_SLK: 1.5,
+            END_SLK: 2.25,
+            NETWORK_TYPE: Some("State Road".to_owned()),
+            ROAD_NAME: Some("Albany Highway".to_owned()),
+            LG_NAME: None,
+            RA_NO: Some("7".to_owned()),
+            START_TRUE_DIST: Some(1.25),
+            END_TRUE_DIST: None,
+        });
+    }
+
+    #[test]
+    fn test_attributes_from_fields_invalid() {
+        let missing_road = fields(vec![
+            ("CWY", FieldValue::Text("Single".to_owned())),
+            ("START_SLK", FieldValue::Number(0.0)),
+            ("END_SLK", FieldValue::Number(1.0)),
+        ]);
+        assert!(attributes_from_fields(&missing_road, &FieldMapping::default()).is_err());
+
+        let bad_cwy = fields(vec![
+            ("ROAD", FieldValue::Text("H001".to_owned())),
+            ("CWY", FieldValue::Text("Middle".to_owned())),
+            ("START_SLK", FieldValue::Number(0.0)),
+            ("END_SLK", FieldValue::Number(1.0)),
+        ]);
+        assert!(attributes_from_fields(&bad_cwy, &FieldMapping::default()).is_err());
+
+        let null_slk = fields(vec![
+            ("ROAD", FieldValue::Text("H001".to_owned())),
+            ("CWY", FieldValue::Text("Single".to_owned())),
+            ("START_SLK", FieldValue::Null),
+            ("END_SLK", FieldValue::Number(1.0)),
+        ]);
+        assert!(attributes_from_fields(&null_slk, &FieldMapping::default()).is_err());
+    }
+
+    #[test]
+    /// A layer published by a local government with its own field names and
+    /// numeric carriageway codes
+    fn test_attributes_from_fields_custom_mapping() {
+        let field_mapping = FieldMapping {
+            NLR_FIELD_ROAD: "road_id".to_owned(),
+            NLR_FIELD_CWY: "carriageway".to_owned(),
+            NLR_FIELD_START_SLK: "from_km".to_owned(),
+            NLR_FIELD_END_SLK: "to_km".to_owned(),
+            NLR_FIELD_NETWORK_TYPE: "".to_owned(),
+            NLR_FIELD_ROAD_NAME: "street_name".to_owned(),
+            NLR_FIELD_LG_NAME: "LG_NAME".to_owned(),
+            NLR_FIELD_RA_NO: "RA_NO".to_owned(),
+            NLR_FIELD_START_TRUE_DIST: "from_true_km".to_owned(),
+            NLR_FIELD_END_TRUE_DIST: "to_true_km".to_owned(),
+            NLR_CWY_LEFT: vec!["1".to_owned()],
+            NLR_CWY_RIGHT: vec!["2".to_owned()],
+            NLR_CWY_SINGLE: vec!["0".to_owned()],
+            NLR_SOURCE_WHERE: None,
+        };
+        let attributes = attributes_from_fields(&fields(vec![
+            ("ROAD_ID", FieldValue::Text("4010123".to_owned())),
+            ("carriageway", FieldValue::Number(2.0)),
+            ("from_km", FieldValue::Number(0.25)),
+            ("to_km", FieldValue::Number(0.5)),
+            ("NETWORK_TYPE", FieldValue::Text("Local Road".to_owned())),
+            ("street_name", FieldValue::Text("Hay Street".to_owned())),
+            ("from_true_km", FieldValue::Text("0.3".to_owned())),
+            ("to_true_km", FieldValue::Number(0.55)),
+        ]), &field_mapping).unwrap();
+        assert_eq!(attributes, Attributes {
+            ROAD: "4010123".to_owned(),
+            CWY: Cwy::Right,
+            START_SLK: 0.25,
+            END_SLK: 0.5,
+            // ignored because the field name is set to an empty string
+            NETWORK_TYPE: None,
+            ROAD_NAME: Some("Hay Street".to_owned()),
+            LG_NAME: None,
+            RA_NO: None,
+            START_TRUE_DIST: Some(0.3),
+            END_TRUE_DIST: Some(0.55),
+        });
+        // The default field names are no longer used
+        let default_names = fields(vec![
+            ("ROAD", FieldValue::Text("H001".to_owned())),
+            ("CWY", FieldValue::Number(2.0)),
+            ("START_SLK", FieldValue::Number(0.0)),
+            ("END_SLK", FieldValue::Number(1.0)),
+        ]);
+        assert!(attributes_from_fields(&default_names, &field_mapping).is_err());
+    }
+}
@@ -0,0 +1,507 @@
+//! Reads line features from a FlatGeobuf file.
+//!
+//! FlatGeobuf is a sequence of size prefixed flatbuffers; a header, an optional
+//! spatial index which is skipped, then one flatbuffer per feature. Only the
+//! small part of the flatbuffer format needed to read the header and features
+//! is implemented here.
+//!
+//! See reference
+//! <https://github.com/flatgeobuf/flatgeobuf/tree/master/src/fbs>
+
+use std::{error::Error, fs, path::Path};
+
+use nickslinetoolsrust::vector2::Vector2;
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::{
+    super::{cached::Feature, FieldMapping},
+    fields::{Fields, FieldValue},
+    build_feature,
+    FeatureCollector,
+};
+
+const MAGIC: &[u8; 8] = b"fgb\x03fgb\x00";
+
+// GeometryType
+const GEOMETRY_UNKNOWN: u8 = 0;
+const GEOMETRY_LINESTRING: u8 = 2;
+const GEOMETRY_MULTILINESTRING: u8 = 5;
+
+// Field indexes of the Header table
+const HEADER_GEOMETRY_TYPE: usize = 2;
+const HEADER_COLUMNS: usize = 7;
+const HEADER_FEATURES_COUNT: usize = 8;
+const HEADER_INDEX_NODE_SIZE: usize = 9;
+const HEADER_CRS: usize = 10;
+
+// Field indexes of the Column table
+const COLUMN_NAME: usize = 0;
+const COLUMN_TYPE: usize = 1;
+
+// Field indexes of the Crs table
+const CRS_CODE: usize = 1;
+
+// Field indexes of the Feature table
+const FEATURE_GEOMETRY: usize = 0;
+const FEATURE_PROPERTIES: usize = 1;
+
+// Field indexes of the Geometry table
+const GEOMETRY_ENDS: usize = 0;
+const GEOMETRY_XY: usize = 1;
+const GEOMETRY_TYPE: usize = 6;
+
+fn corrupt() -> ErrorWithStaticMessage {
+    ErrorWithStaticMessage::new("FlatGeobuf file is corrupt")
+}
+
+fn read_array<const N: usize>(bytes: &[u8], position: usize) -> Result<[u8; N], ErrorWithStaticMessage> {
+    bytes
+        .get(position..position.checked_add(N).ok_or_else(corrupt)?)
+        .ok_or_else(corrupt)
+        .map(|slice| slice.try_into().unwrap())
+}
+
+fn read_u16(bytes: &[u8], position: usize) -> Result<u16, ErrorWithStaticMessage> {
+    read_array(bytes, position).map(u16::from_le_bytes)
+}
+
+fn read_u32(bytes: &[u8], position: usize) -> Result<u32, ErrorWithStaticMessage> {
+    read_array(bytes, position).map(u32::from_le_bytes)
+}
+
+/// A table inside a flatbuffer
+#[derive(Clone, Copy)]
+struct Table<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Table<'a> {
+    /// The root table of a flatbuffer
+    fn root(bytes: &'a [u8]) -> Result<Self, ErrorWithStaticMessage> {
+        Ok(Self { bytes, position: read_u32(bytes, 0)? as usize })
+    }
+
+    /// Absolute position of a field, or `None` if the field is not present
+    fn field_position(&self, field_index: usize) -> Result<Option<usize>, ErrorWithStaticMessage> {
+        let vtable_offset = i32::from_le_bytes(read_array(self.bytes, self.position)?) as i64;
+        let vtable = usize::try_from(self.position as i64 - vtable_offset).map_err(|_| corrupt())?;
+        let vtable_length = read_u16(self.bytes, vtable)? as usize;
+        let entry = 4 + 2 * field_index;
+        if entry + 2 > vtable_length {
+            return Ok(None);
+        }
+        match read_u16(self.bytes, vtable + entry)? {
+            0 => Ok(None),
+            field_offset => Ok(Some(self.position + field_offset as usize)),
+        }
+    }
+
+    fn get_u8(&self, field_index: usize, default: u8) -> Result<u8, ErrorWithStaticMessage> {
+        match self.field_position(field_index)? {
+            Some(position) => Ok(read_array::<1>(self.bytes, position)?[0]),
+            None => Ok(default),
+        }
+    }
+
+    fn get_u16(&self, field_index: usize, default: u16) -> Result<u16, ErrorWithStaticMessage> {
+        match self.field_position(field_index)? {
+            Some(position) => read_u16(self.bytes, position),
+            None => Ok(default),
+        }
+    }
+
+    fn get_i32(&self, field_index: usize, default: i32) -> Result<i32, ErrorWithStaticMessage> {
+        match self.field_position(field_index)? {
+            Some(position) => read_array(self.bytes, position).map(i32::from_le_bytes),
+            None => Ok(default),
+        }
+    }
+
+    fn get_u64(&self, field_index: usize, default: u64) -> Result<u64, ErrorWithStaticMessage> {
+        match self.field_position(field_index)? {
+            Some(position) => read_array(self.bytes, position).map(u64::from_le_bytes),
+            None => Ok(default),
+        }
+    }
+
+    /// Follow the offset stored in a string, vector or table field
+    fn get_target(&self, field_index: usize) -> Result<Option<usize>, ErrorWithStaticMessage> {
+        match self.field_position(field_index)? {
+            Some(position) => Ok(Some(position + read_u32(self.bytes, position)? as usize)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_table(&self, field_index: usize) -> Result<Option<Table<'a>>, ErrorWithStaticMessage> {
+        Ok(self.get_target(field_index)?.map(|position| Table { bytes: self.bytes, position }))
+    }
+
+    /// Returns the position of the first element and the number of elements
+    fn get_vector(&self, field_index: usize) -> Result<Option<(usize, usize)>, ErrorWithStaticMessage> {
+        match self.get_target(field_index)? {
+            Some(position) => Ok(Some((position + 4, read_u32(self.bytes, position)? as usize))),
+            None => Ok(None),
+        }
+    }
+
+    fn get_bytes(&self, field_index: usize) -> Result<&'a [u8], ErrorWithStaticMessage> {
+        match self.get_vector(field_index)? {
+            Some((start, length)) => self.bytes.get(start..start + length).ok_or_else(corrupt),
+            None => Ok(&[]),
+        }
+    }
+
+    fn get_string(&self, field_index: usize) -> Result<&'a str, ErrorWithStaticMessage> {
+        std::str::from_utf8(self.get_bytes(field_index)?).map_err(|_| corrupt())
+    }
+
+    fn get_tables(&self, field_index: usize) -> Result<Vec<Table<'a>>, ErrorWithStaticMessage> {
+        match self.get_vector(field_index)? {
+            Some((start, length)) => (0..length)
+                .map(|index| {
+                    let element = start + index * 4;
+                    Ok(Table { bytes: self.bytes, position: element + read_u32(self.bytes, element)? as usize })
+                })
+                .collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn get_f64s(&self, field_index: usize) -> Result<Vec<f64>, ErrorWithStaticMessage> {
+        match self.get_vector(field_index)? {
+            Some((start, length)) => (0..length)
+                .map(|index| read_array(self.bytes, start + index * 8).map(f64::from_le_bytes))
+                .collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn get_u32s(&self, field_index: usize) -> Result<Vec<u32>, ErrorWithStaticMessage> {
+        match self.get_vector(field_index)? {
+            Some((start, length)) => (0..length)
+                .map(|index| read_u32(self.bytes, start + index * 4))
+                .collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Number of bytes used by the packed hilbert r-tree which follows the header.
+/// Fails if a corrupt feature count makes the size overflow.
+fn packed_rtree_size(feature_count: u64, node_size: u16) -> Result<u64, ErrorWithStaticMessage> {
+    if node_size == 0 || feature_count == 0 {
+        return Ok(0);
+    }
+    let node_size = node_size.max(2) as u64;
+    let mut level_count = feature_count;
+    let mut node_count = level_count;
+    loop {
+        level_count = level_count.div_ceil(node_size);
+        node_count = node_count.checked_add(level_count).ok_or_else(corrupt)?;
+        if level_count == 1 {
+            break;
+        }
+    }
+    // each node is a bounding box of four f64 and a u64 offset
+    node_count.checked_mul(40).ok_or_else(corrupt)
+}
+
+struct Column {
+    name: String,
+    column_type: u8,
+}
+
+/// Decode the properties of a feature. Each property is stored as a u16
+/// column index followed by the value.
+fn parse_properties(bytes: &[u8], columns: &[Column]) -> Result<Fields, ErrorWithStaticMessage> {
+    let mut fields = Fields::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let column = columns.get(read_u16(bytes, position)? as usize).ok_or_else(corrupt)?;
+        position += 2;
+        let (value, length) = match column.column_type {
+            // Byte, UByte, Bool
+            0 => (FieldValue::Number(read_array::<1>(bytes, position)?[0] as i8 as f64), 1),
+            1 | 2 => (FieldValue::Number(read_array::<1>(bytes, position)?[0] as f64), 1),
+            // Short, UShort
+            3 => (FieldValue::Number(i16::from_le_bytes(read_array(bytes, position)?) as f64), 2),
+            4 => (FieldValue::Number(u16::from_le_bytes(read_array(bytes, position)?) as f64), 2),
+            // Int, UInt
+            5 => (FieldValue::Number(i32::from_le_bytes(read_array(bytes, position)?) as f64), 4),
+            6 => (FieldValue::Number(u32::from_le_bytes(read_array(bytes, position)?) as f64), 4),
+            // Long, ULong
+            7 => (FieldValue::Number(i64::from_le_bytes(read_array(bytes, position)?) as f64), 8),
+            8 => (FieldValue::Number(u64::from_le_bytes(read_array(bytes, position)?) as f64), 8),
+            // Float, Double
+            9 => (FieldValue::Number(f32::from_le_bytes(read_array(bytes, position)?) as f64), 4),
+            10 => (FieldValue::Number(f64::from_le_bytes(read_array(bytes, position)?)), 8),
+            // String, Json, DateTime
+            11..=13 => {
+                let length = read_u32(bytes, position)? as usize;
+                let text = bytes.get(position + 4..position + 4 + length).ok_or_else(corrupt)?;
+                (FieldValue::Text(String::from_utf8_lossy(text).into_owned()), 4 + length)
+            }
+            // Binary
+            14 => (FieldValue::Null, 4 + read_u32(bytes, position)? as usize),
+            _ => return Err(ErrorWithStaticMessage::new("FlatGeobuf file has a column of an unknown type")),
+        };
+        fields.insert(column.name.clone(), value);
+        position += length;
+    }
+    Ok(fields)
+}
+
+/// Read the parts of a LineString or MultiLineString geometry. Both store
+/// all vertices in `xy`; a MultiLineString uses `ends` to mark where each
+/// part finishes.
+fn parse_geometry(geometry: &Table, header_geometry_type: u8) -> Result<Vec<Vec<Vector2>>, ErrorWithStaticMessage> {
+    let geometry_type = match geometry.get_u8(GEOMETRY_TYPE, GEOMETRY_UNKNOWN)? {
+        GEOMETRY_UNKNOWN => header_geometry_type,
+        geometry_type => geometry_type,
+    };
+    if geometry_type != GEOMETRY_LINESTRING && geometry_type != GEOMETRY_MULTILINESTRING {
+        return Err(ErrorWithStaticMessage::new("Geometry must be a LineString or MultiLineString"));
+    }
+    let vertexes: Vec<Vector2> = geometry
+        .get_f64s(GEOMETRY_XY)?
+        .chunks_exact(2)
+        .map(|pair| Vector2::new(pair[0], pair[1]))
+        .collect();
+    let ends = geometry.get_u32s(GEOMETRY_ENDS)?;
+    if geometry_type == GEOMETRY_LINESTRING || ends.is_empty() {
+        return Ok(vec![vertexes]);
+    }
+    let mut start = 0;
+    ends.iter()
+        .map(|&end| {
+            let part = vertexes.get(start..end as usize).ok_or_else(corrupt)?.to_vec();
+            start = end as usize;
+            Ok(part)
+        })
+        .collect()
+}
+
+fn parse_feature(
+    bytes: &[u8],
+    columns: &[Column],
+    header_geometry_type: u8,
+    field_mapping: &FieldMapping,
+) -> Result<Feature, ErrorWithStaticMessage> {
+    let feature = Table::root(bytes)?;
+    let fields = parse_properties(feature.get_bytes(FEATURE_PROPERTIES)?, columns)?;
+    let parts = match feature.get_table(FEATURE_GEOMETRY)? {
+        Some(geometry) => parse_geometry(&geometry, header_geometry_type),
+        None => Err(ErrorWithStaticMessage::new("Feature has no geometry")),
+    };
+    build_feature(&fields, parts, field_mapping)
+}
+
+fn parse_file(bytes: &[u8], field_mapping: &FieldMapping) -> Result<Vec<Feature>, ErrorWithStaticMessage> {
+    if bytes.len() < 12 || bytes[0..3] != MAGIC[0..3] || bytes[4..7] != MAGIC[4..7] {
+        return Err(ErrorWithStaticMessage::new("File is not a FlatGeobuf file"));
+    }
+    if bytes[3] != MAGIC[3] {
+        return Err(ErrorWithStaticMessage::new("FlatGeobuf file version is not supported"));
+    }
+    let header_length = read_u32(bytes, 8)? as usize;
+    let header_bytes = bytes.get(12..12 + header_length).ok_or_else(corrupt)?;
+    let header = Table::root(header_bytes)?;
+
+    let header_geometry_type = header.get_u8(HEADER_GEOMETRY_TYPE, GEOMETRY_UNKNOWN)?;
+    if !matches!(header_geometry_type, GEOMETRY_UNKNOWN | GEOMETRY_LINESTRING | GEOMETRY_MULTILINESTRING) {
+        return Err(ErrorWithStaticMessage::new("FlatGeobuf file must contain LineString or MultiLineString features"));
+    }
+    if let Some(crs) = header.get_table(HEADER_CRS)? {
+        let code = crs.get_i32(CRS_CODE, 0)?;
+        if !matches!(code, 0 | 4326 | 4283 | 7844) {
+            println!("WARNING: FlatGeobuf file uses EPSG:{}. Coordinates are expected to be longitude and latitude (EPSG:4326)", code);
+        }
+    }
+    let columns = header
+        .get_tables(HEADER_COLUMNS)?
+        .iter()
+        .map(|column| Ok(Column {
+            name: column.get_string(COLUMN_NAME)?.to_owned(),
+            column_type: column.get_u8(COLUMN_TYPE, 0)?,
+        }))
+        .collect::<Result<Vec<_>, ErrorWithStaticMessage>>()?;
+    let index_length = packed_rtree_size(
+        header.get_u64(HEADER_FEATURES_COUNT, 0)?,
+        header.get_u16(HEADER_INDEX_NODE_SIZE, 16)?,
+    )?;
+
+    let mut position = (12 + header_length as u64)
+        .checked_add(index_length)
+        .and_then(|position| usize::try_from(position).ok())
+        .ok_or_else(corrupt)?;
+    let mut collector = FeatureCollector::new();
+    while position < bytes.len() {
+        let feature_length = read_u32(bytes, position)? as usize;
+        let feature_bytes = bytes.get(position + 4..position + 4 + feature_length).ok_or_else(corrupt)?;
+        collector.push(parse_feature(feature_bytes, &columns, header_geometry_type, field_mapping));
+        position += 4 + feature_length;
+    }
+    Ok(collector.into_features())
+}
+
+/// Load features from a FlatGeobuf file
+pub fn load(path: &Path, field_mapping: &FieldMapping) -> Result<Vec<Feature>, Box<dyn Error>> {
+    Ok(parse_file(&fs::read(path)?, field_mapping)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Cwy;
+
+    /// Writes flatbuffer tables front to back. Offsets always point forward,
+    /// so each child is appended after its parent and the parent is patched.
+    struct TestBuilder {
+        bytes: Vec<u8>,
+    }
+
+    enum Slot {
+        U8(u8),
+        U16(u16),
+        U64(u64),
+        /// Filled in later using [TestBuilder::patch]
+        Offset,
+    }
+
+    impl TestBuilder {
+        /// Returns the position of each field
+        fn table(&mut self, slots: Vec<Option<Slot>>) -> Vec<usize> {
+            let vtable = self.bytes.len();
+            let vtable_length = 4 + 2 * slots.len();
+            let table = vtable + vtable_length;
+            let mut field_offsets = Vec::new();
+            let mut table_bytes = (vtable_length as i32).to_le_bytes().to_vec();
+            let mut positions = Vec::new();
+            for slot in slots.iter() {
+                let offset = table_bytes.len();
+                positions.push(table + offset);
+                match slot {
+                    Some(Slot::U8(value)) => table_bytes.push(*value),
+                    Some(Slot::U16(value)) => table_bytes.extend_from_slice(&value.to_le_bytes()),
+                    Some(Slot::U64(value)) => table_bytes.extend_from_slice(&value.to_le_bytes()),
+                    Some(Slot::Offset) => table_bytes.extend_from_slice(&[0; 4]),
+                    None => (),
+                }
+                field_offsets.push(if slot.is_some() { offset as u16 } else { 0 });
+            }
+            self.bytes.extend_from_slice(&(vtable_length as u16).to_le_bytes());
+            self.bytes.extend_from_slice(&(table_bytes.len() as u16).to_le_bytes());
+            for field_offset in field_offsets {
+                self.bytes.extend_from_slice(&field_offset.to_le_bytes());
+            }
+            self.bytes.extend(table_bytes);
+            positions
+        }
+
+        /// Returns the position of the table written by [TestBuilder::table]
+        fn table_position(positions: &[usize]) -> usize {
+            positions[0] - 4
+        }
+
+        fn patch(&mut self, slot: usize, target: usize) {
+            self.bytes[slot..slot + 4].copy_from_slice(&((target - slot) as u32).to_le_bytes());
+        }
+
+        fn vector(&mut self, length: usize, content: &[u8]) -> usize {
+            let position = self.bytes.len();
+            self.bytes.extend_from_slice(&(length as u32).to_le_bytes());
+            self.bytes.extend_from_slice(content);
+            position
+        }
+
+        fn finish(self, root: usize) -> Vec<u8> {
+            let mut bytes = (root as u32 + 4).to_le_bytes().to_vec();
+            bytes.extend(self.bytes);
+            bytes
+        }
+    }
+
+    fn header() -> Vec<u8> {
+        let mut builder = TestBuilder { bytes: Vec::new() };
+        let header = builder.table(vec![
+            None,
+            None,
+            Some(Slot::U8(GEOMETRY_LINESTRING)),
+            None, None, None, None,
+            Some(Slot::Offset),
+            Some(Slot::U64(2)),
+            Some(Slot::U16(0)),
+        ]);
+        let column_names = ["ROAD", "CWY", "START_SLK", "END_SLK"];
+        let column_types = [11u8, 11, 10, 9];
+        let columns_vector = builder.vector(4, &[0; 16]);
+        builder.patch(header[7], columns_vector);
+        for (index, (name, column_type)) in column_names.iter().zip(column_types).enumerate() {
+            let column = builder.table(vec![Some(Slot::Offset), Some(Slot::U8(column_type))]);
+            builder.patch(columns_vector + 4 + index * 4, TestBuilder::table_position(&column));
+            let name_vector = builder.vector(name.len(), name.as_bytes());
+            builder.patch(column[0], name_vector);
+        }
+        builder.finish(TestBuilder::table_position(&header))
+    }
+
+    fn feature(road: &str, cwy: &str, vertexes: &[f64]) -> Vec<u8> {
+        let mut builder = TestBuilder { bytes: Vec::new() };
+        let feature = builder.table(vec![Some(Slot::Offset), Some(Slot::Offset)]);
+        let geometry = builder.table(vec![None, Some(Slot::Offset)]);
+        builder.patch(feature[0], TestBuilder::table_position(&geometry));
+        let xy: Vec<u8> = vertexes.iter().flat_map(|value| value.to_le_bytes()).collect();
+        let xy_vector = builder.vector(vertexes.len(), &xy);
+        builder.patch(geometry[1], xy_vector);
+        let mut properties = Vec::new();
+        for (column, text) in [(0u16, road), (1, cwy)] {
+            properties.extend_from_slice(&column.to_le_bytes());
+            properties.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            properties.extend_from_slice(text.as_bytes());
+        }
+        properties.extend_from_slice(&2u16.to_le_bytes());
+        properties.extend_from_slice(&1.5f64.to_le_bytes());
+        properties.extend_from_slice(&3u16.to_le_bytes());
+        properties.extend_from_slice(&2.5f32.to_le_bytes());
+        let properties_vector = builder.vector(properties.len(), &properties);
+        builder.patch(feature[1], properties_vector);
+        builder.finish(TestBuilder::table_position(&feature))
+    }
+
+    fn size_prefixed(bytes: Vec<u8>) -> Vec<u8> {
+        let mut result = (bytes.len() as u32).to_le_bytes().to_vec();
+        result.extend(bytes);
+        result
+    }
+
+    #[test]
+    fn test_parse_flatgeobuf() {
+        let mut file = MAGIC.to_vec();
+        file.extend(size_prefixed(header()));
+        file.extend(size_prefixed(feature("H001", "Left", &[115.0, -32.0, 115.01, -32.0])));
+        // only one vertex, will be skipped
+        file.extend(size_prefixed(feature("H002", "Left", &[115.0, -32.0])));
+        let features = parse_file(&file, &FieldMapping::default()).unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0].attributes.ROAD, "H001");
+        assert_eq!(features[0].attributes.CWY, Cwy::Left);
+        assert_eq!(features[0].attributes.START_SLK, 1.5);
+        assert_eq!(features[0].attributes.END_SLK, 2.5);
+        assert_eq!((features[0].geometry[1].x, features[0].geometry[1].y), (115.01, -32.0));
+
+        assert!(parse_file(&file[..file.len() - 3], &FieldMapping::default()).is_err());
+        assert!(parse_file(b"not a flatgeobuf file", &FieldMapping::default()).is_err());
+    }
+
+    #[test]
+    fn test_packed_rtree_size() {
+        assert_eq!(packed_rtree_size(0, 16).unwrap(), 0);
+        assert_eq!(packed_rtree_size(10, 0).unwrap(), 0);
+        assert_eq!(packed_rtree_size(1, 16).unwrap(), 2 * 40);
+        // 100 leaves, 7 level one nodes and a root
+        assert_eq!(packed_rtree_size(100, 16).unwrap(), 108 * 40);
+        assert!(packed_rtree_size(u64::MAX, 16).is_err());
+        assert!(packed_rtree_size(u64::MAX / 40, 2).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use std::{error::Error, fs::File, io::BufReader, path::Path};
+
+use nickslinetoolsrust::vector2::Vector2;
+use serde::Deserialize;
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::{
+    super::{cached::Feature, FieldMapping},
+    fields::{Fields, FieldValue},
+    build_feature,
+    FeatureCollector,
+};
+
+#[derive(Deserialize, Debug)]
+enum GeoJsonFeatureCollectionType {
+    FeatureCollection,
+}
+
+#[derive(Deserialize, Debug)]
+/// Supports the subset of GeoJSON needed to describe the road network.
+///
+/// See reference <https://datatracker.ietf.org/doc/html/rfc7946>
+struct GeoJsonFeatureCollection {
+    #[serde(rename = "type")]
+    #[allow(dead_code)]
+    collection_type: GeoJsonFeatureCollectionType,
+    features: Vec<GeoJsonFeature>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeoJsonFeature {
+    #[serde(default)]
+    properties: Option<serde_json::Map<String, serde_json::Value>>,
+    geometry: Option<GeoJsonGeometry>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type")]
+enum GeoJsonGeometry {
+    LineString {
+        coordinates: Vec<Vec<f64>>,
+    },
+    MultiLineString {
+        coordinates: Vec<Vec<Vec<f64>>>,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+/// Positions may have a third elevation value which is ignored
+fn to_vertexes(positions: Vec<Vec<f64>>) -> Result<Vec<Vector2>, ErrorWithStaticMessage> {
+    positions
+        .into_iter()
+        .map(|position| match position[..] {
+            [x, y, ..] => Ok(Vector2::new(x, y)),
+            _ => Err(ErrorWithStaticMessage::new("Each position must have at least two coordinates")),
+        })
+        .collect()
+}
+
+impl GeoJsonFeature {
+    fn into_feature(self, field_mapping: &FieldMapping) -> Result<Feature, ErrorWithStaticMessage> {
+        let fields: Fields = self.properties
+            .unwrap_or_default()
+            .iter()
+            .map(|(key, value)| (key.clone(), FieldValue::from(value)))
+            .collect();
+        let parts = match self.geometry {
+            Some(GeoJsonGeometry::LineString { coordinates }) => to_vertexes(coordinates).map(|part| vec![part]),
+            Some(GeoJsonGeometry::MultiLineString { coordinates }) => coordinates
+                .into_iter()
+                .map(to_vertexes)
+                .collect::<Result<Vec<_>, _>>(),
+            Some(GeoJsonGeometry::Unsupported) => Err(ErrorWithStaticMessage::new("Geometry must be a LineString or MultiLineString")),
+            None => Err(ErrorWithStaticMessage::new("Feature has no geometry")),
+        };
+        build_feature(&fields, parts, field_mapping)
+    }
+}
+
+fn features_from_collection(collection: GeoJsonFeatureCollection, field_mapping: &FieldMapping) -> Vec<Feature> {
+    let mut collector = FeatureCollector::new();
+    for item in collection.features {
+        collector.push(item.into_feature(field_mapping));
+    }
+    collector.into_features()
+}
+
+/// Load features from a GeoJSON file containing a `FeatureCollection`
+pub fn load(path: &Path, field_mapping: &FieldMapping) -> Result<Vec<Feature>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let collection: GeoJsonFeatureCollection = serde_json::from_reader(reader)?;
+    Ok(features_from_collection(collection, field_mapping))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Cwy;
+
+    #[test]
+    fn test_features_from_collection() {
+        let collection: GeoJsonFeatureCollection = serde_json::from_str(r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": {"ROAD": "H001", "CWY": "Left", "START_SLK": 0.0, "END_SLK": 1.5},
+                    "geometry": {"type": "LineString", "coordinates": [[115.0, -32.0, 10.0], [115.01, -32.0, 11.0]]}
+                },
+                {
+                    "type": "Feature",
+                    "properties": {"ROAD": "H002", "CWY": "Single", "START_SLK": 2.0, "END_SLK": 3.0},
+                    "geometry": {"type": "MultiLineString", "coordinates": [[[116.0, -31.0], [116.0, -31.01]]]}
+                },
+                {
+                    "type": "Feature",
+                    "properties": {"ROAD": "H003", "CWY": "Single", "START_SLK": 0.0, "END_SLK": 1.0},
+                    "geometry": {"type": "Point", "coordinates": [116.0, -31.0]}
+                },
+                {
+                    "type": "Feature",
+                    "properties": null,
+                    "geometry": null
+                }
+            ]
+        }"#).unwrap();
+        let features = features_from_collection(collection, &FieldMapping::default());
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0].attributes.ROAD, "H001");
+        assert_eq!(features[0].attributes.CWY, Cwy::Left);
+        assert_eq!(features[0].attributes.END_SLK, 1.5);
+        assert_eq!(features[0].geometry.len(), 2);
+        assert_eq!(features[0].geometry[1].x, 115.01);
+        assert_eq!(features[1].attributes.ROAD, "H002");
+        assert_eq!(features[1].geometry[1].y, -31.01);
+    }
+
+    #[test]
+    fn test_geojson_must_be_feature_collection() {
+        let result: Result<GeoJsonFeatureCollection, _> = serde_json::from_str(r#"{"type": "Feature", "features": []}"#);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use std::{error::Error, path::Path};
+
+use rusqlite::{types::ValueRef, Connection, OpenFlags};
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::{
+    super::{cached::Feature, FieldMapping},
+    fields::{Fields, FieldValue},
+    wkb::parse_geopackage_lines,
+    build_feature,
+    FeatureCollector,
+};
+
+/// A table listed in `gpkg_geometry_columns`
+struct GeometryTable {
+    table_name: String,
+    column_name: String,
+    geometry_type_name: String,
+    srs_id: i64,
+}
+
+/// Find the table containing the road network. If the GeoPackage contains
+/// more than one line layer the first is used.
+fn find_geometry_table(connection: &Connection) -> Result<GeometryTable, Box<dyn Error>> {
+    let mut statement = connection.prepare(
+        "SELECT table_name, column_name, geometry_type_name, srs_id FROM gpkg_geometry_columns ORDER BY table_name"
+    )?;
+    let tables = statement
+        .query_map([], |row| Ok(GeometryTable {
+            table_name: row.get(0)?,
+            column_name: row.get(1)?,
+            geometry_type_name: row.get(2)?,
+            srs_id: row.get(3)?,
+        }))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut line_tables = tables.into_iter().filter(|table| matches!(
+        table.geometry_type_name.to_ascii_uppercase().as_str(),
+        "LINESTRING" | "MULTILINESTRING" | "GEOMETRY"
+    ));
+    let table = line_tables
+        .next()
+        .ok_or_else(|| Box::new(ErrorWithStaticMessage::new("GeoPackage does not contain a LineString or MultiLineString layer")))?;
+    if line_tables.next().is_some() {
+        println!("WARNING: GeoPackage contains more than one line layer. Using '{}'", table.table_name);
+    }
+    Ok(table)
+}
+
+/// Coordinates are expected to be longitude and latitude. Warn if the layer
+/// says otherwise, but still try to load it.
+fn check_spatial_reference(connection: &Connection, table: &GeometryTable) {
+    let organization_code: Result<(String, i64), _> = connection.query_row(
+        "SELECT organization, organization_coordsys_id FROM gpkg_spatial_ref_sys WHERE srs_id = ?1",
+        [table.srs_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    );
+    match organization_code {
+        Ok((organization, 4326 | 4283 | 7844)) if organization.eq_ignore_ascii_case("EPSG") => (),
+        Ok((organization, code)) => println!(
+            "WARNING: GeoPackage layer '{}' uses {}:{}. Coordinates are expected to be longitude and latitude (EPSG:4326)",
+            table.table_name, organization, code
+        ),
+        Err(_) => println!(
+            "WARNING: Could not check the spatial reference of GeoPackage layer '{}'", table.table_name
+        ),
+    }
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Load features from the first line layer in a GeoPackage file.
+/// `NLR_SOURCE_WHERE` is used as an SQL WHERE clause if it is set.
+pub fn load(path: &Path, field_mapping: &FieldMapping) -> Result<Vec<Feature>, Box<dyn Error>> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let table = find_geometry_table(&connection)?;
+    check_spatial_reference(&connection, &table);
+    println!("INFO: Loading GeoPackage layer '{}'", table.table_name);
+
+    let mut query = format!("SELECT * FROM {}", quote_identifier(&table.table_name));
+    if let Some(condition) = field_mapping.source_where() {
+        query = format!("{} WHERE ({})", query, condition);
+    }
+    let mut statement = connection.prepare(&query)?;
+    let column_names: Vec<String> = statement.column_names().into_iter().map(String::from).collect();
+    let geometry_column = column_names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(&table.column_name))
+        .ok_or_else(|| Box::new(ErrorWithStaticMessage::new("GeoPackage layer is missing its geometry column")))?;
+
+    let mut collector = FeatureCollector::new();
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let mut fields = Fields::new();
+        let mut geometry = None;
+        for (index, name) in column_names.iter().enumerate() {
+            let value = row.get_ref(index)?;
+            if index == geometry_column {
+                if let ValueRef::Blob(blob) = value {
+                    geometry = Some(parse_geopackage_lines(blob));
+                }
+                continue;
+            }
+            let value = match value {
+                ValueRef::Text(text) => FieldValue::Text(String::from_utf8_lossy(text).into_owned()),
+                ValueRef::Integer(integer) => FieldValue::Number(integer as f64),
+                ValueRef::Real(real) => FieldValue::Number(real),
+                ValueRef::Null | ValueRef::Blob(_) => FieldValue::Null,
+            };
+            fields.insert(name.clone(), value);
+        }
+        let geometry = geometry.unwrap_or(Err(ErrorWithStaticMessage::new("Feature has no geometry")));
+        collector.push(build_feature(&fields, geometry, field_mapping));
+    }
+    Ok(collector.into_features())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Cwy;
+
+    fn geopackage_linestring(vertexes: &[(f64, f64)]) -> Vec<u8> {
+        let mut bytes = vec![b'G', b'P', 0, 1];
+        bytes.extend_from_slice(&4326i32.to_le_bytes());
+        bytes.push(1);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&(vertexes.len() as u32).to_le_bytes());
+        for (x, y) in vertexes {
+            bytes.extend_from_slice(&x.to_le_bytes());
+            bytes.extend_from_slice(&y.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_load_geopackage() {
+        let path = std::env::temp_dir().join(format!("nicklinref_test_{}.gpkg", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let connection = Connection::open(&path).unwrap();
+            connection.execute_batch("
+                CREATE TABLE gpkg_spatial_ref_sys (srs_name TEXT, srs_id INTEGER PRIMARY KEY, organization TEXT, organization_coordsys_id INTEGER, definition TEXT);
+                INSERT INTO gpkg_spatial_ref_sys VALUES ('WGS 84', 4326, 'EPSG', 4326, '');
+                CREATE TABLE gpkg_geometry_columns (table_name TEXT, column_name TEXT, geometry_type_name TEXT, srs_id INTEGER, z INTEGER, m INTEGER);
+                INSERT INTO gpkg_geometry_columns VALUES ('network', 'geom', 'LINESTRING', 4326, 0, 0);
+                CREATE TABLE network (fid INTEGER PRIMARY KEY, geom BLOB, road TEXT, cwy TEXT, start_slk REAL, end_slk REAL);
+            ").unwrap();
+            connection.execute(
+                "INSERT INTO network (geom, road, cwy, start_slk, end_slk) VALUES (?1, 'H001', 'Right', 0, 1.5)",
+                [geopackage_linestring(&[(115.0, -32.0), (115.01, -32.0)])],
+            ).unwrap();
+            connection.execute(
+                "INSERT INTO network (geom, road, cwy, start_slk, end_slk) VALUES (NULL, 'H002', 'Right', 0, 1.5)",
+                [],
+            ).unwrap();
+        }
+        let features = load(&path, &FieldMapping::default()).unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0].attributes.ROAD, "H001");
+        assert_eq!(features[0].attributes.CWY, Cwy::Right);
+        assert_eq!(features[0].attributes.END_SLK, 1.5);
+        assert_eq!(features[0].geometry[1].x, 115.01);
+
+        let field_mapping = FieldMapping {
+            NLR_SOURCE_WHERE: Some("road <> 'H001'".to_owned()),
+            ..FieldMapping::default()
+        };
+        let result = load(&path, &field_mapping);
+        let _ = std::fs::remove_file(&path);
+        // the only remaining row has no geometry
+        assert!(result.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+//! Loaders which build a [Layer] from network data stored in local files
+//! instead of downloading it from the esri rest service.
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use nickslinetoolsrust::vector2::Vector2;
+
+use crate::helpers::ErrorWithStaticMessage;
+use super::{
+    cached::{Feature, Layer, SourceMetadata},
+    FieldMapping,
+};
+
+mod fields;
+pub use fields::{attributes_from_fields, Fields, FieldValue};
+mod wkb;
+mod geojson;
+mod flatgeobuf;
+mod geopackage;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum LocalSourceFormat {
+    GeoJson,
+    FlatGeobuf,
+    GeoPackage,
+}
+
+impl LocalSourceFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "geojson" | "json" => Some(Self::GeoJson),
+            "fgb" => Some(Self::FlatGeobuf),
+            "gpkg" => Some(Self::GeoPackage),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the path to a local file if `url` is a `file://` url or a plain
+/// file path. Returns `None` for `http://` and `https://` urls.
+///
+/// `file://` urls are percent decoded. One which is not a valid file url
+/// (such as `file://data/network.gpkg`, where `data` is taken as the host) is
+/// used as a path as written, so that the error names the file.
+pub fn local_source_path(url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Some(
+            reqwest::Url::parse(url)
+                .ok()
+                .and_then(|parsed| parsed.to_file_path().ok())
+                .unwrap_or_else(|| PathBuf::from(path))
+        );
+    }
+    if url.contains("://") {
+        return None;
+    }
+    Some(PathBuf::from(url))
+}
+
+/// Milliseconds since the unix epoch when the file was last modified. Used in
+/// place of the rest service `lastEditDate` so that the scheduled refresh can
+/// tell when a local file has been replaced.
+pub fn modified_time_millis(path: &Path) -> Option<i64> {
+    let modified = path.metadata().ok()?.modified().ok()?;
+    let millis = modified.duration_since(UNIX_EPOCH).ok()?.as_millis();
+    i64::try_from(millis).ok()
+}
+
+/// Convert the parts of a line geometry into a single list of vertices.
+/// The network only contains single part lines, so anything else is
+/// rejected.
+fn single_part(mut parts: Vec<Vec<Vector2>>) -> Result<Vec<Vector2>, ErrorWithStaticMessage> {
+    if parts.len() != 1 {
+        return Err(ErrorWithStaticMessage::new("Geometry must be a LineString or a MultiLineString with exactly one part"));
+    }
+    let part = parts.remove(0);
+    if part.len() < 2 {
+        return Err(ErrorWithStaticMessage::new("Geometry must have at least two vertices"));
+    }
+    Ok(part)
+}
+
+/// Build a [Feature] from the fields and geometry parts read by a loader
+fn build_feature(
+    fields: &Fields,
+    parts: Result<Vec<Vec<Vector2>>, ErrorWithStaticMessage>,
+    field_mapping: &FieldMapping,
+) -> Result<Feature, ErrorWithStaticMessage> {
+    Ok(Feature {
+        attributes: attributes_from_fields(fields, field_mapping)?,
+        geometry: single_part(parts?)?,
+        measures: None,
+    })
+}
+
+/// Collects the features read by a loader or downloaded from the rest service.
+/// Individual features which cannot be converted are skipped, and a summary is
+/// printed at the end so that one bad row does not prevent the server from
+/// starting.
+pub struct FeatureCollector {
+    features: Vec<Feature>,
+    skipped: usize,
+    first_skip_reason: Option<&'static str>,
+}
+
+impl FeatureCollector {
+    pub fn new() -> Self {
+        Self {
+            features: Vec::new(),
+            skipped: 0,
+            first_skip_reason: None,
+        }
+    }
+
+    pub fn push(&mut self, feature: Result<Feature, ErrorWithStaticMessage>) {
+        match feature {
+            Ok(feature) => self.features.push(feature),
+            Err(error) => self.skip(error),
+        }
+    }
+
+    /// Add the parts of one feature which was split into several
+    pub fn extend(&mut self, parts: Vec<Feature>) {
+        self.features.extend(parts);
+    }
+
+    pub fn skip(&mut self, error: ErrorWithStaticMessage) {
+        self.skipped += 1;
+        self.first_skip_reason.get_or_insert(error.get_message());
+    }
+
+    pub fn into_features(self) -> Vec<Feature> {
+        if self.skipped > 0 {
+            println!(
+                "WARNING: Skipped {} features which could not be loaded. The first was skipped because '{}'",
+                self.skipped,
+                self.first_skip_reason.unwrap_or_default()
+            );
+        }
+        self.features
+    }
+}
+
+/// Build a [Layer] from a local GeoJSON, FlatGeobuf or GeoPackage file. The
+/// format is chosen using the file extension. Coordinates must be longitude
+/// and latitude in degrees.
+pub fn load_local_source(path: &Path, field_mapping: &FieldMapping) -> Result<Layer, Box<dyn Error>> {
+    let format = LocalSourceFormat::from_path(path).ok_or_else(|| Box::new(ErrorWithStaticMessage::new(
+        "Local data source must have the extension .geojson, .json, .fgb or .gpkg"
+    )))?;
+    if format != LocalSourceFormat::GeoPackage && field_mapping.source_where().is_some() {
+        println!("WARNING: NLR_SOURCE_WHERE is ignored when loading GeoJSON or FlatGeobuf files");
+    }
+    let mut features = match format {
+        LocalSourceFormat::GeoJson => geojson::load(path, field_mapping)?,
+        LocalSourceFormat::FlatGeobuf => flatgeobuf::load(path, field_mapping)?,
+        LocalSourceFormat::GeoPackage => geopackage::load(path, field_mapping)?,
+    };
+    if features.is_empty() {
+        return Err(Box::new(ErrorWithStaticMessage::new("No features could be loaded from the local data source")));
+    }
+    println!("INFO: Loaded {} features. Sorting data.", features.len());
+    features.sort_by(|a, b| a.attributes.cmp(&b.attributes));
+    let source_metadata = SourceMetadata {
+        count: features.len(),
+        last_edit_date: modified_time_millis(path),
+    };
+    Ok(Layer {
+        features,
+        source_metadata: Some(source_metadata),
+        downloaded: None,
+        spatial_reference: None,
+        cache_metadata: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_source_path() {
+        assert_eq!(local_source_path("https://a.b/query?f=json"), None);
+        assert_eq!(local_source_path("http://a.b/query?f=json"), None);
+        assert_eq!(local_source_path("./data/network.geojson"), Some(PathBuf::from("./data/network.geojson")));
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_local_source_path_file_url() {
+        assert_eq!(local_source_path("file:///data/network.gpkg"), Some(PathBuf::from("/data/network.gpkg")));
+        assert_eq!(local_source_path("file:///My%20Data/R%C3%A9seau%2Bv2.fgb"), Some(PathBuf::from("/My Data/Réseau+v2.fgb")));
+        assert_eq!(local_source_path("file://data/network.gpkg"), Some(PathBuf::from("data/network.gpkg")));
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_local_source_path_file_url() {
+        assert_eq!(local_source_path("file:///C:/My%20Data/network.fgb"), Some(PathBuf::from("C:\\My Data\\network.fgb")));
+    }
+
+    #[test]
+    fn test_local_source_format_from_path() {
+        assert_eq!(LocalSourceFormat::from_path(Path::new("a/b.GeoJSON")), Some(LocalSourceFormat::GeoJson));
+        assert_eq!(LocalSourceFormat::from_path(Path::new("a/b.fgb")), Some(LocalSourceFormat::FlatGeobuf));
+        assert_eq!(LocalSourceFormat::from_path(Path::new("a/b.gpkg")), Some(LocalSourceFormat::GeoPackage));
+        assert_eq!(LocalSourceFormat::from_path(Path::new("a/b.shp")), None);
+    }
+}
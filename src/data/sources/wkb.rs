@@ -0,0 +1,189 @@
+//! Reads line geometry from Well Known Binary, and from the GeoPackage binary
+//! header which wraps it.
+//!
+//! See references
+//! - <https://libgeos.org/specifications/wkb/>
+//! - <https://www.geopackage.org/spec/#gpb_format>
+
+use nickslinetoolsrust::vector2::Vector2;
+
+use crate::helpers::ErrorWithStaticMessage;
+
+const WKB_LINESTRING: u32 = 2;
+const WKB_MULTILINESTRING: u32 = 5;
+
+struct WkbReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    little_endian: bool,
+}
+
+impl<'a> WkbReader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ErrorWithStaticMessage> {
+        let end = self.position + N;
+        let slice = self.bytes
+            .get(self.position..end)
+            .ok_or(ErrorWithStaticMessage::new("Geometry ended unexpectedly"))?;
+        self.position = end;
+        Ok(slice.try_into().unwrap())
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ErrorWithStaticMessage> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, ErrorWithStaticMessage> {
+        let bytes = self.take::<4>()?;
+        Ok(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn read_f64(&mut self) -> Result<f64, ErrorWithStaticMessage> {
+        let bytes = self.take::<8>()?;
+        Ok(if self.little_endian { f64::from_le_bytes(bytes) } else { f64::from_be_bytes(bytes) })
+    }
+
+    /// Reads the byte order and geometry type. Returns the base geometry type
+    /// (eg [WKB_LINESTRING]) and the number of ordinates per vertex.
+    fn read_geometry_header(&mut self) -> Result<(u32, usize), ErrorWithStaticMessage> {
+        self.little_endian = match self.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(ErrorWithStaticMessage::new("Geometry has an invalid byte order")),
+        };
+        let geometry_type = self.read_u32()?;
+        // EWKB stores Z and M as flags in the high bits
+        let ewkb_has_z = geometry_type & 0x8000_0000 != 0;
+        let ewkb_has_m = geometry_type & 0x4000_0000 != 0;
+        let geometry_type = geometry_type & 0x0FFF_FFFF;
+        // ISO WKB adds 1000 for Z, 2000 for M and 3000 for ZM
+        let (base_type, iso_dimensions) = (geometry_type % 1000, geometry_type / 1000);
+        let ordinates = match (iso_dimensions, ewkb_has_z as usize + ewkb_has_m as usize) {
+            (0, extra) => 2 + extra,
+            (1 | 2, 0) => 3,
+            (3, 0) => 4,
+            _ => return Err(ErrorWithStaticMessage::new("Geometry has an invalid type")),
+        };
+        Ok((base_type, ordinates))
+    }
+
+    fn read_vertexes(&mut self, ordinates: usize) -> Result<Vec<Vector2>, ErrorWithStaticMessage> {
+        let count = self.read_u32()? as usize;
+        // Guard against allocating a huge vector for a corrupt count
+        if count.saturating_mul(ordinates * 8) > self.bytes.len() - self.position {
+            return Err(ErrorWithStaticMessage::new("Geometry ended unexpectedly"));
+        }
+        let mut vertexes = Vec::with_capacity(count);
+        for _ in 0..count {
+            let x = self.read_f64()?;
+            let y = self.read_f64()?;
+            for _ in 2..ordinates {
+                self.read_f64()?;
+            }
+            vertexes.push(Vector2::new(x, y));
+        }
+        Ok(vertexes)
+    }
+}
+
+/// Read a WKB `LineString` or `MultiLineString`, returning the vertices of
+/// each part. Z and M values are ignored.
+pub fn parse_wkb_lines(bytes: &[u8]) -> Result<Vec<Vec<Vector2>>, ErrorWithStaticMessage> {
+    let mut reader = WkbReader { bytes, position: 0, little_endian: true };
+    match reader.read_geometry_header()? {
+        (WKB_LINESTRING, ordinates) => Ok(vec![reader.read_vertexes(ordinates)?]),
+        (WKB_MULTILINESTRING, _) => {
+            let part_count = reader.read_u32()?;
+            let mut parts = Vec::new();
+            for _ in 0..part_count {
+                match reader.read_geometry_header()? {
+                    (WKB_LINESTRING, ordinates) => parts.push(reader.read_vertexes(ordinates)?),
+                    _ => return Err(ErrorWithStaticMessage::new("MultiLineString contains something other than a LineString")),
+                }
+            }
+            Ok(parts)
+        }
+        _ => Err(ErrorWithStaticMessage::new("Geometry must be a LineString or MultiLineString")),
+    }
+}
+
+/// Strip the GeoPackage binary header and read the WKB geometry inside it
+pub fn parse_geopackage_lines(bytes: &[u8]) -> Result<Vec<Vec<Vector2>>, ErrorWithStaticMessage> {
+    if bytes.len() < 8 || &bytes[0..2] != b"GP" {
+        return Err(ErrorWithStaticMessage::new("Geometry is not a GeoPackage binary geometry"));
+    }
+    let flags = bytes[3];
+    if flags & 0b0010_0000 != 0 {
+        return Err(ErrorWithStaticMessage::new("Geometry uses a GeoPackage extension which is not supported"));
+    }
+    let envelope_length = match (flags >> 1) & 0b111 {
+        0 => 0,
+        1 => 32,
+        2 | 3 => 48,
+        4 => 64,
+        _ => return Err(ErrorWithStaticMessage::new("Geometry has an invalid GeoPackage envelope")),
+    };
+    let start = 8 + envelope_length;
+    parse_wkb_lines(bytes.get(start..).ok_or(ErrorWithStaticMessage::new("Geometry ended unexpectedly"))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linestring_le(geometry_type: u32, vertexes: &[&[f64]]) -> Vec<u8> {
+        let mut bytes = vec![1u8];
+        bytes.extend_from_slice(&geometry_type.to_le_bytes());
+        bytes.extend_from_slice(&(vertexes.len() as u32).to_le_bytes());
+        for vertex in vertexes {
+            for ordinate in vertex.iter() {
+                bytes.extend_from_slice(&ordinate.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_parse_wkb_linestring() {
+        let parts = parse_wkb_lines(&linestring_le(2, &[&[1.0, 2.0], &[3.0, 4.0]])).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!((parts[0][1].x, parts[0][1].y), (3.0, 4.0));
+
+        // ISO LineString Z
+        let parts = parse_wkb_lines(&linestring_le(1002, &[&[1.0, 2.0, 9.0], &[3.0, 4.0, 9.0]])).unwrap();
+        assert_eq!((parts[0][1].x, parts[0][1].y), (3.0, 4.0));
+
+        // EWKB LineString ZM
+        let parts = parse_wkb_lines(&linestring_le(0xC000_0002, &[&[1.0, 2.0, 9.0, 8.0], &[3.0, 4.0, 9.0, 8.0]])).unwrap();
+        assert_eq!((parts[0][1].x, parts[0][1].y), (3.0, 4.0));
+    }
+
+    #[test]
+    fn test_parse_wkb_big_endian_multilinestring() {
+        let mut bytes = vec![0u8];
+        bytes.extend_from_slice(&5u32.to_be_bytes());
+        bytes.extend_from_slice(&1u32.to_be_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(&2u32.to_be_bytes());
+        bytes.extend_from_slice(&2u32.to_be_bytes());
+        for ordinate in [1.0f64, 2.0, 3.0, 4.0] {
+            bytes.extend_from_slice(&ordinate.to_be_bytes());
+        }
+        let parts = parse_wkb_lines(&bytes).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!((parts[0][0].x, parts[0][0].y), (1.0, 2.0));
+    }
+
+    #[test]
+    fn test_parse_geopackage_lines() {
+        // little endian header with an xy envelope
+        let mut bytes = vec![b'G', b'P', 0, 0b0000_0011];
+        bytes.extend_from_slice(&4326i32.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 32]);
+        bytes.extend(linestring_le(2, &[&[1.0, 2.0], &[3.0, 4.0]]));
+        let parts = parse_geopackage_lines(&bytes).unwrap();
+        assert_eq!((parts[0][0].x, parts[0][0].y), (1.0, 2.0));
+
+        assert!(parse_geopackage_lines(&bytes[..20]).is_err());
+        assert!(parse_wkb_lines(&linestring_le(1, &[&[1.0, 2.0]])).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use warp::{Filter, Rejection};
+
+use crate::{
+    data::SharedIndexedData,
+    helpers::ErrorWithStaticMessage,
+    settings::Settings,
+};
+
+use super::{
+    authorized,
+    query_parameters::{QueryParametersReload, ReloadSource},
+    with_shared_data
+};
+
+pub fn admin_reload(
+    shared_data: Arc<SharedIndexedData>,
+    settings: Arc<Settings>,
+) -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    warp::post()
+    .and(warp::path!("admin" / "reload"))
+    .and(authorized(settings.NLR_ADMIN_TOKEN.clone()))
+    .and(with_shared_data(shared_data.clone()))
+    .and(with_shared_data(settings.clone()))
+    .and(warp::query())
+    .and_then(|
+        shared_data: Arc<SharedIndexedData>,
+        settings: Arc<Settings>,
+        query: QueryParametersReload
+    | async move {
+        println!("INFO: Reloading data from {:?}", query.source);
+        // Run as a separate task so the reload still completes if the client
+        // disconnects while waiting
+        let reload_task = tokio::spawn(async move {
+            shared_data.reload(
+                &settings.get_data_source_settings(),
+                query.source == ReloadSource::download,
+            ).await
+        });
+        match reload_task.await {
+            Ok(Ok(feature_count)) => Ok(format!(r#"{{"features":{}}}"#, feature_count)),
+            Ok(Err(message)) => Err(ErrorWithStaticMessage::reject(message)),
+            Err(_) => Err(ErrorWithStaticMessage::reject("Reload failed. The reload task panicked.")),
+        }
+    })
+}
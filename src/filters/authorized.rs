@@ -0,0 +1,57 @@
+use warp::{reject::Reject, Filter, Rejection};
+
+/// Rejection used when a request to a protected route does not carry the
+/// expected `Authorization` header
+#[derive(Debug)]
+pub struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+/// Compares every byte so that the time taken does not reveal how much of the
+/// token was correct
+fn tokens_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Only allow requests with the header `Authorization: Bearer {admin_token}`.
+///
+/// If no `admin_token` is configured then the protected route is disabled and
+/// every request is rejected as not found.
+pub fn authorized(
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let admin_token = admin_token.clone();
+            async move {
+                match (admin_token, authorization) {
+                    (None, _) => Err(warp::reject::not_found()),
+                    (Some(admin_token), Some(authorization))
+                        if tokens_match(&format!("Bearer {}", admin_token), &authorization) =>
+                    {
+                        Ok(())
+                    }
+                    _ => Err(warp::reject::custom(Unauthorized)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("Bearer abc", "Bearer abc"));
+        assert!(!tokens_match("Bearer abc", "Bearer abd"));
+        assert!(!tokens_match("Bearer abc", "Bearer ab"));
+        assert!(!tokens_match("Bearer abc", ""));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::helpers::ErrorWithStaticMessage;
+use super::{NotFound, Unauthorized};
 use warp::{
     http::StatusCode, reject::{InvalidQuery, MethodNotAllowed, UnsupportedMediaType}, reply::Response, Rejection, Reply
 };
@@ -11,6 +12,12 @@ pub async fn custom_rejection_handler(rejection: Rejection) -> Result<Response,
     if rejection.is_not_found() {
         code = StatusCode::NOT_FOUND;
         message = "Not Found".to_owned();
+    } else if rejection.find::<Unauthorized>().is_some() {
+        code = StatusCode::UNAUTHORIZED;
+        message = "Unauthorized".to_owned();
+    } else if let Some(not_found) = rejection.find::<NotFound>() {
+        code = StatusCode::NOT_FOUND;
+        message = not_found.get_message().to_owned();
     } else if let Some(custom_reject) = rejection.find::<ErrorWithStaticMessage>() {
         code = StatusCode::INTERNAL_SERVER_ERROR;
         message = custom_reject.get_message().to_owned();
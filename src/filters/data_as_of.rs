@@ -0,0 +1,24 @@
+use std::sync::Arc;
+
+use warp::Rejection;
+
+use crate::{
+    data::{IndexedData, SharedIndexedData},
+    helpers::CalendarDate,
+};
+
+use super::NotFound;
+
+/// The data a query with an optional `as_of=` date should be answered from.
+/// See [SharedIndexedData::as_of]. Rejected with `404 Not Found` if there is
+/// no data for that date.
+pub async fn data_as_of(
+    shared_data: &SharedIndexedData,
+    as_of: &Option<CalendarDate>,
+) -> Result<Arc<IndexedData>, Rejection> {
+    shared_data
+        .as_of(as_of)
+        .await
+        .map_err(|err| err.as_rejection())?
+        .ok_or_else(|| NotFound::reject("No data is available for the as_of date. There is no snapshot taken on or before it, or the download date of the current data is not known"))
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use warp::{
+    http::{HeaderValue, Method, StatusCode},
+    hyper::Body,
+    reply::Response,
+    Filter, Rejection, Reply,
+};
+
+use crate::data::SharedIndexedData;
+
+static DATA_VERSION_HEADER: &str = "x-data-version";
+
+/// True if an `If-None-Match` header lists `etag`, or is `*`. Weak tags match
+/// too, since every response for the same data version is identical.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Record the version of the data a response was made from, for routes which
+/// may answer from a snapshot with `as_of=`. [data_version_headers] reports
+/// it instead of the version of the current data.
+pub fn with_data_version(reply: impl Reply, data_version: &str) -> Response {
+    let mut response = reply.into_response();
+    if let Ok(value) = HeaderValue::from_str(data_version) {
+        response.headers_mut().insert(DATA_VERSION_HEADER, value);
+    }
+    response
+}
+
+/// The `ETag` of a response. Compressed and uncompressed bodies of the same
+/// data are different, so the `Content-Encoding` is included.
+fn etag(data_version: &str, response: &Response) -> String {
+    match response.headers().get("content-encoding").and_then(|value| value.to_str().ok()) {
+        Some(encoding) => format!("\"{}-{}\"", data_version, encoding),
+        None => format!("\"{}\"", data_version),
+    }
+}
+
+/// Adds the version of the data being served to the response as the
+/// `X-Data-Version` header, and to successful responses as the `ETag` header.
+/// A `GET` request whose `If-None-Match` header lists the current `ETag` is
+/// answered with `304 Not Modified` and no body.
+///
+/// Routes which answer from a snapshot set the version themselves using
+/// [with_data_version]. Otherwise the version of the current data is read
+/// before the request is answered, so a reload which happens part way through
+/// a request can only make the version older than the data, never newer.
+/// Clients will re-download rather than keep stale results.
+///
+/// Like [super::echo_x_request_id] this only sees rejected requests if
+/// `filter` has already recovered from all rejections.
+pub fn data_version_headers<F, T>(
+    shared_data: Arc<SharedIndexedData>,
+    filter: F,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone + Send + Sync + 'static
+where
+    F: Filter<Extract = (T,), Error = Rejection> + Clone + Send + Sync + 'static,
+    T: Reply,
+{
+    warp::any()
+        .map(move || shared_data.current().data_version().to_owned())
+        .and(warp::method())
+        .and(
+            warp::header::optional::<String>("if-none-match")
+                .or(warp::any().map(|| None))
+                .unify(),
+        )
+        .and(
+            filter
+            .map(|reply: T| reply.into_response())
+        )
+        .map(|current_version: String, method: Method, if_none_match: Option<String>, mut response: Response| {
+            let data_version = response
+                .headers()
+                .get(DATA_VERSION_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map_or(current_version, str::to_owned);
+            let etag = etag(&data_version, &response);
+            if response.status().is_success() {
+                let not_modified = method == Method::GET
+                    && response.status() == StatusCode::OK
+                    && if_none_match.as_deref().is_some_and(|if_none_match| etag_matches(if_none_match, &etag));
+                if not_modified {
+                    // keep the other headers, eg `x-request-id`
+                    *response.body_mut() = Body::empty();
+                    *response.status_mut() = StatusCode::NOT_MODIFIED;
+                    response.headers_mut().remove("content-length");
+                }
+                if let Ok(value) = HeaderValue::from_str(&etag) {
+                    response.headers_mut().insert("etag", value);
+                }
+            }
+            if let Ok(value) = HeaderValue::from_str(&data_version) {
+                response.headers_mut().insert(DATA_VERSION_HEADER, value);
+            }
+            response
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("\"abc\"", "\"abc\""));
+        assert!(etag_matches("W/\"abc\"", "\"abc\""));
+        assert!(etag_matches("\"xyz\", \"abc\"", "\"abc\""));
+        assert!(etag_matches("*", "\"abc\""));
+        assert!(!etag_matches("\"abcd\"", "\"abc\""));
+        assert!(!etag_matches("abc", "\"abc\""));
+    }
+
+    #[test]
+    fn test_etag() {
+        let mut response = with_data_version("[]", "abc");
+        assert_eq!(response.headers().get(DATA_VERSION_HEADER).unwrap(), "abc");
+        assert_eq!(etag("abc", &response), "\"abc\"");
+        response.headers_mut().insert("content-encoding", HeaderValue::from_static("gzip"));
+        assert_eq!(etag("abc", &response), "\"abc-gzip\"");
+    }
+
+    #[tokio::test]
+    async fn test_data_version_headers_snapshot() {
+        use crate::data::{cached::{Attributes, Cwy, Feature, Layer}, IndexedData, Snapshots};
+        let indexed_data = IndexedData::from_layer(Layer {
+            features: vec![Feature::new(Attributes::new("H001", Cwy::Single, 0.0, 1.0), &[(115.0, -32.0), (115.01, -32.0)])],
+            source_metadata: None,
+            downloaded: None,
+            spatial_reference: None,
+            cache_metadata: None,
+        }).unwrap();
+        let current_version = indexed_data.data_version().to_owned();
+        let shared_data = Arc::new(SharedIndexedData::new(indexed_data, Snapshots::open(&None)));
+
+        let filter = data_version_headers(shared_data.clone(), warp::path("current").map(|| "[]"));
+        let response = warp::test::request().path("/current").filter(&filter).await.unwrap();
+        assert_eq!(response.headers().get("x-data-version").unwrap(), current_version.as_str());
+        assert_eq!(response.headers().get("etag").unwrap().to_str().unwrap(), format!("\"{}\"", current_version));
+
+        let filter = data_version_headers(shared_data, warp::path("snapshot").map(|| with_data_version("[]", "0123456789abcdef")));
+        let response = warp::test::request().path("/snapshot").filter(&filter).await.unwrap();
+        assert_eq!(response.headers().get("x-data-version").unwrap(), "0123456789abcdef");
+        assert_eq!(response.headers().get("etag").unwrap(), "\"0123456789abcdef\"");
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::Arc;
+
+use warp::Filter;
+
+use crate::{
+    data::{IndexedData, SharedIndexedData},
+    helpers::ErrorWithStaticMessage,
+};
+
+use super::with_current_data;
+
+/// List the problems found in the current data when it was loaded
+pub fn diagnostics(
+    shared_data: Arc<SharedIndexedData>
+) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::path("diagnostics")
+    .and(warp::path::end())
+    .and(warp::get())
+    .and(with_current_data(shared_data.clone()))
+    .and_then(|
+        indexed_data: Arc<IndexedData>
+    | async move {
+        serde_json::to_string(indexed_data.validation())
+            .map_err(|_| ErrorWithStaticMessage::reject("Unable to serialise diagnostics"))
+    })
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use warp::Filter;
+
+use crate::{
+    data::{NetworkDiff, SharedIndexedData},
+    helpers::ErrorWithStaticMessage,
+};
+
+use super::{
+    authorized,
+    data_as_of,
+    query_parameters::QueryParametersDiff,
+    with_shared_data
+};
+
+/// Report how the network changed between two snapshots, or between a
+/// snapshot and the current data.
+///
+/// Comparing the whole network is slow, so like `/admin/reload` the route
+/// needs the admin token and is not available when none is set. It takes
+/// dates rather than the cache file paths taken by the `diff` command so that
+/// clients can not make the server read arbitrary files.
+pub fn diff(
+    shared_data: Arc<SharedIndexedData>,
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::path("diff")
+    .and(warp::path::end())
+    .and(warp::get())
+    .and(authorized(admin_token))
+    .and(with_shared_data(shared_data.clone()))
+    .and(warp::query())
+    .and_then(|
+        shared_data: Arc<SharedIndexedData>,
+        query: QueryParametersDiff
+    | async move {
+        let old = data_as_of(&shared_data, &Some(query.from)).await?;
+        let new = data_as_of(&shared_data, &query.to).await?;
+        // Comparing the whole network takes a while; keep it off the async threads
+        tokio::task::spawn_blocking(move || {
+            NetworkDiff::compare(old.features(), new.features(), query.tolerance).to_format(&query.f)
+        })
+        .await
+        .map_err(|_| ErrorWithStaticMessage::reject("Unable to compare the networks"))
+    })
+}
@@ -0,0 +1,77 @@
+use serde::Serialize;
+
+use crate::data::cached::Attributes;
+use super::merge_fragments::Discontinuity;
+
+/// The GeoJSON `properties` of a line or point result. A result is usually
+/// built from several features of the road network, so each property lists
+/// the distinct values of that attribute in the order they were encountered
+/// (ie in order of increasing SLK). Missing values are skipped.
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct FeatureProperties<'a> {
+    network_type: Vec<&'a str>,
+    road_name: Vec<&'a str>,
+    lg_name: Vec<&'a str>,
+    ra_no: Vec<&'a str>,
+    /// Only present when `merge=true` was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    discontinuities: Option<Vec<Discontinuity>>,
+}
+
+fn push_distinct<'a>(values: &mut Vec<&'a str>, value: &'a Option<String>) {
+    if let Some(value) = value {
+        if !values.contains(&value.as_str()) {
+            values.push(value);
+        }
+    }
+}
+
+impl<'a> FeatureProperties<'a> {
+    pub fn add(&mut self, attributes: &'a Attributes) {
+        push_distinct(&mut self.network_type, &attributes.NETWORK_TYPE);
+        push_distinct(&mut self.road_name, &attributes.ROAD_NAME);
+        push_distinct(&mut self.lg_name, &attributes.LG_NAME);
+        push_distinct(&mut self.ra_no, &attributes.RA_NO);
+    }
+
+    pub fn set_discontinuities(&mut self, discontinuities: Option<Vec<Discontinuity>>) {
+        self.discontinuities = discontinuities;
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or("{}".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Cwy;
+
+    fn attributes(lg_name: Option<&str>) -> Attributes {
+        Attributes {
+            NETWORK_TYPE: Some("State Road".to_owned()),
+            ROAD_NAME: Some("Albany Highway".to_owned()),
+            LG_NAME: lg_name.map(str::to_owned),
+            ..Attributes::new("H001", Cwy::Single, 0.0, 1.0)
+        }
+    }
+
+    #[test]
+    fn test_feature_properties_distinct_values() {
+        let items = [
+            attributes(Some("City of Armadale")),
+            attributes(None),
+            attributes(Some("Shire of Serpentine-Jarrahdale")),
+            attributes(Some("City of Armadale")),
+        ];
+        let mut properties = FeatureProperties::default();
+        for item in items.iter() {
+            properties.add(item);
+        }
+        assert_eq!(
+            properties.to_json(),
+            r#"{"network_type":["State Road"],"road_name":["Albany Highway"],"lg_name":["City of Armadale","Shire of Serpentine-Jarrahdale"],"ra_no":[]}"#
+        );
+    }
+}
@@ -2,42 +2,52 @@
 use crate::data::IndexedData;
 use crate::filters::query_parameters::{QueryParametersLine, output_format::OutputFormatLines};
 use nickslinetoolsrust::line_string_measured::{LineStringMeasured};
-use crate::helpers::{convert_metres_to_degrees, ErrorWithStaticMessage};
+use nickslinetoolsrust::vector2::Vector2;
+use crate::helpers::ErrorWithStaticMessage;
+use super::{check_offset, check_simplify, get_polygon, merge_fragments, offset_fragment, simplify_and_densify, FeatureProperties, Fragment};
 
 
 pub fn get_linestring_m(query:&QueryParametersLine, indexed_data:&IndexedData)->Result<String, ErrorWithStaticMessage>{
 
-	let road_data =  indexed_data.query(&query.road, &query.cwy)?;
-    let features = road_data.filter_map(|item|{
-			if item.attributes.END_SLK>query.slk_from && item.attributes.START_SLK<query.slk_to{
+	if query.polygon_offsets().is_some() {
+		// fails; polygons have no m values
+		return get_polygon(query, indexed_data);
+	}
+	check_offset(query)?;
+	check_simplify(query)?;
+	let road_data =  indexed_data.query_range(&query.road, &query.cwy, &query.measure, query.slk_from, query.slk_to)?;
+    let features = road_data
+		.filter(|(item, _)| query.includes_attributes(&item.attributes))
+		.filter_map(|(item, measured_line)|{
+			let (item_start, item_end) = query.measure.feature_range(&item.attributes)?;
+			if item_end>query.slk_from && item_start<query.slk_to{
 
-				let lsm:LineStringMeasured = LineStringMeasured::from(&item.geometry);
-				
-				let item_len_km = item.attributes.END_SLK - item.attributes.START_SLK;
-				let frac_start = (query.slk_from-item.attributes.START_SLK) / item_len_km;
-				let frac_end = (query.slk_to-item.attributes.START_SLK) / item_len_km;
+				let item_len_km = item_end - item_start;
+				let frac_start = measured_line.length_fraction(((query.slk_from-item_start) / item_len_km).into());
+				let frac_end = measured_line.length_fraction(((query.slk_to-item_start) / item_len_km).into());
 
-				match lsm.cut_twice(frac_start.into(), frac_end.into()){
-					(_, Some(b), _) => {
-							if query.offset == 0.0 {
-								Some(b.into_tuples_measured(
-									query.slk_from.max(item.attributes.START_SLK)as f64,
-									query.slk_to.min(item.attributes.END_SLK) as f64
-								))
+				let range = (query.slk_from.max(item_start), query.slk_to.min(item_end));
+
+				match measured_line.cut(&item.geometry, frac_start, frac_end){
+					Some(vertexes) => {
+							if query.offset_range() == (0.0, 0.0) {
+								Some(Fragment::new(LineStringMeasured::from(vertexes).into_tuples_measured(
+									query.slk_from.max(item_start)as f64,
+									query.slk_to.min(item_end) as f64
+								), &item.attributes, range))
 							}else{
-								let degree_offset:f64 = -convert_metres_to_degrees(query.offset.into());
-								match b.offset_basic(degree_offset){
+								match offset_fragment(query, (item_start, item_end), measured_line, (frac_start, frac_end), &vertexes){
 									Some(offset_ls)=>{
-										Some(LineStringMeasured::from(offset_ls).into_tuples_measured(
-											query.slk_from.max(item.attributes.START_SLK)as f64,
-											query.slk_to.min(item.attributes.END_SLK) as f64
-										))
+										Some(Fragment::new(LineStringMeasured::from(offset_ls).into_tuples_measured(
+											query.slk_from.max(item_start)as f64,
+											query.slk_to.min(item_end) as f64
+										), &item.attributes, range))
 									},
 									None=>None
 								}
 							}
 						},
-					_=>None
+					None=>None
 				}
 
 			}else{
@@ -45,29 +55,54 @@ pub fn get_linestring_m(query:&QueryParametersLine, indexed_data:&IndexedData)->
 			}
 		});
 
+	let (mut features, discontinuities) = if query.merge {
+		let (merged, discontinuities) = merge_fragments(features, |vertex| Vector2::new(vertex.0, vertex.1), query.merge_tolerance.into());
+		(merged, Some(discontinuities))
+	}else{
+		(features.collect::<Vec<_>>(), None)
+	};
+
+	// the m value of a new vertex is interpolated along with its position
+	simplify_and_densify(
+		query,
+		features.iter_mut().map(|fragment| &mut fragment.vertexes),
+		|vertex| Vector2::new(vertex.0, vertex.1),
+		|a, b, fraction| (
+			a.0 + (b.0 - a.0) * fraction,
+			a.1 + (b.1 - a.1) * fraction,
+			a.2 + (b.2 - a.2) * fraction,
+		),
+	)?;
+
 		match query.f{
 			OutputFormatLines::json => {
 				let line_string_string = features
-					.map(|linestring|{
-							"[".to_string() + &linestring.iter().filter_map(|vertex| serde_json::to_string(vertex).ok()).collect::<Vec<String>>().join(",") + "]"
+					.iter()
+					.map(|fragment|{
+							"[".to_string() + &fragment.vertexes.iter().filter_map(|vertex| serde_json::to_string(vertex).ok()).collect::<Vec<String>>().join(",") + "]"
 					})
 					.collect::<Vec<String>>()
 					.join(",");
 				Ok("[".to_string() + &line_string_string + "]")
 			},
 			OutputFormatLines::geojson => {
+				let mut properties = FeatureProperties::default();
+				properties.set_discontinuities(discontinuities);
 				let line_string_string = features
-					.map(|linestring|{
-							"[".to_string() + &linestring.iter().filter_map(|vertex| serde_json::to_string(vertex).ok()).collect::<Vec<String>>().join(",") + "]"
+					.iter()
+					.map(|fragment|{
+							fragment.attributes.iter().for_each(|attributes| properties.add(attributes));
+							"[".to_string() + &fragment.vertexes.iter().filter_map(|vertex| serde_json::to_string(vertex).ok()).collect::<Vec<String>>().join(",") + "]"
 					})
 					.collect::<Vec<String>>()
 					.join(",");
-				Ok( r#"{"type":"Feature", "geometry":{"type":"MultiLineString", "coordinates":["#.to_string() + &line_string_string + "]}}")
+				Ok( r#"{"type":"Feature", "properties":"#.to_string() + &properties.to_json() + r#", "geometry":{"type":"MultiLineString", "coordinates":["# + &line_string_string + "]}}")
 			},
 			OutputFormatLines::wkt => {
 				let line_string_string = features
-					.map(|linestring|{
-							"(".to_string() + &linestring.iter().map(|vertex| format!("{} {} {}", vertex.0, vertex.1, vertex.2)).collect::<Vec<String>>().join(",") + ")"
+					.iter()
+					.map(|fragment|{
+							"(".to_string() + &fragment.vertexes.iter().map(|vertex| format!("{} {} {}", vertex.0, vertex.1, vertex.2)).collect::<Vec<String>>().join(",") + ")"
 					})
 					.collect::<Vec<String>>()
 					.join(",");
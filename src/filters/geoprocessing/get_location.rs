@@ -0,0 +1,149 @@
+use serde::Serialize;
+use nickslinetoolsrust::vector2::Vector2;
+
+use crate::data::IndexedData;
+use crate::data::cached::{Cwy, Feature};
+use crate::filters::query_parameters::QueryParametersLocate;
+use crate::helpers::{convert_degrees_to_metres, convert_metres_to_degrees, ErrorWithStaticMessage};
+
+/// The largest `radius=` accepted. The search visits every cell of the
+/// spatial index within the radius, so this keeps the work for one request
+/// small
+const MAX_RADIUS_METRES: f32 = 5000.0;
+
+#[derive(Serialize, Debug)]
+struct Location<'a> {
+	road: &'a str,
+	cwy: Cwy,
+	slk: f64,
+	/// metres from the centreline; negative to the left and positive to the
+	/// right when facing the direction of increasing SLK
+	offset: f64,
+	/// unsigned metres from the centreline
+	distance: f64,
+}
+
+/// Move `point` into a local cartesian frame measured in metres, centred on
+/// `origin`. Only suitable for short distances.
+fn to_local_metres(point: &Vector2, origin: &Vector2) -> Vector2 {
+	Vector2::new(
+		convert_degrees_to_metres(point.x - origin.x) * origin.y.to_radians().cos(),
+		convert_degrees_to_metres(point.y - origin.y),
+	)
+}
+
+/// Find the point on `feature` which is nearest to `target`
+fn locate_on_feature<'a>(feature: &'a Feature, target: &Vector2) -> Option<Location<'a>> {
+	// The target becomes the origin of the local frame
+	let vertexes: Vec<Vector2> = feature.geometry
+		.iter()
+		.map(|vertex| to_local_metres(vertex, target))
+		.collect();
+
+	let mut total_length = 0.0;
+	// (distance, length along feature, cross product)
+	let mut nearest: Option<(f64, f64, f64)> = None;
+	for segment in vertexes.windows(2) {
+		let (a, b) = (segment[0], segment[1]);
+		let (ab_x, ab_y) = (b.x - a.x, b.y - a.y);
+		let segment_length = (ab_x * ab_x + ab_y * ab_y).sqrt();
+		let fraction = if segment_length > 0.0 {
+			((-a.x * ab_x - a.y * ab_y) / (segment_length * segment_length)).clamp(0.0, 1.0)
+		} else {
+			0.0
+		};
+		let (nearest_x, nearest_y) = (a.x + ab_x * fraction, a.y + ab_y * fraction);
+		let distance = (nearest_x * nearest_x + nearest_y * nearest_y).sqrt();
+		if nearest.map_or(true, |(nearest_distance, _, _)| distance < nearest_distance) {
+			let cross = ab_x * -a.y - ab_y * -a.x;
+			nearest = Some((distance, total_length + segment_length * fraction, cross));
+		}
+		total_length += segment_length;
+	}
+
+	let (distance, length_along, cross) = nearest?;
+	let fraction_of_feature = feature.measure_fraction(if total_length > 0.0 { length_along / total_length } else { 0.0 });
+	let attributes = &feature.attributes;
+	Some(Location {
+		road: &attributes.ROAD,
+		cwy: attributes.CWY,
+		slk: attributes.START_SLK as f64 + (attributes.END_SLK - attributes.START_SLK) as f64 * fraction_of_feature,
+		// a positive cross product means the target is on the left
+		offset: if cross > 0.0 { -distance } else { distance },
+		distance,
+	})
+}
+
+/// Fail if the point is not a valid longitude / latitude or the radius is
+/// out of range
+fn check_locate(query: &QueryParametersLocate) -> Result<(), ErrorWithStaticMessage> {
+	if !(-180.0..=180.0).contains(&query.lon) {
+		return Err(ErrorWithStaticMessage::new("lon must be between -180 and 180"));
+	}
+	if !(-90.0..=90.0).contains(&query.lat) {
+		return Err(ErrorWithStaticMessage::new("lat must be between -90 and 90"));
+	}
+	if !(query.radius > 0.0 && query.radius <= MAX_RADIUS_METRES) {
+		return Err(ErrorWithStaticMessage::new("radius must be greater than 0 and at most 5000 metres"));
+	}
+	Ok(())
+}
+
+/// The nearest road to the point, serialised as JSON. `None` if there is no
+/// road within the search radius.
+pub fn get_location(
+	query: &QueryParametersLocate,
+	indexed_data: &IndexedData,
+) -> Result<Option<String>, ErrorWithStaticMessage> {
+	check_locate(query)?;
+	let target = Vector2::new(query.lon, query.lat);
+	let radius_lat = convert_metres_to_degrees(query.radius.into());
+	// near the poles the box would otherwise be unbounded east to west
+	let radius_lon = (radius_lat / query.lat.to_radians().cos()).min(180.0);
+
+	let nearest = indexed_data
+		.query_bounding_box(
+			Vector2::new(query.lon - radius_lon, query.lat - radius_lat),
+			Vector2::new(query.lon + radius_lon, query.lat + radius_lat),
+		)
+		.filter(|feature| query.road.as_ref().map_or(true, |road| &feature.attributes.ROAD == road))
+		.filter(|feature| query.cwy == feature.attributes.CWY)
+		.filter_map(|feature| locate_on_feature(feature, &target))
+		.filter(|location| location.distance <= query.radius as f64)
+		.min_by(|a, b| a.distance.total_cmp(&b.distance));
+
+	nearest
+		.map(|location| serde_json::to_string(&location)
+			.map_err(|_| ErrorWithStaticMessage::new("Unable to serialise location")))
+		.transpose()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::cached::Attributes;
+
+	#[test]
+	/// A point just north of an eastbound road is on the left
+	fn test_locate_on_feature() {
+		let feature = Feature::new(Attributes::new("H001", Cwy::Single, 1.0, 2.0), &[(115.0, -32.0), (115.02, -32.0)]);
+		let target = Vector2::new(115.005, -32.0 + convert_metres_to_degrees(10.0));
+		let location = locate_on_feature(&feature, &target).unwrap();
+		assert_eq!(location.road, "H001");
+		assert!((location.slk - 1.25).abs() < 0.001);
+		assert!((location.offset + 10.0).abs() < 0.001);
+		assert!((location.distance - 10.0).abs() < 0.001);
+	}
+
+	#[test]
+	fn test_check_locate() {
+		let check = |query: &str| check_locate(&serde_urlencoded::from_str(query).unwrap());
+		assert!(check("lon=115.89&lat=-31.97").is_ok());
+		assert!(check("lon=-180&lat=90&radius=5000").is_ok());
+		assert!(check("lon=180.5&lat=-31.97").is_err());
+		assert!(check("lon=115.89&lat=-90.1").is_err());
+		assert!(check("lon=115.89&lat=-31.97&radius=0").is_err());
+		assert!(check("lon=115.89&lat=-31.97&radius=-5").is_err());
+		assert!(check("lon=115.89&lat=-31.97&radius=5001").is_err());
+	}
+}
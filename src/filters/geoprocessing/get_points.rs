@@ -1,38 +1,30 @@
-use crate::data::IndexedData;
+use crate::data::{IndexedData, MeasuredLine};
 use crate::filters::query_parameters::{QueryParametersPoint, output_format::OutputFormatPoints};
-use nickslinetoolsrust::line_string_measured::LineStringMeasured;
 use nickslinetoolsrust::vector2::Vector2;
-use crate::helpers::{convert_metres_to_degrees, ErrorWithStaticMessage, mean_angle};
+use crate::helpers::{ErrorWithStaticMessage, mean_angle};
+use super::{offset_line, FeatureProperties};
 
 pub fn get_points(
 	query: &QueryParametersPoint,
 	indexed_data: &IndexedData,
 ) -> Result<String, ErrorWithStaticMessage> {
-	let features = indexed_data.query(&query.road, &query.cwy)?
-		.filter_map(|item| {
-			if item.attributes.END_SLK >= query.slk && item.attributes.START_SLK <= query.slk {
-				let lsm: LineStringMeasured = LineStringMeasured::from(&item.geometry);
-				let item_len_km = item.attributes.END_SLK - item.attributes.START_SLK;
-				let frac = (query.slk - item.attributes.START_SLK) / item_len_km;
+	let features = indexed_data.query_range(&query.road, &query.cwy, &query.measure, query.slk, query.slk)?
+		.filter(|(item, _)| query.includes_attributes(&item.attributes))
+		.filter_map(|(item, measured_line)| {
+			let (item_start, item_end) = query.measure.feature_range(&item.attributes)?;
+			if item_end >= query.slk && item_start <= query.slk {
+				let item_len_km = item_end - item_start;
+				let frac = measured_line.length_fraction(((query.slk - item_start) / item_len_km).into());
 
 				// support offset
-				let lsmo:Option<LineStringMeasured> = if query.offset == 0.0 {
-					Some(lsm)
+				let point = if query.offset == 0.0 {
+					measured_line.interpolate(&item.geometry, frac)
 				}else{
-					let degree_offset:f64 = -convert_metres_to_degrees(query.offset.into());
-					match lsm.offset_basic(degree_offset) {
-						Some(vec_of_vector2)=> Some(LineStringMeasured::from(vec_of_vector2)),
-						None=>None
-					}
+					let offset_geometry = offset_line(&item.geometry, query.offset.into())?;
+					MeasuredLine::from_geometry(&offset_geometry).interpolate(&offset_geometry, frac)
 				};
-				
-				match lsmo {
-					Some(lsm)=> match lsm.interpolate(frac as f64) {
-						Some(vec)=>Some((vec, lsm.direction(frac as f64))),
-						None=>None
-					},
-					_=>None
-				}
+
+				point.map(|(vec, direction)| (vec, direction, &item.attributes))
 
 			} else {
 				None
@@ -42,7 +34,7 @@ pub fn get_points(
 	match query.f {
 		OutputFormatPoints::json => {
 			let points = features
-				.filter_map(|(vertex, _dir)| serde_json::to_string(&vertex).ok())
+				.filter_map(|(vertex, _dir, _)| serde_json::to_string(&vertex).ok())
 				.collect::<Vec<String>>()
 				.join(",");
 			if points.len()>0{
@@ -52,14 +44,20 @@ pub fn get_points(
 			}
 		}
 		OutputFormatPoints::geojson => {
+			let mut properties = FeatureProperties::default();
 			let points = features
-				.filter_map(|(vertex, _dir)| serde_json::to_string(&vertex).ok())
+				.filter_map(|(vertex, _dir, attributes)| {
+					properties.add(attributes);
+					serde_json::to_string(&vertex).ok()
+				})
 				.collect::<Vec<String>>()
 				.join(",");
 			if points.len()>0{
 				Ok(
-					r#"{"type":"Feature", "geometry":{"type":"MultiPoint", "coordinates":["#
+					r#"{"type":"Feature", "properties":"#
 						.to_string()
+						+ &properties.to_json()
+						+ r#", "geometry":{"type":"MultiPoint", "coordinates":["#
 						+ &points
 						+ "]}}",
 				)
@@ -69,7 +67,7 @@ pub fn get_points(
 		}
 		OutputFormatPoints::wkt => {
 			let points = features
-				.map(|(vertex, _dir)| format!("({} {})", vertex.x, vertex.y))
+				.map(|(vertex, _dir, _)| format!("({} {})", vertex.x, vertex.y))
 				.collect::<Vec<String>>()
 				.join(",");
 			if points.len()>0{
@@ -79,7 +77,7 @@ pub fn get_points(
 			}
 		}
 		OutputFormatPoints::latlon => {
-			let vertexes: Vec<(Vector2,f64)> = features.collect();
+			let vertexes: Vec<(Vector2,f64)> = features.map(|(vertex, dir, _)| (vertex, dir)).collect();
 			if vertexes.len()>0{
 				let point = vertexes
 					.iter()
@@ -91,7 +89,7 @@ pub fn get_points(
 			}
 		},
 		OutputFormatPoints::latlondir => {
-			let vertexes: Vec<(Vector2,f64)> = features.collect();
+			let vertexes: Vec<(Vector2,f64)> = features.map(|(vertex, dir, _)| (vertex, dir)).collect();
 			if vertexes.len()>0{
 				let point = vertexes
 					.iter()
@@ -0,0 +1,185 @@
+use nickslinetoolsrust::vector2::Vector2;
+
+use crate::data::IndexedData;
+use crate::filters::query_parameters::{QueryParametersLine, output_format::OutputFormatLines};
+use crate::helpers::ErrorWithStaticMessage;
+use super::{check_simplify, merge_fragments, offset_line, simplify_and_densify, FeatureProperties, Fragment};
+
+/// Twice the signed area of a ring; positive when it runs counter clockwise
+fn signed_area(ring: &[Vector2]) -> f64 {
+    ring.windows(2)
+        .map(|segment| segment[0].x * segment[1].y - segment[1].x * segment[0].y)
+        .sum()
+}
+
+/// The outline of the area between two offsets of `fragment`, with square
+/// ends. The ring is closed and runs counter clockwise as GeoJSON requires.
+/// `None` if either side can not be offset.
+fn ribbon(fragment: &[Vector2], (offset_a, offset_b): (f32, f32)) -> Option<Vec<Vector2>> {
+    if fragment.len() < 2 {
+        return None;
+    }
+    let side = |offset: f32| if offset == 0.0 {
+        Some(fragment.to_vec())
+    } else {
+        offset_line(fragment, offset.into())
+    };
+    let mut ring = side(offset_a)?;
+    ring.extend(side(offset_b)?.into_iter().rev());
+    ring.push(*ring.first()?);
+    if signed_area(&ring) < 0.0 {
+        ring.reverse();
+    }
+    Some(ring)
+}
+
+/// Fail if `query` asks for a polygon which can not be made
+fn check_polygon(query: &QueryParametersLine, (offset_a, offset_b): (f32, f32)) -> Result<(), ErrorWithStaticMessage> {
+    if query.buffer.is_some() && (query.offset_left.is_some() || query.offset_right.is_some()) {
+        return Err(ErrorWithStaticMessage::new("Use either buffer or offset_left and offset_right, not both"));
+    }
+    if query.is_tapered() {
+        return Err(ErrorWithStaticMessage::new("offset_from and offset_to can not be used with buffer, offset_left or offset_right"));
+    }
+    if query.m {
+        return Err(ErrorWithStaticMessage::new("m=true can not be used with buffer, offset_left or offset_right"));
+    }
+    if offset_a == offset_b {
+        return Err(ErrorWithStaticMessage::new("The polygon has no width. buffer must not be zero and offset_left and offset_right must not be the same line"));
+    }
+    Ok(())
+}
+
+/// Like [super::get_linestring], but returns the area between the two
+/// offsets of [QueryParametersLine::polygon_offsets] as a polygon for each
+/// part of the road. With `merge=true` the parts are joined before they are
+/// offset, so each continuous run of road makes one polygon. `simplify=` and
+/// `densify=` are applied to the outline of each polygon after offsetting.
+pub fn get_polygon(query:&QueryParametersLine, indexed_data:&IndexedData)->Result<String, ErrorWithStaticMessage>{
+    let polygon_offsets = query.polygon_offsets().ok_or(ErrorWithStaticMessage::new("No polygon requested"))?;
+    check_polygon(query, polygon_offsets)?;
+    check_simplify(query)?;
+
+    let road_data = indexed_data.query_range(&query.road, &query.cwy, &query.measure, query.slk_from, query.slk_to)?;
+
+    let fragments = road_data
+        .filter(|(item, _)| query.includes_attributes(&item.attributes))
+        .filter_map(|(item, measured_line)|{
+            let (item_start, item_end) = query.measure.feature_range(&item.attributes)?;
+            if item_end>query.slk_from && item_start<query.slk_to{
+
+                let item_len_km = item_end - item_start;
+                let frac_start = measured_line.length_fraction(((query.slk_from-item_start) / item_len_km).into());
+                let frac_end = measured_line.length_fraction(((query.slk_to-item_start) / item_len_km).into());
+
+                let range = (query.slk_from.max(item_start), query.slk_to.min(item_end));
+
+                measured_line
+                    .cut(&item.geometry, frac_start, frac_end)
+                    .map(|vertexes| Fragment::new(vertexes, &item.attributes, range))
+            }else{
+                None
+            }
+        });
+
+    let (fragments, discontinuities) = if query.merge {
+        let (merged, discontinuities) = merge_fragments(fragments, |vertex| *vertex, query.merge_tolerance.into());
+        (merged, Some(discontinuities))
+    }else{
+        (fragments.collect::<Vec<_>>(), None)
+    };
+
+    let mut polygons: Vec<(Vec<Vector2>, &Vec<_>)> = fragments
+        .iter()
+        .filter_map(|fragment| ribbon(&fragment.vertexes, polygon_offsets).map(|ring| (ring, &fragment.attributes)))
+        .collect();
+
+    simplify_and_densify(
+        query,
+        polygons.iter_mut().map(|(ring, _)| ring),
+        |vertex| *vertex,
+        |a, b, fraction| Vector2::new(a.x + (b.x - a.x) * fraction, a.y + (b.y - a.y) * fraction),
+    )?;
+
+    match query.f{
+        OutputFormatLines::json => {
+            let polygon_string = polygons
+                .iter()
+                .map(|(ring, _)|{
+                    "[[".to_string() + &ring.iter().filter_map(|vertex| serde_json::to_string(vertex).ok()).collect::<Vec<String>>().join(",") + "]]"
+                })
+                .collect::<Vec<String>>()
+                .join(",");
+            Ok("[".to_string() + &polygon_string + "]")
+        },
+        OutputFormatLines::geojson => {
+            let mut properties = FeatureProperties::default();
+            properties.set_discontinuities(discontinuities);
+            let polygon_strings = polygons
+                .iter()
+                .map(|(ring, attributes)|{
+                    attributes.iter().for_each(|attributes| properties.add(attributes));
+                    "[[".to_string() + &ring.iter().filter_map(|vertex| serde_json::to_string(vertex).ok()).collect::<Vec<String>>().join(",") + "]]"
+                })
+                .collect::<Vec<String>>();
+            let geometry = if polygon_strings.len() == 1 {
+                r#"{"type":"Polygon", "coordinates":"#.to_string() + &polygon_strings[0] + "}"
+            } else {
+                r#"{"type":"MultiPolygon", "coordinates":["#.to_string() + &polygon_strings.join(",") + "]}"
+            };
+            Ok(r#"{"type":"Feature", "properties":"#.to_string() + &properties.to_json() + r#", "geometry":"# + &geometry + "}")
+        },
+        OutputFormatLines::wkt => {
+            let polygon_strings = polygons
+                .iter()
+                .map(|(ring, _)|{
+                    "((".to_string() + &ring.iter().map(|vertex| format!("{} {}", vertex.x, vertex.y)).collect::<Vec<String>>().join(",") + "))"
+                })
+                .collect::<Vec<String>>();
+            Ok(match polygon_strings.len() {
+                0 => "MULTIPOLYGON EMPTY".to_string(),
+                1 => "POLYGON ".to_string() + &polygon_strings[0],
+                _ => "MULTIPOLYGON (".to_string() + &polygon_strings.join(",") + ")",
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::geodesic_distance_metres;
+
+    #[test]
+    fn test_ribbon() {
+        // heading north
+        let fragment = [Vector2::new(115.86, -31.95), Vector2::new(115.86, -31.949)];
+        for offsets in [(-3.5, 3.5), (0.0, 2.0), (-5.0, -1.0)] {
+            let ring = ribbon(&fragment, offsets).unwrap();
+            assert_eq!(ring.len(), 5);
+            assert_eq!((ring[0].x, ring[0].y), (ring[4].x, ring[4].y));
+            assert!(signed_area(&ring) > 0.0, "{offsets:?}");
+            // the ends are as wide as the gap between the offsets
+            let width = geodesic_distance_metres(&ring[0], &ring[1]).unwrap()
+                .min(geodesic_distance_metres(&ring[0], &ring[3]).unwrap());
+            assert!((width - (offsets.1 - offsets.0) as f64).abs() < 0.001, "{offsets:?}: {width}");
+        }
+        assert!(ribbon(&fragment[..1], (0.0, 1.0)).is_none());
+    }
+
+    #[test]
+    fn test_check_polygon() {
+        let check = |query: &str| {
+            let query: QueryParametersLine = serde_urlencoded::from_str(query).unwrap();
+            check_polygon(&query, query.polygon_offsets().unwrap())
+        };
+        assert!(check("road=H001&buffer=2").is_ok());
+        assert!(check("road=H001&offset_left=2&offset_right=1").is_ok());
+        assert!(check("road=H001&offset_left=2&offset_right=-2").is_err());
+        assert!(check("road=H001&buffer=0").is_err());
+        assert!(check("road=H001&offset_right=0").is_err());
+        assert!(check("road=H001&buffer=2&offset_left=1").is_err());
+        assert!(check("road=H001&slk_from=1&slk_to=2&buffer=2&offset_to=3").is_err());
+        assert!(check("road=H001&buffer=2&m=true").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use serde::Serialize;
+use nickslinetoolsrust::vector2::Vector2;
+
+use crate::data::IndexedData;
+use crate::data::cached::{Cwy, Feature};
+use crate::helpers::{distance_metres, ErrorWithStaticMessage};
+
+/// Features whose SLKs are closer than this (half a metre) are treated as
+/// continuous. SLKs are stored as f32 so are not always exactly equal.
+const SLK_GAP_TOLERANCE_KM: f32 = 0.0005;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+struct SlkRange {
+	slk_from: f32,
+	slk_to: f32,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct CwyMetadata {
+	cwy: Cwy,
+	slk_from: f32,
+	slk_to: f32,
+	/// The sum of `END_SLK - START_SLK` of every feature
+	slk_length: f32,
+	/// The sum of the geometric length of every feature in metres
+	length_m: f64,
+	feature_count: usize,
+	/// SLK ranges which are not covered by any feature of this carriageway
+	gaps: Vec<SlkRange>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct RoadMetadata<'a> {
+	road: &'a str,
+	carriageways: Vec<CwyMetadata>,
+	/// SLK ranges where the road has a single carriageway
+	single: Vec<SlkRange>,
+	/// SLK ranges where the road has a left or right carriageway
+	dual: Vec<SlkRange>,
+}
+
+/// Sort ranges and join any which touch or overlap
+fn merge_ranges(mut ranges: Vec<SlkRange>) -> Vec<SlkRange> {
+	ranges.sort_by(|a, b| a.slk_from.total_cmp(&b.slk_from));
+	let mut merged: Vec<SlkRange> = Vec::with_capacity(ranges.len());
+	for range in ranges {
+		match merged.last_mut() {
+			Some(last) if range.slk_from <= last.slk_to + SLK_GAP_TOLERANCE_KM => {
+				last.slk_to = last.slk_to.max(range.slk_to);
+			},
+			_ => merged.push(range),
+		}
+	}
+	merged
+}
+
+fn feature_range(feature: &Feature) -> SlkRange {
+	SlkRange {
+		slk_from: feature.attributes.START_SLK,
+		slk_to: feature.attributes.END_SLK,
+	}
+}
+
+/// Approximate length in metres of a linestring stored in degrees
+fn geometric_length_metres(geometry: &[Vector2]) -> f64 {
+	geometry
+		.windows(2)
+		.map(|segment| distance_metres(&segment[0], &segment[1]))
+		.sum()
+}
+
+fn cwy_metadata(cwy: Cwy, features: &[&Feature]) -> CwyMetadata {
+	let covered = merge_ranges(features.iter().copied().map(feature_range).collect());
+	let gaps = covered
+		.windows(2)
+		.map(|pair| SlkRange { slk_from: pair[0].slk_to, slk_to: pair[1].slk_from })
+		.collect();
+	CwyMetadata {
+		cwy,
+		slk_from: covered.first().map_or(0.0, |range| range.slk_from),
+		slk_to: covered.last().map_or(0.0, |range| range.slk_to),
+		slk_length: features.iter().map(|feature| feature.attributes.END_SLK - feature.attributes.START_SLK).sum(),
+		length_m: features.iter().map(|feature| geometric_length_metres(&feature.geometry)).sum(),
+		feature_count: features.len(),
+		gaps,
+	}
+}
+
+fn road_metadata<'a>(road: &'a str, features_by_cwy: &[(Cwy, Vec<&Feature>)]) -> RoadMetadata<'a> {
+	let ranges_where = |is_included: fn(&Cwy) -> bool| merge_ranges(
+		features_by_cwy
+			.iter()
+			.filter(|(cwy, _)| is_included(cwy))
+			.flat_map(|(_, features)| features.iter().copied().map(feature_range))
+			.collect()
+	);
+	RoadMetadata {
+		road,
+		carriageways: features_by_cwy
+			.iter()
+			.map(|(cwy, features)| cwy_metadata(*cwy, features))
+			.collect(),
+		single: ranges_where(|cwy| *cwy == Cwy::Single),
+		dual: ranges_where(|cwy| *cwy != Cwy::Single),
+	}
+}
+
+pub fn get_road_metadata(
+	road: &String,
+	indexed_data: &IndexedData,
+) -> Result<String, ErrorWithStaticMessage> {
+	let features_by_cwy = indexed_data.query_by_cwy(road)?;
+	serde_json::to_string(&road_metadata(road, &features_by_cwy))
+		.map_err(|_| ErrorWithStaticMessage::new("Unable to serialise road metadata"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::cached::Attributes;
+
+	fn feature(cwy: Cwy, start_slk: f32, end_slk: f32) -> Feature {
+		Feature::new(Attributes::new("H001", cwy, start_slk, end_slk), &[(115.0, -32.0), (115.01, -32.0)])
+	}
+
+	fn range(slk_from: f32, slk_to: f32) -> SlkRange {
+		SlkRange { slk_from, slk_to }
+	}
+
+	#[test]
+	fn test_merge_ranges() {
+		assert_eq!(
+			merge_ranges(vec![range(2.0, 3.0), range(0.0, 1.0), range(1.0, 1.5), range(2.5, 4.0)]),
+			vec![range(0.0, 1.5), range(2.0, 4.0)]
+		);
+		assert_eq!(merge_ranges(vec![]), vec![]);
+	}
+
+	#[test]
+	fn test_road_metadata() {
+		// Single from 0 to 2, dual from 2 to 4 with a gap in the left
+		// carriageway, then single again from 4 to 5
+		let left = [feature(Cwy::Left, 2.0, 2.5), feature(Cwy::Left, 3.0, 4.0)];
+		let right = [feature(Cwy::Right, 2.0, 4.0)];
+		let single = [feature(Cwy::Single, 4.0, 5.0), feature(Cwy::Single, 0.0, 2.0)];
+		let metadata = road_metadata("H001", &[
+			(Cwy::Left, left.iter().collect()),
+			(Cwy::Right, right.iter().collect()),
+			(Cwy::Single, single.iter().collect()),
+		]);
+
+		assert_eq!(metadata.single, vec![range(0.0, 2.0), range(4.0, 5.0)]);
+		assert_eq!(metadata.dual, vec![range(2.0, 4.0)]);
+
+		let left = &metadata.carriageways[0];
+		assert_eq!(left.cwy, Cwy::Left);
+		assert_eq!((left.slk_from, left.slk_to), (2.0, 4.0));
+		assert_eq!(left.slk_length, 1.5);
+		assert_eq!(left.feature_count, 2);
+		assert_eq!(left.gaps, vec![range(2.5, 3.0)]);
+		// 0.01 degrees of longitude at 32 degrees south is about 944m
+		assert!((left.length_m - 2.0 * 944.0).abs() < 2.0);
+
+		let single = &metadata.carriageways[2];
+		assert_eq!((single.slk_from, single.slk_to), (0.0, 5.0));
+		assert_eq!(single.gaps, vec![range(2.0, 4.0)]);
+	}
+}
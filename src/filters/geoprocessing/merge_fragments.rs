@@ -0,0 +1,131 @@
+use serde::Serialize;
+use nickslinetoolsrust::vector2::Vector2;
+
+use crate::data::cached::{Attributes, Cwy};
+use crate::helpers::distance_metres;
+
+/// One line of a line query result: the part of a feature between `from` and
+/// `to` (in the requested measure), or several of them joined end to end by
+/// [merge_fragments]
+pub struct Fragment<'a, V> {
+    pub vertexes: Vec<V>,
+    /// The attributes of each feature the fragment was cut from
+    pub attributes: Vec<&'a Attributes>,
+    pub cwy: Cwy,
+    pub from: f32,
+    pub to: f32,
+}
+
+impl<'a, V> Fragment<'a, V> {
+    pub fn new(vertexes: Vec<V>, attributes: &'a Attributes, (from, to): (f32, f32)) -> Self {
+        Self {
+            vertexes,
+            attributes: vec![attributes],
+            cwy: attributes.CWY,
+            from,
+            to,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+/// A break left in a merged line between two consecutive fragments of the
+/// same carriageway, returned in the GeoJSON `properties`
+pub struct Discontinuity {
+    pub cwy: Cwy,
+    /// Where the line before the break ends, in the requested measure
+    pub from: f32,
+    /// Where the line after the break starts
+    pub to: f32,
+    /// The distance between the two ends
+    pub metres: f64,
+}
+
+/// Join each fragment onto the one before it if both are on the same
+/// carriageway and the end of one is within `tolerance` metres of the start
+/// of the next. `position` gives the longitude / latitude of a vertex.
+///
+/// The fragments are first sorted by carriageway and then by `from`, since
+/// the features of a carriageway are not necessarily stored in SLK order.
+/// When two fragments are joined, the end vertex of the first is replaced by
+/// the start vertex of the second so that the small gap is closed without
+/// adding a short segment.
+///
+/// Returns the joined lines, and the breaks left between consecutive
+/// fragments of the same carriageway which were too far apart to join.
+pub fn merge_fragments<'a, V>(
+    fragments: impl IntoIterator<Item = Fragment<'a, V>>,
+    position: impl Fn(&V) -> Vector2,
+    tolerance: f64,
+) -> (Vec<Fragment<'a, V>>, Vec<Discontinuity>) {
+    let mut fragments: Vec<Fragment<'a, V>> = fragments.into_iter().collect();
+    fragments.sort_by(|a, b| a.cwy.cmp(&b.cwy).then(a.from.total_cmp(&b.from)));
+    let mut merged: Vec<Fragment<'a, V>> = Vec::new();
+    let mut discontinuities = Vec::new();
+    for fragment in fragments {
+        let previous = merged.last_mut().filter(|previous| previous.cwy == fragment.cwy);
+        if let Some(previous) = previous {
+            if let (Some(end), Some(start)) = (previous.vertexes.last(), fragment.vertexes.first()) {
+                let gap = distance_metres(&position(end), &position(start));
+                if gap <= tolerance {
+                    previous.vertexes.pop();
+                    previous.vertexes.extend(fragment.vertexes);
+                    previous.attributes.extend(fragment.attributes);
+                    previous.to = fragment.to;
+                    continue;
+                }
+                discontinuities.push(Discontinuity {
+                    cwy: fragment.cwy,
+                    from: previous.to,
+                    to: fragment.from,
+                    metres: gap,
+                });
+            }
+        }
+        merged.push(fragment);
+    }
+    (merged, discontinuities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::convert_metres_to_degrees;
+
+    fn attributes(cwy: Cwy) -> Attributes {
+        Attributes::new("H001", cwy, 0.0, 1.0)
+    }
+
+    #[test]
+    fn test_merge_fragments() {
+        let left = attributes(Cwy::Left);
+        let right = attributes(Cwy::Right);
+        let step = convert_metres_to_degrees(100.0);
+        let point = |metres: f64| (115.0, -32.0 + convert_metres_to_degrees(metres));
+        // given out of order, as features may be stored
+        let fragments = vec![
+            // another carriageway is never joined or reported
+            Fragment::new(vec![point(400.0), point(400.0 + step)], &right, (0.4, 0.5)),
+            // starts half a metre from the end of the one before
+            Fragment::new(vec![point(200.5), point(300.0)], &left, (0.2, 0.3)),
+            Fragment::new(vec![point(0.0), point(100.0)], &left, (0.0, 0.1)),
+            // a gap of 50 metres
+            Fragment::new(vec![point(350.0), point(400.0)], &left, (0.35, 0.4)),
+            // shares a vertex with the one before
+            Fragment::new(vec![point(100.0), point(200.0)], &left, (0.1, 0.2)),
+        ];
+        let (merged, discontinuities) = merge_fragments(fragments, |vertex| Vector2::new(vertex.0, vertex.1), 1.0);
+
+        assert_eq!(merged.len(), 3);
+        // the end at 200 m is moved onto the start at 200.5 m
+        assert_eq!(merged[0].vertexes, vec![point(0.0), point(100.0), point(200.5), point(300.0)]);
+        assert_eq!(merged[0].attributes.len(), 3);
+        assert_eq!((merged[0].from, merged[0].to), (0.0, 0.3));
+        assert_eq!((merged[1].from, merged[1].to), (0.35, 0.4));
+        assert_eq!(merged[2].cwy, Cwy::Right);
+
+        assert_eq!(discontinuities.len(), 1);
+        assert_eq!((discontinuities[0].cwy, discontinuities[0].from, discontinuities[0].to), (Cwy::Left, 0.3, 0.35));
+        assert!((discontinuities[0].metres - 50.0).abs() < 0.5);
+    }
+}
@@ -1,8 +1,29 @@
+mod feature_properties;
+use feature_properties::FeatureProperties;
+
+mod merge_fragments;
+use merge_fragments::{merge_fragments, Fragment};
+
+mod offset_line;
+use offset_line::{check_offset, offset_fragment, offset_line};
+
+mod simplify_line;
+use simplify_line::{check_simplify, simplify_and_densify};
+
 mod get_linestring;
 pub use get_linestring::get_linestring;
 
 mod get_linestring_m;
 pub use get_linestring_m::get_linestring_m;
 
+mod get_polygon;
+use get_polygon::get_polygon;
+
 mod get_points;
-pub use get_points::get_points;
\ No newline at end of file
+pub use get_points::get_points;
+
+mod get_location;
+pub use get_location::get_location;
+
+mod get_road_metadata;
+pub use get_road_metadata::get_road_metadata;
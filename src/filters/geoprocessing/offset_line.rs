@@ -0,0 +1,221 @@
+use nickslinetoolsrust::line_string_measured::LineStringMeasured;
+use nickslinetoolsrust::vector2::Vector2;
+
+use crate::data::MeasuredLine;
+use crate::filters::query_parameters::QueryParametersLine;
+use crate::helpers::{ErrorWithStaticMessage, LocalFrame};
+
+/// Corners sharper than this (the cosine of half the turn) are not extended
+/// any further, so that a tapered offset around a hairpin bend does not shoot
+/// off into the distance
+const MIN_MITRE_COSINE: f64 = 0.25;
+
+/// Offset a line stored in longitude / latitude degrees by `offset` metres,
+/// using the same sign as the `offset=` query parameter. The line is moved
+/// into a [LocalFrame] centred on it so that the offset is the same distance
+/// on the ground whichever way the road runs. `None` if the line can not be
+/// offset.
+pub fn offset_line(vertexes: &[Vector2], offset: f64) -> Option<Vec<Vector2>> {
+	let frame = LocalFrame::around(vertexes)?;
+	let vertexes_in_metres: Vec<Vector2> = vertexes.iter().map(|vertex| frame.to_metres(vertex)).collect();
+	let offset_in_metres = LineStringMeasured::from(vertexes_in_metres).offset_basic(-offset)?;
+	Some(offset_in_metres.iter().map(|vertex| frame.to_degrees(vertex)).collect())
+}
+
+/// Offset each vertex of a line stored in longitude / latitude degrees by its
+/// own number of metres from `offsets`, using the same sign as the `offset=`
+/// query parameter. Each vertex is moved along the bisector of the segments
+/// either side of it, far enough that both segments are offset by the right
+/// distance. `None` if the line has no length.
+pub fn offset_line_tapered(vertexes: &[Vector2], offsets: &[f64]) -> Option<Vec<Vector2>> {
+	let frame = LocalFrame::around(vertexes)?;
+	let vertexes_in_metres: Vec<Vector2> = vertexes.iter().map(|vertex| frame.to_metres(vertex)).collect();
+	// The unit vector to the right of each segment. `None` for segments with
+	// no length.
+	let normals: Vec<Option<Vector2>> = vertexes_in_metres
+		.windows(2)
+		.map(|segment| {
+			let (dx, dy) = (segment[1].x - segment[0].x, segment[1].y - segment[0].y);
+			let length = dx.hypot(dy);
+			(length > 0.0).then(|| Vector2::new(dy / length, -dx / length))
+		})
+		.collect();
+	vertexes_in_metres
+		.iter()
+		.zip(offsets)
+		.enumerate()
+		.map(|(index, (vertex, offset))| {
+			let before = normals[..index].iter().rev().find_map(|normal| *normal);
+			let after = normals[index..].iter().find_map(|normal| *normal);
+			let direction = match (before, after) {
+				(Some(before), Some(after)) => {
+					let (x, y) = (before.x + after.x, before.y + after.y);
+					let length = x.hypot(y);
+					if length > 0.0 {
+						let cosine = ((x * after.x + y * after.y) / length).max(MIN_MITRE_COSINE);
+						Vector2::new(x / length / cosine, y / length / cosine)
+					} else {
+						// the line doubles back on itself
+						after
+					}
+				},
+				(Some(normal), None) | (None, Some(normal)) => normal,
+				(None, None) => return None,
+			};
+			Some(frame.to_degrees(&Vector2::new(vertex.x + direction.x * offset, vertex.y + direction.y * offset)))
+		})
+		.collect()
+}
+
+/// Fail if `query` asks for a tapered offset without a finite `slk_from` and
+/// `slk_to` to interpolate it between
+pub fn check_offset(query: &QueryParametersLine) -> Result<(), ErrorWithStaticMessage> {
+	if query.is_tapered() && !(query.slk_from.is_finite() && query.slk_to.is_finite()) {
+		return Err(ErrorWithStaticMessage::new("offset_from and offset_to can only be used with a finite slk_from and slk_to"));
+	}
+	Ok(())
+}
+
+/// Offset `fragment`, the part of a feature cut out by a line query, by the
+/// offset requested in `query`. `feature_range` is the start and end of the
+/// feature in the requested measure, and `length_fraction_range` is where
+/// the fragment was cut from as fractions of the length of the feature.
+///
+/// A tapered offset is worked out for each vertex from its SLK (or true
+/// distance), so it changes smoothly across fragments cut from neighbouring
+/// features.
+pub fn offset_fragment(
+	query: &QueryParametersLine,
+	feature_range: (f32, f32),
+	measured_line: &MeasuredLine,
+	length_fraction_range: (f64, f64),
+	fragment: &[Vector2],
+) -> Option<Vec<Vector2>> {
+	if !query.is_tapered() {
+		return offset_line(fragment, query.offset_range().0.into());
+	}
+	let length_fraction_start = length_fraction_range.0.clamp(0.0, 1.0);
+	let length_fraction_end = length_fraction_range.1.clamp(0.0, 1.0);
+	let mut total_length = 0.0;
+	let lengths_along: Vec<f64> = fragment
+		.iter()
+		.enumerate()
+		.map(|(index, vertex)| {
+			if index > 0 {
+				let previous = fragment[index - 1];
+				total_length += (vertex.x - previous.x).hypot(vertex.y - previous.y);
+			}
+			total_length
+		})
+		.collect();
+	let (feature_start, feature_end) = (feature_range.0 as f64, feature_range.1 as f64);
+	let offsets: Vec<f64> = lengths_along
+		.iter()
+		.map(|length_along| {
+			let fraction_of_fragment = if total_length > 0.0 { length_along / total_length } else { 0.0 };
+			let length_fraction = length_fraction_start + (length_fraction_end - length_fraction_start) * fraction_of_fragment;
+			let measure_fraction = measured_line.measure_fraction(length_fraction);
+			query.offset_at(feature_start + (feature_end - feature_start) * measure_fraction)
+		})
+		.collect();
+	offset_line_tapered(fragment, &offsets)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::helpers::{convert_metres_to_degrees, geodesic_distance_metres};
+
+	/// Positive if `point` is to the left of the line from `a` to `b`
+	fn side(a: &Vector2, b: &Vector2, point: &Vector2) -> f64 {
+		(b.x - a.x) * (point.y - a.y) - (b.y - a.y) * (point.x - a.x)
+	}
+
+	#[test]
+	fn test_offset_line_matches_geodesic_distance() {
+		let start = Vector2::new(115.86, -31.95);
+		// north, east, north east, south west and a steep diagonal, about
+		// 100 m long
+		for (index, direction) in [(0.0, 1.0), (1.0, 0.0), (1.0, 1.0), (-1.0, -1.0), (0.2, 1.0)].iter().enumerate() {
+			let end = Vector2::new(start.x + direction.0 * 0.001, start.y + direction.1 * 0.001);
+			for offset in [-10.0, 3.5, 10.0, 40.0] {
+				let offset_vertexes = offset_line(&[start, end], offset).unwrap();
+				assert_eq!(offset_vertexes.len(), 2);
+				for (original, moved) in [start, end].iter().zip(offset_vertexes.iter()) {
+					let distance = geodesic_distance_metres(original, moved).unwrap();
+					assert!((distance - offset.abs()).abs() < 0.001, "direction {index}, offset {offset}: {distance}");
+				}
+				// the same side as the old approximation in degrees
+				let approximate = LineStringMeasured::from(vec![start, end])
+					.offset_basic(-convert_metres_to_degrees(offset))
+					.unwrap();
+				assert_eq!(
+					side(&start, &end, &offset_vertexes[0]).signum(),
+					side(&start, &end, &approximate[0]).signum(),
+					"direction {index}, offset {offset}"
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_offset_line_empty() {
+		assert!(offset_line(&[], 10.0).is_none());
+		assert!(offset_line_tapered(&[], &[]).is_none());
+		let point = Vector2::new(115.86, -31.95);
+		assert!(offset_line_tapered(&[point, point], &[1.0, 1.0]).is_none());
+	}
+
+	#[test]
+	fn test_offset_line_tapered() {
+		// north for about 110 m, then east for about 95 m
+		let start = Vector2::new(115.86, -31.95);
+		let corner = Vector2::new(115.86, -31.949);
+		let end = Vector2::new(115.861, -31.949);
+
+		let straight = offset_line_tapered(&[start, corner], &[2.0, 10.0]).unwrap();
+		assert!((geodesic_distance_metres(&start, &straight[0]).unwrap() - 2.0).abs() < 0.001);
+		assert!((geodesic_distance_metres(&corner, &straight[1]).unwrap() - 10.0).abs() < 0.001);
+		// positive is to the right; east when heading north
+		assert!(straight[0].x > start.x && straight[1].x > corner.x);
+
+		let bent = offset_line_tapered(&[start, corner, end], &[10.0, 10.0, -10.0]).unwrap();
+		// the corner is moved diagonally so both segments are 10 m away
+		let corner_distance = geodesic_distance_metres(&corner, &bent[1]).unwrap();
+		assert!((corner_distance - 10.0 * 2f64.sqrt()).abs() < 0.001, "{corner_distance}");
+		assert!(bent[1].x > corner.x && bent[1].y < corner.y);
+		// negative is to the left; north when heading east
+		assert!((geodesic_distance_metres(&end, &bent[2]).unwrap() - 10.0).abs() < 0.001);
+		assert!(bent[2].y > end.y);
+
+		// repeated vertexes are skipped when finding the direction
+		let repeated = offset_line_tapered(&[start, start, corner], &[5.0, 5.0, 5.0]).unwrap();
+		assert!(repeated.iter().zip([start, start, corner]).all(|(moved, original)| (geodesic_distance_metres(&original, moved).unwrap() - 5.0).abs() < 0.001));
+	}
+
+	#[test]
+	fn test_offset_fragment() {
+		use crate::data::cached::{Attributes, Cwy, Feature};
+
+		let start = Vector2::new(115.86, -31.95);
+		let feature = Feature::new(Attributes::new("H001", Cwy::Single, 1.0, 2.0), &[(start.x, start.y), (115.86, -31.945), (115.86, -31.94)]);
+		let measured_line = MeasuredLine::new(&feature);
+		let query: QueryParametersLine = serde_urlencoded::from_str("road=H001&slk_from=0&slk_to=2&offset_from=0&offset_to=20").unwrap();
+
+		// the second half of the feature, from SLK 1.5 to 2.0
+		let fragment = [feature.geometry[1], feature.geometry[2]];
+		let offset = offset_fragment(&query, (1.0, 2.0), &measured_line, (0.5, 1.0), &fragment).unwrap();
+		assert!((geodesic_distance_metres(&fragment[0], &offset[0]).unwrap() - 15.0).abs() < 0.001);
+		assert!((geodesic_distance_metres(&fragment[1], &offset[1]).unwrap() - 20.0).abs() < 0.001);
+
+		// a constant offset
+		let query: QueryParametersLine = serde_urlencoded::from_str("road=H001&offset_from=3&offset_to=3").unwrap();
+		assert!(check_offset(&query).is_ok());
+		let offset = offset_fragment(&query, (1.0, 2.0), &measured_line, (0.5, 1.0), &fragment).unwrap();
+		assert!((geodesic_distance_metres(&fragment[1], &offset[1]).unwrap() - 3.0).abs() < 0.001);
+
+		// a taper needs somewhere to start and end
+		let query: QueryParametersLine = serde_urlencoded::from_str("road=H001&slk_to=2&offset_to=3").unwrap();
+		assert!(check_offset(&query).is_err());
+	}
+}
@@ -0,0 +1,237 @@
+use nickslinetoolsrust::vector2::Vector2;
+
+use crate::filters::query_parameters::QueryParametersLine;
+use crate::helpers::{ErrorWithStaticMessage, LocalFrame};
+
+/// The smallest `densify=` accepted, so that a query for a whole road can not
+/// ask for an unreasonable number of vertexes
+const MIN_DENSIFY_METRES: f32 = 1.0;
+
+/// The most vertexes `densify=` may make for one query. Checked before the
+/// lines are densified, since a small `densify=` over a long road could
+/// otherwise make a response of any size.
+const MAX_DENSIFIED_VERTEXES: usize = 100_000;
+
+/// Fail if `query` asks for a `simplify=` or `densify=` distance which can not
+/// be used
+pub fn check_simplify(query: &QueryParametersLine) -> Result<(), ErrorWithStaticMessage> {
+	if query.simplify.is_some_and(|simplify| simplify <= 0.0) {
+		return Err(ErrorWithStaticMessage::new("simplify must be greater than zero"));
+	}
+	if query.densify.is_some_and(|densify| densify < MIN_DENSIFY_METRES) {
+		return Err(ErrorWithStaticMessage::new("densify must be at least 1 metre"));
+	}
+	Ok(())
+}
+
+/// The vertexes of a line stored in longitude / latitude degrees moved into a
+/// [LocalFrame] centred on the line. `position` gives the longitude / latitude
+/// of a vertex. `None` if there are no vertexes.
+fn to_metres<V>(vertexes: &[V], position: impl Fn(&V) -> Vector2) -> Option<Vec<Vector2>> {
+	let positions: Vec<Vector2> = vertexes.iter().map(position).collect();
+	let frame = LocalFrame::around(&positions)?;
+	Some(positions.iter().map(|vertex| frame.to_metres(vertex)).collect())
+}
+
+/// The distance from `point` to the nearest point on the segment from `a` to
+/// `b`
+fn distance_to_segment(point: &Vector2, a: &Vector2, b: &Vector2) -> f64 {
+	let (dx, dy) = (b.x - a.x, b.y - a.y);
+	let length_squared = dx * dx + dy * dy;
+	let fraction = if length_squared > 0.0 {
+		(((point.x - a.x) * dx + (point.y - a.y) * dy) / length_squared).clamp(0.0, 1.0)
+	} else {
+		0.0
+	};
+	(point.x - (a.x + dx * fraction)).hypot(point.y - (a.y + dy * fraction))
+}
+
+/// Remove the vertexes of a line which are within `tolerance` metres of the
+/// simplified line by the Douglas-Peucker method. The first and last vertexes
+/// are always kept. `position` gives the longitude / latitude of a vertex.
+pub fn simplify_line<V: Copy>(vertexes: &[V], position: impl Fn(&V) -> Vector2, tolerance: f64) -> Vec<V> {
+	if vertexes.len() < 3 {
+		return vertexes.to_vec();
+	}
+	let vertexes_in_metres = match to_metres(vertexes, position) {
+		Some(vertexes_in_metres) => vertexes_in_metres,
+		None => return vertexes.to_vec(),
+	};
+	let mut keep = vec![false; vertexes.len()];
+	keep[0] = true;
+	keep[vertexes.len() - 1] = true;
+	let mut sections = vec![(0, vertexes.len() - 1)];
+	while let Some((start, end)) = sections.pop() {
+		let farthest = (start + 1..end)
+			.map(|index| (index, distance_to_segment(&vertexes_in_metres[index], &vertexes_in_metres[start], &vertexes_in_metres[end])))
+			.max_by(|a, b| a.1.total_cmp(&b.1));
+		if let Some((index, distance)) = farthest {
+			if distance > tolerance {
+				keep[index] = true;
+				sections.push((start, index));
+				sections.push((index, end));
+			}
+		}
+	}
+	vertexes
+		.iter()
+		.zip(keep)
+		.filter_map(|(vertex, keep)| keep.then_some(*vertex))
+		.collect()
+}
+
+/// Add vertexes evenly along each segment of a line which is longer than
+/// `spacing` metres, so that no two vertexes are further apart than that.
+/// `position` gives the longitude / latitude of a vertex and `interpolate`
+/// makes a new vertex a fraction of the way from one vertex to the next.
+pub fn densify_line<V: Copy>(
+	vertexes: &[V],
+	position: impl Fn(&V) -> Vector2,
+	interpolate: impl Fn(&V, &V, f64) -> V,
+	spacing: f64,
+) -> Vec<V> {
+	let vertexes_in_metres = match to_metres(vertexes, position) {
+		Some(vertexes_in_metres) => vertexes_in_metres,
+		None => return vertexes.to_vec(),
+	};
+	let mut densified = Vec::with_capacity(vertexes.len());
+	for (segment, segment_in_metres) in vertexes.windows(2).zip(vertexes_in_metres.windows(2)) {
+		densified.push(segment[0]);
+		let steps = (segment_length(segment_in_metres) / spacing).ceil() as usize;
+		for step in 1..steps {
+			densified.push(interpolate(&segment[0], &segment[1], step as f64 / steps as f64));
+		}
+	}
+	densified.extend(vertexes.last().copied());
+	densified
+}
+
+fn segment_length(segment: &[Vector2]) -> f64 {
+	(segment[1].x - segment[0].x).hypot(segment[1].y - segment[0].y)
+}
+
+/// The number of vertexes [densify_line] would return, without making them.
+/// Counted as a float so that a tiny `spacing` can not overflow the count.
+fn densified_vertex_count<V>(vertexes: &[V], position: impl Fn(&V) -> Vector2, spacing: f64) -> f64 {
+	match to_metres(vertexes, position) {
+		Some(vertexes_in_metres) => vertexes_in_metres
+			.windows(2)
+			.map(|segment| (segment_length(segment) / spacing).ceil().max(1.0))
+			.sum::<f64>() + 1.0,
+		None => vertexes.len() as f64,
+	}
+}
+
+/// Apply the `simplify=` and then the `densify=` requested in `query` to each
+/// of `lines`. Fails before densifying if that would make more than
+/// [MAX_DENSIFIED_VERTEXES] vertexes in total.
+pub fn simplify_and_densify<'a, V: Copy + 'a>(
+	query: &QueryParametersLine,
+	lines: impl IntoIterator<Item = &'a mut Vec<V>>,
+	position: impl Fn(&V) -> Vector2,
+	interpolate: impl Fn(&V, &V, f64) -> V,
+) -> Result<(), ErrorWithStaticMessage> {
+	let mut lines: Vec<&mut Vec<V>> = lines.into_iter().collect();
+	if let Some(tolerance) = query.simplify {
+		for line in lines.iter_mut() {
+			**line = simplify_line(line, &position, tolerance.into());
+		}
+	}
+	if let Some(spacing) = query.densify {
+		let vertex_count: f64 = lines
+			.iter()
+			.map(|line| densified_vertex_count(line, &position, spacing.into()))
+			.sum();
+		if vertex_count > MAX_DENSIFIED_VERTEXES as f64 {
+			return Err(ErrorWithStaticMessage::new("densify would make more than 100000 vertices. Use a larger densify distance or a shorter slk range"));
+		}
+		for line in lines.iter_mut() {
+			**line = densify_line(line, &position, &interpolate, spacing.into());
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::helpers::geodesic_distance_metres;
+
+	fn position(vertex: &(f64, f64)) -> Vector2 {
+		Vector2::new(vertex.0, vertex.1)
+	}
+
+	fn interpolate(a: &(f64, f64), b: &(f64, f64), fraction: f64) -> (f64, f64) {
+		(a.0 + (b.0 - a.0) * fraction, a.1 + (b.1 - a.1) * fraction)
+	}
+
+	#[test]
+	fn test_simplify_line() {
+		// north for about 110 m with a 2 m kink to the east half way, then
+		// east for about 95 m
+		let kink = 2.0 / 94_500.0;
+		let line = [
+			(115.86, -31.95),
+			(115.86 + kink, -31.9495),
+			(115.86, -31.949),
+			(115.8605, -31.949),
+			(115.861, -31.949),
+		];
+		assert_eq!(simplify_line(&line, position, 5.0), vec![line[0], line[2], line[4]]);
+		assert_eq!(simplify_line(&line, position, 1.0), vec![line[0], line[1], line[2], line[4]]);
+		// the corner is more than 50 m from the line joining the ends
+		assert_eq!(simplify_line(&line, position, 50.0), vec![line[0], line[2], line[4]]);
+		assert_eq!(simplify_line(&line, position, 100.0), vec![line[0], line[4]]);
+		assert_eq!(simplify_line(&line[..2], position, 100.0), line[..2].to_vec());
+		assert!(simplify_line(&[], position, 1.0).is_empty());
+	}
+
+	#[test]
+	fn test_densify_line() {
+		// about 110 m north, then a segment which is already short enough
+		let line = [(115.86, -31.95), (115.86, -31.949), (115.86, -31.94895)];
+		let densified = densify_line(&line, position, interpolate, 10.0);
+		assert_eq!(densified.len(), 14);
+		assert_eq!(densified[0], line[0]);
+		assert_eq!(densified[12], line[1]);
+		assert_eq!(densified[13], line[2]);
+		for segment in densified.windows(2) {
+			let length = geodesic_distance_metres(&position(&segment[0]), &position(&segment[1])).unwrap();
+			assert!(length <= 10.0, "{length}");
+		}
+		assert_eq!(densify_line(&line[..1], position, interpolate, 10.0), line[..1].to_vec());
+		assert!(densify_line(&[], position, interpolate, 10.0).is_empty());
+	}
+
+	#[test]
+	fn test_simplify_and_densify() {
+		// about 110 m north
+		let mut lines = vec![vec![(115.86, -31.95), (115.86, -31.949)], vec![(115.86, -31.95), (115.86, -31.95)]];
+		let query = serde_urlencoded::from_str("road=H001&densify=10").unwrap();
+		assert_eq!(densified_vertex_count(&lines[0], position, 10.0), 13.0);
+		assert_eq!(densified_vertex_count(&lines[1], position, 10.0), 2.0);
+		simplify_and_densify(&query, lines.iter_mut(), position, interpolate).unwrap();
+		assert_eq!(lines[0].len(), 13);
+		assert_eq!(lines[1].len(), 2);
+
+		// about 1000 km would need a million vertexes
+		let mut lines = vec![vec![(115.86, -31.95), (115.86, -40.95)]];
+		let query = serde_urlencoded::from_str("road=H001&densify=1").unwrap();
+		assert!(simplify_and_densify(&query, lines.iter_mut(), position, interpolate).is_err());
+		assert_eq!(lines[0].len(), 2);
+
+		let query = serde_urlencoded::from_str("road=H001&simplify=5&densify=1000").unwrap();
+		simplify_and_densify(&query, lines.iter_mut(), position, interpolate).unwrap();
+		assert!(lines[0].len() > 900 && lines[0].len() < 1100);
+	}
+
+	#[test]
+	fn test_check_simplify() {
+		let check = |query: &str| check_simplify(&serde_urlencoded::from_str(query).unwrap());
+		assert!(check("road=H001").is_ok());
+		assert!(check("road=H001&simplify=5&densify=10").is_ok());
+		assert!(check("road=H001&simplify=0").is_err());
+		assert!(check("road=H001&densify=-1").is_err());
+		assert!(check("road=H001&densify=0.01").is_err());
+	}
+}
@@ -2,10 +2,10 @@ use std::{error::Error, sync::Arc};
 
 use warp::{Filter, wrap_fn, filters::BoxedFilter, reply::Response, fs::File, Reply};
 
-use crate::{data::IndexedData, settings::Settings};
+use crate::{data::SharedIndexedData, settings::Settings};
 
 /// get the combined routes / filters to handle each feature of the server
-pub async fn get_combined_filters(settings:&Settings, indexed_data:Arc<IndexedData>) -> Result<BoxedFilter<(Response,)>, Box<dyn Error>> {
+pub async fn get_combined_filters(settings:&Settings, shared_data:Arc<SharedIndexedData>) -> Result<BoxedFilter<(Response,)>, Box<dyn Error>> {
 
     let filter_show = 
         warp::path("show")
@@ -13,10 +13,17 @@ pub async fn get_combined_filters(settings:&Settings, indexed_data:Arc<IndexedDa
             warp::fs::dir(settings.NLR_STATIC_HTTP.clone())
             .map(|r:File| r.into_response())
         );
-    let filter_lines          = super::lines(indexed_data.clone());
-    let filter_points         = super::points(indexed_data.clone());
-    let filter_lines_batch    = super::lines_batch(indexed_data.clone());
-    let filter_unified_batch  = super::unified_batch(indexed_data.clone());
+    let filter_lines          = super::lines(shared_data.clone());
+    let filter_points         = super::points(shared_data.clone());
+    let filter_locate         = super::locate(shared_data.clone());
+    let filter_roads          = super::roads(shared_data.clone());
+    let filter_road           = super::road(shared_data.clone());
+    let filter_diff           = super::diff(shared_data.clone(), settings.NLR_ADMIN_TOKEN.clone());
+    let filter_diagnostics    = super::diagnostics(shared_data.clone());
+    let filter_status         = super::status(shared_data.clone());
+    let filter_lines_batch    = super::lines_batch(shared_data.clone());
+    let filter_unified_batch  = super::unified_batch(shared_data.clone());
+    let filter_admin_reload   = super::admin_reload(shared_data.clone(), Arc::new(settings.clone()));
 
     // Chain filters together into a single filter
     Ok(
@@ -24,13 +31,21 @@ pub async fn get_combined_filters(settings:&Settings, indexed_data:Arc<IndexedDa
         .or(
             filter_lines
             .or(filter_points)
+            .or(filter_locate)
+            .or(filter_roads)
+            .or(filter_road)
+            .or(filter_diff)
+            .or(filter_diagnostics)
+            .or(filter_status)
             .or(filter_unified_batch)
+            .or(filter_admin_reload)
             .or(
                 filter_lines_batch
                 .with(warp::compression::gzip())
             )
             .recover(super::custom_rejection_handler)
             .with(wrap_fn(super::echo_x_request_id))
+            .with(wrap_fn(move |filter| super::data_version_headers(shared_data.clone(), filter)))
         ).unify()
         .boxed()
     )
@@ -39,6 +54,7 @@ pub async fn get_combined_filters(settings:&Settings, indexed_data:Arc<IndexedDa
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data::{IndexedData, Snapshots};
     use crate::filters::query_parameters::RequestedCwy;
     use byteorder::{WriteBytesExt, LittleEndian};
     use std::io::Read;
@@ -55,20 +71,22 @@ mod tests {
             //  - prevent saving the data file by providing an empty filepath
             //  - set data source url to download a small subset of data
             //    (The first 5-ish kilometres of H015)
+            //  - enable the admin routes
 
             let settings = Settings {
                 NLR_DATA_FILE: "".to_owned(), 
-                NLR_DATA_SOURCE_URL: "https://mrgis.mainroads.wa.gov.au/arcgis/rest/services/OpenData/RoadAssets_DataPortal/MapServer/17/query?where=ROAD%3D%27H015%27%20and%20END_SLK%3C5&outFields=ROAD,START_SLK,END_SLK,CWY&outSR=4326&f=json".to_owned(),
+                NLR_DATA_SOURCE_URL: "https://mrgis.mainroads.wa.gov.au/arcgis/rest/services/OpenData/RoadAssets_DataPortal/MapServer/17/query?where=ROAD%3D%27H015%27%20and%20END_SLK%3C5&outFields=ROAD,START_SLK,END_SLK,CWY,START_TRUE_DIST,END_TRUE_DIST,NETWORK_TYPE,ROAD_NAME,LG_NAME,RA_NO&outSR=4326&f=json".to_owned(),
+                NLR_ADMIN_TOKEN: Some("test-token".to_owned()),
                 ..Settings::default()
             };
 
-            let indexed_data:Arc<_> = IndexedData::load(
-                &settings.NLR_DATA_FILE,
-                &settings.NLR_DATA_SOURCE_URL,
-                &settings.NLR_FORCE_UPDATE_DATA
-            ).await.unwrap().into();
+            let indexed_data = IndexedData::load(
+                &settings.get_data_source_settings(),
+                &settings.NLR_FORCE_UPDATE_DATA,
+                &true,
+            ).await.unwrap();
 
-            get_combined_filters(&settings, indexed_data).await.unwrap()
+            get_combined_filters(&settings, SharedIndexedData::new(indexed_data, Snapshots::open(&None)).into()).await.unwrap()
         }};
     }
 
@@ -108,6 +126,123 @@ mod tests {
         let result = warp::test::request().header("x-request-id", "11").path("/?road=H000").filter(&filter).await.unwrap();
         assert!(result.headers().get("x-request-id").map_or(false, |header| header=="11"));
 
+        println!("test: Locate should find the road at a point returned by /point");
+        let result = warp::test::request().path("/point?road=H015&slk=1&cwy=S&f=latlon").filter(&filter).await.unwrap();
+        let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
+        let body_str = String::from_utf8(body_bytes.to_vec()).unwrap();
+        let (lat, lon) = body_str.split_once(',').unwrap();
+        let result = warp::test::request().path(&format!("/locate?lon={}&lat={}&road=H015&cwy=S", lon, lat)).filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+        let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
+        let location: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(location["road"], "H015");
+        assert!((location["slk"].as_f64().unwrap() - 1.0).abs() < 0.001);
+
+        println!("test: Geojson lines should include the network type as a property");
+        let result = warp::test::request().path("/?road=H015&slk_from=1&slk_to=2").filter(&filter).await.unwrap();
+        let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
+        let feature: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(feature["properties"]["network_type"], serde_json::json!(["State Road"]));
+
+        println!("test: Filtering on a different network type should return nothing");
+        let result = warp::test::request().path("/?road=H015&slk_from=1&slk_to=2&network_type=Local%20Road").filter(&filter).await.unwrap();
+        let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
+        let feature: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(feature["geometry"]["coordinates"], serde_json::json!([]));
+
+        println!("test: Points can be found by true distance");
+        let result = warp::test::request().path("/?road=H015&slk=1&measure=true_dist&f=latlon").filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+
+        println!("test: as_of dates on or after the download date use the current data");
+        let result = warp::test::request().path("/line?road=H015&slk_from=1&slk_to=2&as_of=2999-01-01").filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+
+        println!("test: as_of dates older than every snapshot are not found");
+        let result = warp::test::request().path("/line?road=H015&slk_from=1&slk_to=2&as_of=2000-01-01").filter(&filter).await.unwrap();
+        assert_eq!(result.status(), warp::http::StatusCode::NOT_FOUND);
+
+        println!("test: Comparing the current data with itself should find no changes");
+        let result = warp::test::request().path("/diff?from=2999-01-01&f=csv").header("Authorization", "Bearer test-token").filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+        let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
+        assert_eq!(body_bytes.split(|byte| *byte == b'\n').filter(|line| !line.is_empty()).count(), 1);
+
+        println!("test: Diff from a date older than every snapshot is not found");
+        let result = warp::test::request().path("/diff?from=2000-01-01&f=csv").header("Authorization", "Bearer test-token").filter(&filter).await.unwrap();
+        assert_eq!(result.status(), warp::http::StatusCode::NOT_FOUND);
+
+        println!("test: Diff needs a from date");
+        let result = warp::test::request().path("/diff").header("Authorization", "Bearer test-token").filter(&filter).await.unwrap();
+        assert!(result.status().is_client_error());
+
+        println!("test: Diff needs the admin token");
+        let result = warp::test::request().path("/diff?from=2999-01-01&f=csv").filter(&filter).await.unwrap();
+        assert_eq!(result.status(), warp::http::StatusCode::UNAUTHORIZED);
+
+        println!("test: Diagnostics should count the issues it lists");
+        let result = warp::test::request().path("/diagnostics").filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+        let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+        let issue_count = report["issues"].as_array().unwrap().len() as u64;
+        assert_eq!(report["error_count"].as_u64().unwrap() + report["warning_count"].as_u64().unwrap(), issue_count);
+
+        println!("test: Status should describe the data being served");
+        let result = warp::test::request().path("/status").filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+        let data_version = result.headers().get("x-data-version").unwrap().to_str().unwrap().to_owned();
+        let etag = result.headers().get("etag").unwrap().to_str().unwrap().to_owned();
+        assert_eq!(etag, format!("\"{}\"", data_version));
+        let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
+        let status: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(status["data_version"], data_version.as_str());
+        assert!(status["feature_count"].as_u64().unwrap() > 0);
+        assert_eq!(status["road_count"], 1);
+        assert!(status["source_url"].as_str().unwrap().contains("H015"));
+
+        println!("test: A matching If-None-Match should answer 304 Not Modified");
+        let result = warp::test::request().path("/?road=H015").header("if-none-match", &etag).header("x-request-id", "12").filter(&filter).await.unwrap();
+        assert_eq!(result.status(), warp::http::StatusCode::NOT_MODIFIED);
+        assert_eq!(result.headers().get("etag").unwrap().to_str().unwrap(), etag);
+        assert_eq!(result.headers().get("x-request-id").unwrap(), "12");
+        let result = warp::test::request().path("/?road=H015").header("if-none-match", "\"other\"").filter(&filter).await.unwrap();
+        assert_eq!(result.status(), warp::http::StatusCode::OK);
+
+        println!("test: Rejected requests should have a data version but no ETag");
+        let result = warp::test::request().path("/?road=H000").filter(&filter).await.unwrap();
+        assert!(result.headers().contains_key("x-data-version"));
+        assert!(!result.headers().contains_key("etag"));
+
+        println!("test: Road search should find H015");
+        let result = warp::test::request().path("/roads?q=h15").filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+        let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
+        let roads: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(roads[0]["road"], "H015");
+        assert!(roads[0]["road_name"].as_array().map_or(false, |names| !names.is_empty()));
+
+        println!("test: Road search without a search term should reject");
+        let result = warp::test::request().path("/roads").filter(&filter).await.unwrap();
+        assert!(result.status().is_client_error());
+
+        println!("test: Road metadata should describe the single carriageway of H015");
+        let result = warp::test::request().path("/road/H015").filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+        let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
+        let metadata: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(metadata["road"], "H015");
+        assert!(metadata["carriageways"].as_array().map_or(false, |carriageways| !carriageways.is_empty()));
+        assert!(metadata["single"][0]["slk_from"].as_f64().unwrap() < 0.1);
+
+        println!("test: Road metadata for an unknown road should reject");
+        let result = warp::test::request().path("/road/H000").filter(&filter).await.unwrap();
+        assert!(result.status().is_server_error());
+
+        println!("test: Locate far from any road should reject");
+        let result = warp::test::request().path("/locate?lon=0&lat=0").filter(&filter).await.unwrap();
+        assert!(result.status().is_server_error());
+
         println!("test: static http");
         let result = warp::test::request().path("/show/index.html").filter(&filter).await.unwrap();
         let body_bytes = warp::hyper::body::to_bytes(result.into_body()).await.unwrap();
@@ -116,6 +251,27 @@ mod tests {
         assert_eq!(first_line.trim(), "<!doctype html>");
     }
 
+    #[tokio::test]
+    async fn admin_reload_test() {
+        let filter = setup_filter_for_testing!();
+
+        println!("test: Reload without a token should be unauthorized");
+        let result = warp::test::request().method("POST").path("/admin/reload").filter(&filter).await.unwrap();
+        assert_eq!(result.status(), warp::http::StatusCode::UNAUTHORIZED);
+
+        println!("test: Reload with the wrong token should be unauthorized");
+        let result = warp::test::request().method("POST").path("/admin/reload").header("authorization", "Bearer nope").filter(&filter).await.unwrap();
+        assert_eq!(result.status(), warp::http::StatusCode::UNAUTHORIZED);
+
+        println!("test: Reload with the correct token should download and swap in new data");
+        let result = warp::test::request().method("POST").path("/admin/reload?source=download").header("authorization", "Bearer test-token").filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+
+        println!("test: Queries should still work after reload");
+        let result = warp::test::request().method("GET").path("/?road=H015").filter(&filter).await.unwrap();
+        assert!(result.status().is_success());
+    }
+
     #[tokio::test]
     /// TODO: so far very primitive tests for /batch/
     async fn batch_request_test(){
@@ -1,51 +1,57 @@
 use std::sync::Arc;
 
-use warp::{Filter, Rejection};
+use warp::{reply::Response, Filter, Rejection};
 
-use crate::data::IndexedData;
+use crate::data::SharedIndexedData;
 
 use super::{
+    data_as_of,
     geoprocessing::{get_linestring, get_linestring_m},
     query_parameters::QueryParametersLine,
+    with_data_version,
     with_shared_data
 };
 
 pub fn lines(
-    indexed_data: Arc<IndexedData>,
-) -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    shared_data: Arc<SharedIndexedData>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
     warp::path::end()
     .and(warp::get())
-    .and(with_shared_data(indexed_data.clone()))
+    .and(with_shared_data(shared_data.clone()))
     .and(warp::query())
     .and_then(|
-        indexed_data: Arc<IndexedData>,
+        shared_data: Arc<SharedIndexedData>,
         query: QueryParametersLine
     | async move {
-        if query.m {
-            get_linestring_m(&query, &indexed_data).map_err(|err|err.as_rejection())
+        let indexed_data = data_as_of(&shared_data, &query.as_of).await?;
+        let body = if query.m {
+            get_linestring_m(&query, &indexed_data).map_err(|err|err.as_rejection())?
         } else {
-            get_linestring(&query, &indexed_data).map_err(|err|err.as_rejection())
-        }
+            get_linestring(&query, &indexed_data).map_err(|err|err.as_rejection())?
+        };
+        Ok::<_, Rejection>(with_data_version(body, indexed_data.data_version()))
     })
     // New version of the endpoint must be descriminated by the `/line` route
     // this new version will accept both GET and POST requests
     .or(
         warp::path("line")
-        .and(with_shared_data(indexed_data.clone()))
+        .and(with_shared_data(shared_data.clone()))
         .and(
             warp::get().and(warp::query())
             .or(warp::post().and(warp::body::json()))
             .unify()
         )
         .and_then(|
-            indexed_data: Arc<IndexedData>,
+            shared_data: Arc<SharedIndexedData>,
             query: QueryParametersLine
         | async move {
-            if query.m {
-                get_linestring_m(&query, &indexed_data).map_err(|err|err.as_rejection())
+            let indexed_data = data_as_of(&shared_data, &query.as_of).await?;
+            let body = if query.m {
+                get_linestring_m(&query, &indexed_data).map_err(|err|err.as_rejection())?
             } else {
-                get_linestring(&query, &indexed_data).map_err(|err|err.as_rejection())
-            }
+                get_linestring(&query, &indexed_data).map_err(|err|err.as_rejection())?
+            };
+            Ok::<_, Rejection>(with_data_version(body, indexed_data.data_version()))
         })
     )
     .unify()
@@ -2,30 +2,35 @@
 
 use std::sync::Arc;
 
-use warp::Filter;
+use warp::{reply::Response, Filter};
 
 use crate::{
     helpers::ErrorWithStaticMessage,
-    data::IndexedData,
+    data::SharedIndexedData,
 };
 
 use super::{
+    data_as_of,
     geoprocessing::get_linestring,
-    query_parameters::QueryParameterBatch,
+    query_parameters::{QueryParameterBatch, QueryParametersAsOf},
+    with_data_version,
     with_shared_data
 };
 
 pub fn lines_batch(
-    indexed_data:Arc<IndexedData>
-) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    shared_data: Arc<SharedIndexedData>
+) -> impl Filter<Extract = (Response,), Error = warp::Rejection> + Clone {
     warp::post()
     .and(warp::path("batch").and(warp::path::end()))
-    .and(with_shared_data(indexed_data.clone()))
+    .and(with_shared_data(shared_data.clone()))
+    .and(warp::query())
     .and(warp::body::bytes())
     .and_then(|
-            indexed_data: Arc<IndexedData>,
+            shared_data: Arc<SharedIndexedData>,
+            options: QueryParametersAsOf,
             body: bytes::Bytes
         | async move {
+            let indexed_data = data_as_of(&shared_data, &options.as_of).await?;
             if let Ok(batch_query) = QueryParameterBatch::try_from(body) {
                 let result_string = 
                     batch_query
@@ -37,10 +42,70 @@ pub fn lines_batch(
                     })
                     .collect::<Vec<String>>()
                     .join(",");
-                Ok(format!("[{}]", result_string))
+                Ok(with_data_version(format!("[{}]", result_string), indexed_data.data_version()))
             } else {
                 Err(ErrorWithStaticMessage::reject("Unable to parse batch query parameters"))
             }
         })
 }
 
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use bytes::{BufMut, BytesMut};
+
+    use super::*;
+    use crate::data::{cached::{Attributes, Cwy, Feature, Layer}, IndexedData, Snapshots};
+    use crate::filters::query_parameters::RequestedCwy;
+
+    /// 200 roads running east, each made of 50 features of 1 km with 40
+    /// vertexes
+    fn benchmark_data() -> IndexedData {
+        let features = (0..200).flat_map(|road| (0..50).map(move |slk| Feature::new(
+            Attributes::new(&format!("H{:03}", road), Cwy::Single, slk as f32, (slk + 1) as f32),
+            &(0..40)
+                .map(|vertex| (115.0 + (slk as f64 + vertex as f64 / 39.0) * 0.01, -32.0 + road as f64 * 0.01 + (vertex % 2) as f64 * 1e-5))
+                .collect::<Vec<_>>(),
+        ))).collect();
+        IndexedData::from_layer(Layer {
+            features,
+            source_metadata: None,
+            downloaded: None,
+            spatial_reference: None,
+            cache_metadata: None,
+        }).unwrap()
+    }
+
+    /// Run with `cargo test --release -- --ignored benchmark_lines_batch --nocapture`.
+    /// Times one `POST /batch` request of 10,000 lines, 2.5 km long, on
+    /// every carriageway.
+    #[tokio::test]
+    #[ignore]
+    async fn benchmark_lines_batch() {
+        let filter = lines_batch(Arc::new(SharedIndexedData::new(benchmark_data(), Snapshots::open(&None))));
+        let mut body = BytesMut::new();
+        for index in 0..10_000 {
+            let road = format!("H{:03}", index % 200);
+            let slk_from = (index * 7 % 45) as f32 + 0.3;
+            body.put_u8(road.len() as u8);
+            body.put_slice(road.as_bytes());
+            body.put_slice(&slk_from.to_le_bytes());
+            body.put_slice(&(slk_from + 2.5).to_le_bytes());
+            body.put_slice(&0.0f32.to_le_bytes());
+            body.put_u8(RequestedCwy::LRS.into());
+        }
+        let body = body.freeze();
+
+        let mut times = Vec::new();
+        for _ in 0..20 {
+            let start = Instant::now();
+            let response = warp::test::request().method("POST").path("/batch").body(body.clone()).reply(&filter).await;
+            times.push(start.elapsed());
+            assert_eq!(response.status(), warp::http::StatusCode::OK);
+            assert!(!response.body().starts_with(b"[null"));
+        }
+        times.sort();
+        println!("POST /batch of 10,000 lines, 20 times: fastest {:?}, median {:?}", times[0], times[10]);
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use warp::Filter;
+
+use crate::data::{IndexedData, SharedIndexedData};
+
+use super::{
+    geoprocessing::get_location,
+    query_parameters::QueryParametersLocate,
+    with_current_data,
+    NotFound
+};
+
+pub fn locate(
+    shared_data: Arc<SharedIndexedData>
+) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::path("locate")
+    .and(warp::path::end())
+    .and(with_current_data(shared_data.clone()))
+    .and(
+        warp::get().and(warp::query())
+        .or(warp::post().and(warp::body::json()))
+        .unify()
+    )
+    .and_then(|
+        indexed_data: Arc<IndexedData>,
+        query: QueryParametersLocate
+    | async move {
+        match get_location(&query, &indexed_data) {
+            Ok(Some(location)) => Ok(location),
+            Ok(None) => Err(NotFound::reject("Found no road within the search radius")),
+            Err(err) => Err(err.as_rejection()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{cached::{Attributes, Cwy, Feature, Layer}, Snapshots};
+    use crate::filters::custom_rejection_handler;
+
+    #[tokio::test]
+    async fn test_locate_route() {
+        let indexed_data = IndexedData::from_layer(Layer {
+            features: vec![Feature::new(Attributes::new("H001", Cwy::Single, 0.0, 1.0), &[(115.0, -32.0), (115.01, -32.0)])],
+            source_metadata: None,
+            downloaded: None,
+            spatial_reference: None,
+            cache_metadata: None,
+        }).unwrap();
+        let filter = locate(Arc::new(SharedIndexedData::new(indexed_data, Snapshots::open(&None))))
+            .recover(custom_rejection_handler);
+
+        let response = warp::test::request().path("/locate?lon=115.005&lat=-32.0001").reply(&filter).await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+
+        // about 1 km from the road
+        let response = warp::test::request().path("/locate?lon=115.005&lat=-31.991").reply(&filter).await;
+        assert_eq!(response.status(), warp::http::StatusCode::NOT_FOUND);
+        assert_eq!(response.body(), "Found no road within the search radius");
+
+        let response = warp::test::request().path("/locate?lon=115.005&lat=-32.0001&road=H002").reply(&filter).await;
+        assert_eq!(response.status(), warp::http::StatusCode::NOT_FOUND);
+    }
+}
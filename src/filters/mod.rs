@@ -3,6 +3,9 @@ mod geoprocessing;
 mod echo_x_request_id;
 pub use echo_x_request_id::echo_x_request_id;
 
+mod data_version_headers;
+use data_version_headers::{data_version_headers, with_data_version};
+
 pub mod query_parameters;
 
 mod lines;
@@ -11,9 +14,39 @@ use lines::lines;
 mod points;
 use points::points;
 
+mod locate;
+use locate::locate;
+
+mod roads;
+use roads::roads;
+
+mod road;
+use road::road;
+
+mod diff;
+use diff::diff;
+
+mod diagnostics;
+use diagnostics::diagnostics;
+
+mod status;
+use status::status;
+
 mod lines_batch;
 use lines_batch::lines_batch;
 
+mod authorized;
+use authorized::{authorized, Unauthorized};
+
+mod not_found;
+use not_found::NotFound;
+
+mod data_as_of;
+use data_as_of::data_as_of;
+
+mod admin_reload;
+use admin_reload::admin_reload;
+
 mod custom_rejection_handler;
 pub use custom_rejection_handler::custom_rejection_handler;
 
@@ -23,5 +56,8 @@ pub use get_combined_filters::get_combined_filters;
 mod with_shared_data;
 pub use with_shared_data::with_shared_data;
 
+mod with_current_data;
+pub use with_current_data::with_current_data;
+
 mod unified_batch;
 use unified_batch::unified_batch;
\ No newline at end of file
@@ -0,0 +1,21 @@
+use warp::{reject::Reject, Rejection};
+
+/// Rejection used when a valid request matches nothing, for example a
+/// `/locate` request with no road inside the search radius. Answered with
+/// `404 Not Found` and the message, so that it is not mistaken for a fault in
+/// the server.
+#[derive(Debug)]
+pub struct NotFound {
+    message: &'static str,
+}
+
+impl NotFound {
+    pub fn reject(message: &'static str) -> Rejection {
+        warp::reject::custom(NotFound { message })
+    }
+    pub fn get_message(&self) -> &'static str {
+        self.message
+    }
+}
+
+impl Reject for NotFound {}
@@ -1,43 +1,51 @@
 use std::sync::Arc;
 
-use warp::Filter;
+use warp::{reply::Response, Filter};
 
-use crate::data::IndexedData;
+use crate::data::SharedIndexedData;
 
 use super::{
+    data_as_of,
     geoprocessing::get_points,
     query_parameters::QueryParametersPoint,
+    with_data_version,
     with_shared_data
 };
 
 pub fn points(
-    indexed_data: Arc<IndexedData>
-) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    shared_data: Arc<SharedIndexedData>
+) -> impl Filter<Extract = (Response,), Error = warp::Rejection> + Clone {
     warp::path::end()
     .and(warp::get())
-    .and(with_shared_data(indexed_data.clone()))
+    .and(with_shared_data(shared_data.clone()))
     .and(warp::query())
     .and_then(|
-        indexed_data: Arc<IndexedData>,
+        shared_data: Arc<SharedIndexedData>,
         query: QueryParametersPoint
     | async move {
-        get_points    (&query, &indexed_data).map_err(|err|err.as_rejection())
+        let indexed_data = data_as_of(&shared_data, &query.as_of).await?;
+        get_points    (&query, &indexed_data)
+            .map(|body| with_data_version(body, indexed_data.data_version()))
+            .map_err(|err|err.as_rejection())
     })
     // New version of the endpoint must be descriminated by the `/point` route
     // this new version will accept both GET and POST requests
     .or(
         warp::path("point")
-        .and(with_shared_data(indexed_data.clone()))
+        .and(with_shared_data(shared_data.clone()))
         .and(
             warp::get().and(warp::query())
             .or(warp::post().and(warp::body::json()))
             .unify()
         )
         .and_then(|
-            indexed_data: Arc<IndexedData>,
+            shared_data: Arc<SharedIndexedData>,
             query: QueryParametersPoint
         | async move {
-            get_points(&query, &indexed_data).map_err(|err|err.as_rejection())
+            let indexed_data = data_as_of(&shared_data, &query.as_of).await?;
+            get_points(&query, &indexed_data)
+                .map(|body| with_data_version(body, indexed_data.data_version()))
+                .map_err(|err|err.as_rejection())
         })
     )
     .unify()
@@ -0,0 +1,35 @@
+use crate::data::cached::Attributes;
+
+/// Returns true if the attributes pass the optional `network_type=` and
+/// `lg_name=` filters. Values must match exactly, and features without the
+/// attribute never pass a filter on it.
+pub fn attributes_match_filters(
+    attributes: &Attributes,
+    network_type: &Option<String>,
+    lg_name: &Option<String>,
+) -> bool {
+    let matches = |filter: &Option<String>, value: &Option<String>| match filter {
+        Some(filter) => value.as_ref() == Some(filter),
+        None => true,
+    };
+    matches(network_type, &attributes.NETWORK_TYPE) && matches(lg_name, &attributes.LG_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Cwy;
+
+    #[test]
+    fn test_attributes_match_filters() {
+        let attributes = Attributes {
+            NETWORK_TYPE: Some("State Road".to_owned()),
+            ..Attributes::new("H001", Cwy::Single, 0.0, 1.0)
+        };
+        let filter = |value: &str| Some(value.to_owned());
+        assert!(attributes_match_filters(&attributes, &None, &None));
+        assert!(attributes_match_filters(&attributes, &filter("State Road"), &None));
+        assert!(!attributes_match_filters(&attributes, &filter("Local Road"), &None));
+        assert!(!attributes_match_filters(&attributes, &filter("State Road"), &filter("City of Perth")));
+    }
+}
@@ -0,0 +1,44 @@
+use serde::Deserialize;
+
+use crate::data::cached::Attributes;
+
+/// The linear measure used to interpret `slk_from`, `slk_to` and `slk`
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Default, Copy)]
+#[allow(non_camel_case_types)]
+pub enum Measure {
+    /// Straight Line Kilometres, which may contain points of equation
+    #[default]
+    slk,
+    /// True distance in kilometres, as measured along the road by survey
+    /// vehicles
+    true_dist,
+}
+
+impl Measure {
+    /// The start and end of a feature in this measure. Features without a
+    /// true distance return `None` so that they are left out of the result.
+    pub fn feature_range(&self, attributes: &Attributes) -> Option<(f32, f32)> {
+        match self {
+            Measure::slk => Some((attributes.START_SLK, attributes.END_SLK)),
+            Measure::true_dist => attributes.START_TRUE_DIST.zip(attributes.END_TRUE_DIST),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cached::Cwy;
+
+    #[test]
+    fn test_measure_feature_range() {
+        let mut attributes = Attributes {
+            START_TRUE_DIST: Some(1.1),
+            ..Attributes::new("H001", Cwy::Single, 1.0, 2.0)
+        };
+        assert_eq!(Measure::slk.feature_range(&attributes), Some((1.0, 2.0)));
+        assert_eq!(Measure::true_dist.feature_range(&attributes), None);
+        attributes.END_TRUE_DIST = Some(2.1);
+        assert_eq!(Measure::true_dist.feature_range(&attributes), Some((1.1, 2.1)));
+    }
+}
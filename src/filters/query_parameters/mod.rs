@@ -3,15 +3,36 @@ pub mod output_format;
 mod requested_cwy;
 pub use requested_cwy::RequestedCwy;
 
+mod measure;
+pub use measure::Measure;
+
+mod attribute_filter;
+pub use attribute_filter::attributes_match_filters;
+
 mod query_parameters_line;
 pub use query_parameters_line::QueryParametersLine;
 
 mod query_parameters_point;
 pub use query_parameters_point::QueryParametersPoint;
 
+mod query_parameters_locate;
+pub use query_parameters_locate::QueryParametersLocate;
+
+mod query_parameters_roads;
+pub use query_parameters_roads::QueryParametersRoads;
+
+mod query_parameters_reload;
+pub use query_parameters_reload::{QueryParametersReload, ReloadSource};
+
 mod query_parameters_batch;
 pub use query_parameters_batch::QueryParameterBatch;
 
+mod query_parameters_as_of;
+pub use query_parameters_as_of::QueryParametersAsOf;
+
+mod query_parameters_diff;
+pub use query_parameters_diff::QueryParametersDiff;
+
 mod query_parameters_unified;
 pub use query_parameters_unified::{
     QueryParametersPointLine,
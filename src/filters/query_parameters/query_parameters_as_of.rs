@@ -0,0 +1,31 @@
+use serde::Deserialize;
+
+use crate::helpers::CalendarDate;
+
+/// Options given in the url of the binary `/batch` route. The frames of the
+/// request body have no room for them.
+#[derive(Deserialize, Debug, PartialEq, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct QueryParametersAsOf {
+    #[serde(default)]
+    /// Answer every query in the batch using the network as it was on this
+    /// date (YYYY-MM-DD)
+    pub as_of: Option<CalendarDate>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_parameters_as_of() {
+        let query: QueryParametersAsOf = serde_urlencoded::from_str("").unwrap();
+        assert_eq!(query, QueryParametersAsOf::default());
+
+        let query: QueryParametersAsOf = serde_urlencoded::from_str("as_of=2021-11-05").unwrap();
+        assert_eq!(query.as_of, CalendarDate::new(2021, 11, 5));
+
+        let query: Result<QueryParametersAsOf, _> = serde_urlencoded::from_str("as_of=5/11/2021");
+        assert!(query.is_err());
+    }
+}
@@ -1,10 +1,16 @@
-use super::{QueryParametersLine, RequestedCwy, output_format::OutputFormatLines};
+use super::{Measure, QueryParametersLine, RequestedCwy, output_format::OutputFormatLines};
 
 use std::convert::TryFrom;
 use std::fmt;
 
 pub struct QueryParameterBatch(pub Vec<QueryParametersLine>);
 
+const MASK_CWY: u8 = 0b0000_0111;
+const FLAG_TRUE_DIST: u8 = 0b0000_1000;
+/// The frame ends with a 4 byte `offset_to`, and `offset` is the offset at
+/// `slk_from`
+const FLAG_OFFSET_TO: u8 = 0b0001_0000;
+
 #[derive(Debug)]
 pub struct BatchQueryParametersDecodeError;
 impl std::error::Error for BatchQueryParametersDecodeError {}
@@ -61,15 +67,55 @@ impl TryFrom<bytes::Bytes> for QueryParameterBatch {
                 other_bytes[10],
                 other_bytes[11],
             ]);
-            let cwy: RequestedCwy = other_bytes[12].into();
+            // The lowest 3 bits are the carriageway, bit 3 selects true
+            // distance instead of SLK and bit 4 adds `offset_to`. The
+            // remaining bits are reserved.
+            let flags = other_bytes[12];
+            let cwy: RequestedCwy = (flags & MASK_CWY).into();
+            let measure = if flags & FLAG_TRUE_DIST != 0 {
+                Measure::true_dist
+            } else {
+                Measure::slk
+            };
+            let offset_to = if flags & FLAG_OFFSET_TO != 0 {
+                let offset_to_bytes = buffer_iter
+                    .by_ref()
+                    .take(4)
+                    .copied()
+                    .collect::<Vec<u8>>();
+                if offset_to_bytes.len() != 4 {
+                    return Err(BatchQueryParametersDecodeError);
+                }
+                Some(f32::from_le_bytes([
+                    offset_to_bytes[0],
+                    offset_to_bytes[1],
+                    offset_to_bytes[2],
+                    offset_to_bytes[3],
+                ]))
+            } else {
+                None
+            };
             params.push(QueryParametersLine {
                 road: road_name.to_string(),
                 slk_from,
                 slk_to,
                 cwy,
                 offset,
+                offset_from: offset_to.map(|_| offset),
+                offset_to,
+                offset_left: None,
+                offset_right: None,
+                buffer: None,
+                merge: false,
+                merge_tolerance: 1.0,
+                simplify: None,
+                densify: None,
                 m: false,
                 f: OutputFormatLines::json,
+                measure,
+                network_type: None,
+                lg_name: None,
+                as_of: None,
             })
         }
         Ok(QueryParameterBatch(params))
@@ -97,7 +143,18 @@ mod tests {
         buffer.put_slice(&query_parameters_line.slk_from.to_le_bytes());
         buffer.put_slice(&query_parameters_line.slk_to.to_le_bytes());
         buffer.put_slice(&query_parameters_line.offset.to_le_bytes());
-        buffer.put_u8(query_parameters_line.cwy.into());
+        let cwy: u8 = query_parameters_line.cwy.into();
+        let measure = match query_parameters_line.measure {
+            Measure::slk => 0,
+            Measure::true_dist => FLAG_TRUE_DIST,
+        };
+        match query_parameters_line.offset_to {
+            Some(offset_to) => {
+                buffer.put_u8(cwy | measure | FLAG_OFFSET_TO);
+                buffer.put_slice(&offset_to.to_le_bytes());
+            },
+            None => buffer.put_u8(cwy | measure),
+        }
         // Convert BytesMut into Bytes
         buffer.freeze()
     }
@@ -111,8 +168,21 @@ mod tests {
             slk_to: 2.0,
             cwy: RequestedCwy::LRS,
             offset: 0.0,
+            offset_from: None,
+            offset_to: None,
+            offset_left: None,
+            offset_right: None,
+            buffer: None,
+            merge: false,
+            merge_tolerance: 1.0,
+            simplify: None,
+            densify: None,
             m: false,
             f: OutputFormatLines::json,
+            measure: Measure::slk,
+            network_type: None,
+            lg_name: None,
+            as_of: None,
         };
 
         let binary = create_sample_binary(sample.clone());
@@ -122,4 +192,79 @@ mod tests {
             Err(_) => panic!("Deserialization failed"),
         }
     }
+
+    #[test]
+    fn test_query_parameter_batch_true_dist() {
+        let sample = QueryParametersLine {
+            road: "H001".to_string(),
+            slk_from: 1.0,
+            slk_to: 2.0,
+            cwy: RequestedCwy::LS,
+            offset: 0.0,
+            offset_from: None,
+            offset_to: None,
+            offset_left: None,
+            offset_right: None,
+            buffer: None,
+            merge: false,
+            merge_tolerance: 1.0,
+            simplify: None,
+            densify: None,
+            m: false,
+            f: OutputFormatLines::json,
+            measure: Measure::true_dist,
+            network_type: None,
+            lg_name: None,
+            as_of: None,
+        };
+
+        let binary = create_sample_binary(sample.clone());
+        assert_eq!(binary[binary.len() - 1], 0b0000_1110);
+
+        let batch = QueryParameterBatch::try_from(binary).unwrap();
+        assert_eq!(batch.0[0], sample);
+    }
+
+    #[test]
+    fn test_query_parameter_batch_offset_to() {
+        let tapered = QueryParametersLine {
+            road: "H001".to_string(),
+            slk_from: 1.0,
+            slk_to: 2.0,
+            cwy: RequestedCwy::L,
+            offset: 2.0,
+            offset_from: Some(2.0),
+            offset_to: Some(5.5),
+            offset_left: None,
+            offset_right: None,
+            buffer: None,
+            merge: false,
+            merge_tolerance: 1.0,
+            simplify: None,
+            densify: None,
+            m: false,
+            f: OutputFormatLines::json,
+            measure: Measure::slk,
+            network_type: None,
+            lg_name: None,
+            as_of: None,
+        };
+        let constant = QueryParametersLine {
+            road: "H002".to_string(),
+            offset_from: None,
+            offset_to: None,
+            ..tapered.clone()
+        };
+
+        // old and new frames can be mixed in one request
+        let mut binary = BytesMut::new();
+        binary.put(create_sample_binary(tapered.clone()));
+        binary.put(create_sample_binary(constant.clone()));
+        let batch = QueryParameterBatch::try_from(binary.freeze()).unwrap();
+        assert_eq!(batch.0, vec![tapered.clone(), constant]);
+
+        // offset_to is missing
+        let binary = create_sample_binary(tapered);
+        assert!(QueryParameterBatch::try_from(binary.slice(..binary.len() - 2)).is_err());
+    }
 }
@@ -0,0 +1,56 @@
+use serde::Deserialize;
+
+use crate::{
+    data::DiffFormat,
+    helpers::{serde_helpers::f64_finite_or_fail, CalendarDate},
+};
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QueryParametersDiff {
+    /// The older version of the network is the snapshot in use on this date
+    pub from: CalendarDate,
+
+    #[serde(default)]
+    /// The newer version of the network. The current data is used if not set
+    pub to: Option<CalendarDate>,
+
+    #[serde(default)]
+    /// `geojson` or `csv`
+    pub f: DiffFormat,
+
+    #[serde(default="default_tolerance", deserialize_with = "f64_finite_or_fail")]
+    /// Geometry which moved further than this many metres is reported
+    pub tolerance: f64,
+}
+
+fn default_tolerance() -> f64 {
+    5.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_parameters_diff() {
+        let query: QueryParametersDiff = serde_urlencoded::from_str("from=2024-01-31").unwrap();
+        assert_eq!(query, QueryParametersDiff{
+            from: CalendarDate::new(2024, 1, 31).unwrap(),
+            to: None,
+            f: DiffFormat::geojson,
+            tolerance: 5.0,
+        });
+
+        let query: QueryParametersDiff = serde_urlencoded::from_str("from=2024-01-31&to=2024-06-30&f=csv&tolerance=0.5").unwrap();
+        assert_eq!(query.to, CalendarDate::new(2024, 6, 30));
+        assert_eq!(query.f, DiffFormat::csv);
+        assert_eq!(query.tolerance, 0.5);
+
+        let query: Result<QueryParametersDiff, _> = serde_urlencoded::from_str("to=2024-06-30");
+        assert!(query.is_err());
+
+        let query: Result<QueryParametersDiff, _> = serde_urlencoded::from_str("from=2024-01-31&tolerance=inf");
+        assert!(query.is_err());
+    }
+}
@@ -1,8 +1,10 @@
 use serde::Deserialize;
 
-use crate::helpers::serde_helpers::{f32_finite_or_zero, f32_not_nan_or_fail};
+use crate::helpers::CalendarDate;
+use crate::helpers::serde_helpers::{f32_finite_or_fail, f32_finite_or_zero, f32_not_nan_or_fail, option_f32_finite_or_fail};
 
-use super::RequestedCwy;
+use crate::data::cached::Attributes;
+use super::{attributes_match_filters, Measure, RequestedCwy};
 use super::output_format::OutputFormatLines;
 
 #[derive(Deserialize, Debug, PartialEq, Clone)]
@@ -30,6 +32,49 @@ pub struct QueryParametersLine {
     /// will offset to the left, and positive values to the right.
 	pub offset:f32,
 
+	#[serde(default, deserialize_with = "option_f32_finite_or_fail")] // default None
+	/// The offset in metres at `slk_from`, when it should change along the
+	/// line. Defaults to `offset`
+	pub offset_from:Option<f32>,
+
+	#[serde(default, deserialize_with = "option_f32_finite_or_fail")] // default None
+	/// The offset in metres at `slk_to`. Defaults to `offset`
+	pub offset_to:Option<f32>,
+
+	#[serde(default, deserialize_with = "option_f32_finite_or_fail")] // default None
+	/// Return the area between this many metres to the left and
+	/// `offset_right` metres to the right as a polygon instead of a line.
+	/// Negative values are to the right. The side not given is at `offset`
+	pub offset_left:Option<f32>,
+
+	#[serde(default, deserialize_with = "option_f32_finite_or_fail")] // default None
+	/// Metres to the right; negative values are to the left. See
+	/// `offset_left`
+	pub offset_right:Option<f32>,
+
+	#[serde(default, deserialize_with = "option_f32_finite_or_fail")] // default None
+	/// Return the area within this many metres either side of the (offset)
+	/// line as a polygon instead of a line
+	pub buffer:Option<f32>,
+
+	#[serde(default)] // default false
+	/// Join consecutive parts of the same carriageway whose ends are within
+	/// `merge_tolerance` metres of each other into one line
+	pub merge:bool,
+
+	#[serde(default="default_merge_tolerance", deserialize_with = "f32_finite_or_fail")]
+	/// See `merge`
+	pub merge_tolerance:f32,
+
+	#[serde(default, deserialize_with = "option_f32_finite_or_fail")] // default None
+	/// Remove vertexes which are less than this many metres from the line
+	/// without them (Douglas-Peucker)
+	pub simplify:Option<f32>,
+
+	#[serde(default, deserialize_with = "option_f32_finite_or_fail")] // default None
+	/// Add vertexes so that no two are more than this many metres apart
+	pub densify:Option<f32>,
+
 	#[serde(default)] // default GEOJSON
     /// The output data format to be returned by the server
 	pub f: OutputFormatLines,
@@ -39,9 +84,70 @@ pub struct QueryParametersLine {
     /// possible
 	pub m:bool,
 
+	#[serde(default)] // default slk
+	/// Whether `slk_from` and `slk_to` are SLK or true distance
+	pub measure: Measure,
+
+	#[serde(default)] // default None
+	/// Only include features with this `NETWORK_TYPE` (eg "State Road")
+	pub network_type: Option<String>,
+
+	#[serde(default)] // default None
+	/// Only include features with this `LG_NAME` (eg "City of Perth")
+	pub lg_name: Option<String>,
+
+	#[serde(default)] // default None
+	/// Answer the query using the network as it was on this date (YYYY-MM-DD)
+	pub as_of: Option<CalendarDate>,
+
 }
 
 impl QueryParametersLine {
+    /// Returns true if a feature with these attributes passes the
+    /// `network_type=` and `lg_name=` filters
+    pub fn includes_attributes(&self, attributes: &Attributes) -> bool {
+        attributes_match_filters(attributes, &self.network_type, &self.lg_name)
+    }
+
+    /// The offset at `slk_from` and at `slk_to`
+    pub fn offset_range(&self) -> (f32, f32) {
+        (
+            self.offset_from.unwrap_or(self.offset),
+            self.offset_to.unwrap_or(self.offset),
+        )
+    }
+
+    /// True if the offset changes along the line
+    pub fn is_tapered(&self) -> bool {
+        let (offset_from, offset_to) = self.offset_range();
+        offset_from != offset_to
+    }
+
+    /// The offset at `slk` (in the requested [Measure]), found by linear
+    /// interpolation from `offset_from` at `slk_from` to `offset_to` at
+    /// `slk_to`. Only meaningful when `slk_from` and `slk_to` are finite.
+    pub fn offset_at(&self, slk: f64) -> f64 {
+        let (offset_from, offset_to) = self.offset_range();
+        let length = self.slk_to as f64 - self.slk_from as f64;
+        if length == 0.0 {
+            return offset_from as f64;
+        }
+        let fraction = (slk - self.slk_from as f64) / length;
+        offset_from as f64 + (offset_to as f64 - offset_from as f64) * fraction
+    }
+
+    /// The offsets of the two sides of the polygon to return instead of a
+    /// line, with the same sign as `offset` (positive to the right), smallest
+    /// first. `None` if a line was requested.
+    pub fn polygon_offsets(&self) -> Option<(f32, f32)> {
+        let (left, right) = match (self.buffer, self.offset_left, self.offset_right) {
+            (Some(buffer), _, _) => (self.offset - buffer.abs(), self.offset + buffer.abs()),
+            (None, None, None) => return None,
+            (None, left, right) => (left.map_or(self.offset, |left| -left), right.unwrap_or(self.offset)),
+        };
+        Some((left.min(right), left.max(right)))
+    }
+
     pub fn with_format(&self, format:&OutputFormatLines) -> Self{
         QueryParametersLine{
             f:format.clone(),
@@ -58,6 +164,10 @@ fn default_slk_to() -> f32 {
     f32::INFINITY
 }
 
+fn default_merge_tolerance() -> f32 {
+    1.0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -74,9 +184,22 @@ mod tests {
             slk_from: 5.0,
             slk_to: 6.0,
             offset: 0.0,
+            offset_from: None,
+            offset_to: None,
+            offset_left: None,
+            offset_right: None,
+            buffer: None,
+            merge: false,
+            merge_tolerance: 1.0,
+            simplify: None,
+            densify: None,
             cwy: RequestedCwy::LRS,
             f: OutputFormatLines::geojson,
             m: false,
+            measure: Measure::slk,
+            network_type: None,
+            lg_name: None,
+            as_of: None,
         });
     }
 
@@ -91,16 +214,29 @@ mod tests {
             slk_from: f32::NEG_INFINITY,
             slk_to: f32::INFINITY,
             offset: 0.0,
+            offset_from: None,
+            offset_to: None,
+            offset_left: None,
+            offset_right: None,
+            buffer: None,
+            merge: false,
+            merge_tolerance: 1.0,
+            simplify: None,
+            densify: None,
             cwy: RequestedCwy::LRS,
             f: OutputFormatLines::geojson,
             m: false,
+            measure: Measure::slk,
+            network_type: None,
+            lg_name: None,
+            as_of: None,
         });
     }
 
     #[test]
     /// Test when all parameters are provided.
     fn test_query_parameters_line_all() {
-        let query = format!("{}&cwy=LS&offset=10&f=wkt&m=true", CONFIRMED_WORKING_MINIMUM);
+        let query = format!("{}&cwy=LS&offset=10&f=wkt&m=true&measure=true_dist&network_type=State%20Road&lg_name=City+of+Perth&as_of=2020-01-31&merge=true&merge_tolerance=2.5&simplify=5&densify=10", CONFIRMED_WORKING_MINIMUM);
         let query: QueryParametersLine = serde_urlencoded::from_str(&query).unwrap();
         assert_eq!(query, QueryParametersLine{
             road: "H001".to_string(),
@@ -108,8 +244,21 @@ mod tests {
             slk_to: 6.0,
             cwy: RequestedCwy::LS,
             offset: 10.0,
+            offset_from: None,
+            offset_to: None,
+            offset_left: None,
+            offset_right: None,
+            buffer: None,
+            merge: true,
+            merge_tolerance: 2.5,
+            simplify: Some(5.0),
+            densify: Some(10.0),
             f: OutputFormatLines::wkt,
-            m:true
+            m:true,
+            measure: Measure::true_dist,
+            network_type: Some("State Road".to_string()),
+            lg_name: Some("City of Perth".to_string()),
+            as_of: CalendarDate::new(2020, 1, 31),
         });
     }
 
@@ -124,11 +273,83 @@ mod tests {
             slk_to: 6.0,
             cwy: RequestedCwy::LRS,
             offset: 0.0,
+            offset_from: None,
+            offset_to: None,
+            offset_left: None,
+            offset_right: None,
+            buffer: None,
+            merge: false,
+            merge_tolerance: 1.0,
+            simplify: None,
+            densify: None,
             f: OutputFormatLines::geojson,
             m: false,
+            measure: Measure::slk,
+            network_type: None,
+            lg_name: None,
+            as_of: None,
         });
     }
 
+    #[test]
+    /// Test a tapered offset
+    fn test_query_parameters_line_offset_from_to() {
+        let query = format!("{}&offset=1&offset_from=-2&offset_to=4", CONFIRMED_WORKING_MINIMUM);
+        let query: QueryParametersLine = serde_urlencoded::from_str(&query).unwrap();
+        assert_eq!(query.offset_range(), (-2.0, 4.0));
+        assert!(query.is_tapered());
+        assert_eq!(query.offset_at(5.0), -2.0);
+        assert_eq!(query.offset_at(5.5), 1.0);
+        assert_eq!(query.offset_at(6.0), 4.0);
+
+        // either end defaults to offset
+        let query = format!("{}&offset=3&offset_to=5", CONFIRMED_WORKING_MINIMUM);
+        let query: QueryParametersLine = serde_urlencoded::from_str(&query).unwrap();
+        assert_eq!(query.offset_range(), (3.0, 5.0));
+
+        let query = format!("{}&offset=3&offset_from=3", CONFIRMED_WORKING_MINIMUM);
+        let query: QueryParametersLine = serde_urlencoded::from_str(&query).unwrap();
+        assert!(!query.is_tapered());
+
+        let query = format!("{}&offset_from=nan&offset_to=1", CONFIRMED_WORKING_MINIMUM);
+        let query: Result<QueryParametersLine, _> = serde_urlencoded::from_str(&query);
+        assert!(query.is_err());
+    }
+
+    #[test]
+    /// Test polygon requests
+    fn test_query_parameters_line_polygon_offsets() {
+        let query: QueryParametersLine = serde_urlencoded::from_str(CONFIRMED_WORKING_MINIMUM).unwrap();
+        assert_eq!(query.polygon_offsets(), None);
+
+        let query = format!("{}&offset=2&buffer=1.5", CONFIRMED_WORKING_MINIMUM);
+        let query: QueryParametersLine = serde_urlencoded::from_str(&query).unwrap();
+        assert_eq!(query.polygon_offsets(), Some((0.5, 3.5)));
+
+        // offset_left is measured to the left, so is negated
+        let query = format!("{}&offset_left=3.5&offset_right=3.5", CONFIRMED_WORKING_MINIMUM);
+        let query: QueryParametersLine = serde_urlencoded::from_str(&query).unwrap();
+        assert_eq!(query.polygon_offsets(), Some((-3.5, 3.5)));
+
+        // both sides to the right of the centreline
+        let query = format!("{}&offset_left=-2&offset_right=5", CONFIRMED_WORKING_MINIMUM);
+        let query: QueryParametersLine = serde_urlencoded::from_str(&query).unwrap();
+        assert_eq!(query.polygon_offsets(), Some((2.0, 5.0)));
+
+        // the other side defaults to offset
+        let query = format!("{}&offset_left=3.5", CONFIRMED_WORKING_MINIMUM);
+        let query: QueryParametersLine = serde_urlencoded::from_str(&query).unwrap();
+        assert_eq!(query.polygon_offsets(), Some((-3.5, 0.0)));
+
+        let query = format!("{}&offset=1&offset_right=3.5", CONFIRMED_WORKING_MINIMUM);
+        let query: QueryParametersLine = serde_urlencoded::from_str(&query).unwrap();
+        assert_eq!(query.polygon_offsets(), Some((1.0, 3.5)));
+
+        let query = format!("{}&buffer=Infinity", CONFIRMED_WORKING_MINIMUM);
+        let query: Result<QueryParametersLine, _> = serde_urlencoded::from_str(&query);
+        assert!(query.is_err());
+    }
+
     #[test]
     /// Test infinite offset should fail to parse and reject the query
     fn test_query_parameters_line_offset_infinity_fails() {
@@ -0,0 +1,87 @@
+use serde::Deserialize;
+
+use crate::helpers::serde_helpers::{f32_finite_or_fail, f64_finite_or_fail};
+
+use super::RequestedCwy;
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QueryParametersLocate {
+    #[serde(deserialize_with = "f64_finite_or_fail")]
+    /// Longitude of the point to be located
+    pub lon: f64,
+
+    #[serde(deserialize_with = "f64_finite_or_fail")]
+    /// Latitude of the point to be located
+    pub lat: f64,
+
+    #[serde(default)]
+    /// Optionally restrict the search to a single road number (eg "H001")
+    pub road: Option<String>,
+
+    #[serde(default)] // default LRS
+    /// The carriageway filter; all carriageways are searched by default
+    pub cwy: RequestedCwy,
+
+    #[serde(default="default_radius", deserialize_with = "f32_finite_or_fail")]
+    /// The maximum distance in metres from the point to the road centreline
+    pub radius: f32,
+}
+
+fn default_radius() -> f32 {
+    100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test when minimum parameters are provided
+    fn test_query_parameters_locate_defaults() {
+        let query = "lon=115.89&lat=-31.97";
+        let query: QueryParametersLocate = serde_urlencoded::from_str(query).unwrap();
+        assert_eq!(query, QueryParametersLocate{
+            lon: 115.89,
+            lat: -31.97,
+            road: None,
+            cwy: RequestedCwy::LRS,
+            radius: 100.0,
+        });
+    }
+
+    #[test]
+    /// Test when all parameters are provided
+    fn test_query_parameters_locate_all() {
+        let query = "lon=115.89&lat=-31.97&road=H001&cwy=L&radius=20";
+        let query: QueryParametersLocate = serde_urlencoded::from_str(query).unwrap();
+        assert_eq!(query, QueryParametersLocate{
+            lon: 115.89,
+            lat: -31.97,
+            road: Some("H001".to_string()),
+            cwy: RequestedCwy::L,
+            radius: 20.0,
+        });
+    }
+
+    #[test]
+    /// Coordinates must be finite and must not be omitted
+    fn test_query_parameters_locate_invalid_coordinates() {
+        let query:Result<QueryParametersLocate, _> = serde_urlencoded::from_str("lon=nan&lat=-31.97");
+        assert!(query.is_err());
+
+        let query:Result<QueryParametersLocate, _> = serde_urlencoded::from_str("lon=115.89&lat=Infinity");
+        assert!(query.is_err());
+
+        let query:Result<QueryParametersLocate, _> = serde_urlencoded::from_str("lon=115.89");
+        assert!(query.is_err());
+    }
+
+    #[test]
+    /// don't allow unknown fields (eg misspelling `cwy=` as `cway=`)
+    fn test_query_parameters_locate_deny_unknown_fields() {
+        let query = "lon=115.89&lat=-31.97&cway=L";
+        let query:Result<QueryParametersLocate, _> = serde_urlencoded::from_str(query);
+        assert!(query.is_err());
+    }
+}
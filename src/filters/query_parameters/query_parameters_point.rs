@@ -1,8 +1,10 @@
 use serde::Deserialize;
 
+use crate::helpers::CalendarDate;
 use crate::helpers::serde_helpers::{f32_finite_or_fail, f32_finite_or_zero};
 
-use super::RequestedCwy;
+use crate::data::cached::Attributes;
+use super::{attributes_match_filters, Measure, RequestedCwy};
 use super::output_format::OutputFormatPoints;
 
 
@@ -29,16 +31,42 @@ pub struct QueryParametersPoint {
     /// format of the response
 	pub f: OutputFormatPoints,
 
+	#[serde(default)]
+	/// Whether `slk` is SLK or true distance
+	pub measure: Measure,
+
+	#[serde(default)]
+	/// Only include features with this `NETWORK_TYPE` (eg "State Road")
+	pub network_type: Option<String>,
+
+	#[serde(default)]
+	/// Only include features with this `LG_NAME` (eg "City of Perth")
+	pub lg_name: Option<String>,
+
+	#[serde(default)]
+	/// Answer the query using the network as it was on this date (YYYY-MM-DD)
+	pub as_of: Option<CalendarDate>,
+
 }
 
 impl QueryParametersPoint {
+    /// Returns true if a feature with these attributes passes the
+    /// `network_type=` and `lg_name=` filters
+    pub fn includes_attributes(&self, attributes: &Attributes) -> bool {
+        attributes_match_filters(attributes, &self.network_type, &self.lg_name)
+    }
+
     pub fn with_format(&self, format:&OutputFormatPoints) -> Self{
         QueryParametersPoint{
             f:format.clone(), // TODO: SHould not clone inside function i think???
             cwy:self.cwy,
             offset:self.offset,
             road:self.road.clone(),
-            slk:self.slk
+            slk:self.slk,
+            measure:self.measure,
+            network_type:self.network_type.clone(),
+            lg_name:self.lg_name.clone(),
+            as_of:self.as_of,
         }
     }
 }
@@ -60,13 +88,17 @@ mod tests {
             slk: 5.0,
             offset: 0.0,
             f: OutputFormatPoints::geojson,
+            measure: Measure::slk,
+            network_type: None,
+            lg_name: None,
+            as_of: None,
         });
     }
 
     #[test]
     /// Test when all parameters are provided
     fn test_query_parameters_point_all() {
-        let query = "road=H001&slk=5&cwy=LS&offset=10&f=wkt";
+        let query = "road=H001&slk=5&cwy=LS&offset=10&f=wkt&measure=true_dist&network_type=Local+Road&lg_name=Shire%20of%20Broome&as_of=2020-01-31";
         let query: QueryParametersPoint = serde_urlencoded::from_str(query).unwrap();
         assert_eq!(query, QueryParametersPoint{
             road: "H001".to_string(),
@@ -74,6 +106,10 @@ mod tests {
             slk: 5.0,
             offset: 10.0,
             f: OutputFormatPoints::wkt,
+            measure: Measure::true_dist,
+            network_type: Some("Local Road".to_string()),
+            lg_name: Some("Shire of Broome".to_string()),
+            as_of: CalendarDate::new(2020, 1, 31),
         });
     }
 
@@ -88,6 +124,10 @@ mod tests {
             slk: 5.0,
             offset: 0.0,
             f: OutputFormatPoints::geojson,
+            measure: Measure::slk,
+            network_type: None,
+            lg_name: None,
+            as_of: None,
         });
     }
 
@@ -0,0 +1,38 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[allow(non_camel_case_types)]
+pub enum ReloadSource {
+    /// Download fresh data from `NLR_DATA_SOURCE_URL` and overwrite the cache
+    /// file
+    #[default]
+    download,
+    /// Read the cache file at `NLR_DATA_FILE`. If it cannot be read, fresh
+    /// data is downloaded instead
+    file,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QueryParametersReload {
+    #[serde(default)] // default download
+    /// Where the new data should come from
+    pub source: ReloadSource,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_parameters_reload() {
+        let query: QueryParametersReload = serde_urlencoded::from_str("").unwrap();
+        assert_eq!(query.source, ReloadSource::download);
+
+        let query: QueryParametersReload = serde_urlencoded::from_str("source=file").unwrap();
+        assert_eq!(query.source, ReloadSource::file);
+
+        let query: Result<QueryParametersReload, _> = serde_urlencoded::from_str("source=ftp");
+        assert!(query.is_err());
+    }
+}
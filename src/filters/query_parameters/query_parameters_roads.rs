@@ -0,0 +1,68 @@
+use serde::{Deserialize, Deserializer};
+
+/// The longest `q=` accepted. Longer searches can not match a road number
+/// or name, and would make the fuzzy match slow.
+const MAX_SEARCH_LENGTH: usize = 64;
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QueryParametersRoads {
+    /// Part of a road number or road name (eg "H1" or "albany")
+    #[serde(deserialize_with="search_text")]
+    pub q: String,
+
+    #[serde(default="default_limit")]
+    /// The maximum number of roads to return. Values over 100 are treated as
+    /// 100
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+/// Fail on searches longer than [MAX_SEARCH_LENGTH] characters
+fn search_text<'de, D>(deserializer: D) -> Result<String, D::Error>
+where D:Deserializer<'de>{
+    let result = String::deserialize(deserializer)?;
+    match result {
+        result if result.chars().count() > MAX_SEARCH_LENGTH => Err(serde::de::Error::custom("q must be at most 64 characters")),
+        result => Ok(result)
+    }
+}
+
+impl QueryParametersRoads {
+    pub fn limit(&self) -> usize {
+        self.limit.min(100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_parameters_roads() {
+        let query: QueryParametersRoads = serde_urlencoded::from_str("q=H1").unwrap();
+        assert_eq!(query, QueryParametersRoads{
+            q: "H1".to_string(),
+            limit: 10,
+        });
+
+        let query: QueryParametersRoads = serde_urlencoded::from_str("q=albany%20hwy&limit=1000").unwrap();
+        assert_eq!(query.q, "albany hwy");
+        assert_eq!(query.limit(), 100);
+
+        let query: Result<QueryParametersRoads, _> = serde_urlencoded::from_str("limit=5");
+        assert!(query.is_err());
+
+        let query: Result<QueryParametersRoads, _> = serde_urlencoded::from_str("q=H1&road=H1");
+        assert!(query.is_err());
+
+        let query: Result<QueryParametersRoads, _> = serde_urlencoded::from_str(&format!("q={}", "a".repeat(64)));
+        assert!(query.is_ok());
+
+        let query: Result<QueryParametersRoads, _> = serde_urlencoded::from_str(&format!("q={}", "a".repeat(65)));
+        assert!(query.is_err());
+    }
+}
@@ -30,7 +30,8 @@ pub struct QueryParametersUnifiedGet{
 
 #[cfg(test)]
 mod tests {
-    use crate::filters::query_parameters::{output_format::{OutputFormatLines, OutputFormatPoints}, RequestedCwy};
+    use crate::filters::query_parameters::{output_format::{OutputFormatLines, OutputFormatPoints}, Measure, RequestedCwy};
+    use crate::helpers::CalendarDate;
 
     use super::*;
 
@@ -51,7 +52,8 @@ mod tests {
                 },
                 {
                     "road":"H015",
-                    "slk":10
+                    "slk":10,
+                    "as_of":"2020-01-31"
                 }
             ]
         }
@@ -67,23 +69,44 @@ mod tests {
                     slk_from: 10.0,
                     slk_to: 20.0,
                     offset: 10.0,
+                    offset_from: None,
+                    offset_to: None,
+                    offset_left: None,
+                    offset_right: None,
+                    buffer: None,
+                    merge: false,
+                    merge_tolerance: 1.0,
+                    simplify: None,
+                    densify: None,
                     f: OutputFormatLines::geojson,
                     cwy: RequestedCwy::LRS,
-                    m: false
+                    m: false,
+                    measure: Measure::slk,
+                    network_type: None,
+                    lg_name: None,
+                    as_of: None,
                 }),
                 QueryParametersPointLine::Point(QueryParametersPoint {
                     road: String::from("H016"),
                     slk: 10.0,
                     cwy: RequestedCwy::LRS,
                     offset:0.0,
-                    f:OutputFormatPoints::geojson
+                    f:OutputFormatPoints::geojson,
+                    measure: Measure::slk,
+                    network_type: None,
+                    lg_name: None,
+                    as_of: None,
                 }),
                 QueryParametersPointLine::Point(QueryParametersPoint {
                     road: String::from("H015"),
                     slk: 10.0,
                     cwy: RequestedCwy::LRS,
                     offset:0.0,
-                    f:OutputFormatPoints::geojson
+                    f:OutputFormatPoints::geojson,
+                    measure: Measure::slk,
+                    network_type: None,
+                    lg_name: None,
+                    as_of: CalendarDate::new(2020, 1, 31),
                 }),
             ],
         };
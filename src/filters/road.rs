@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use warp::Filter;
+
+use crate::data::{IndexedData, SharedIndexedData};
+
+use super::{
+    geoprocessing::get_road_metadata,
+    with_current_data
+};
+
+/// Describe the carriageways, SLK extents and gaps of a single road
+pub fn road(
+    shared_data: Arc<SharedIndexedData>
+) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::path("road")
+    .and(warp::path::param::<String>())
+    .and(warp::path::end())
+    .and(warp::get())
+    .and(with_current_data(shared_data.clone()))
+    .and_then(|
+        road: String,
+        indexed_data: Arc<IndexedData>
+    | async move {
+        get_road_metadata(&road, &indexed_data).map_err(|err|err.as_rejection())
+    })
+}
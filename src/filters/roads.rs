@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use warp::Filter;
+
+use crate::{
+    data::{IndexedData, SharedIndexedData},
+    helpers::ErrorWithStaticMessage,
+};
+
+use super::{
+    query_parameters::QueryParametersRoads,
+    with_current_data
+};
+
+/// Search for roads by road number or name; intended for type-ahead boxes
+pub fn roads(
+    shared_data: Arc<SharedIndexedData>
+) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::path("roads")
+    .and(warp::path::end())
+    .and(warp::get())
+    .and(with_current_data(shared_data.clone()))
+    .and(warp::query())
+    .and_then(|
+        indexed_data: Arc<IndexedData>,
+        query: QueryParametersRoads
+    | async move {
+        let roads = indexed_data.search_roads(&query.q, query.limit());
+        serde_json::to_string(&roads)
+            .map_err(|_| ErrorWithStaticMessage::reject("Unable to serialise roads"))
+    })
+}
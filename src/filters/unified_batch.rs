@@ -1,9 +1,9 @@
-use std::sync::Arc;
+use std::{collections::BTreeSet, sync::Arc};
 
-use warp::Filter;
+use warp::{reply::Response, Filter, Reply};
 
 use crate::{
-    data::IndexedData,
+    data::SharedIndexedData,
     filters::{
         geoprocessing::get_linestring,
         query_parameters::output_format::{OutputFormatLines, OutputFormatPoints, OutputFormatUnified}
@@ -14,21 +14,64 @@ use crate::{
 use super::{
     geoprocessing::get_points,
     query_parameters::{QueryParametersPointLine, QueryParametersUnifiedGet, QueryParametersUnifiedPost},
+    with_data_version,
     with_shared_data
 };
 
+/// Answer a single item of the batch. Each item may ask for a different
+/// `as_of` date, so the version of the data used is added to
+/// `data_versions`. Returns `"null"` if the item fails.
+async fn get_item(
+    request: &QueryParametersPointLine,
+    format: &OutputFormatUnified,
+    shared_data: &SharedIndexedData,
+    data_versions: &mut BTreeSet<String>,
+) -> String {
+    use QueryParametersPointLine::*;
+    match request {
+        Point(point_request) => {
+            let format:OutputFormatPoints = format.clone().into();
+            match shared_data.as_of(&point_request.as_of).await {
+                Ok(Some(indexed_data)) => {
+                    data_versions.insert(indexed_data.data_version().to_owned());
+                    get_points(&point_request.with_format(&format), &indexed_data).unwrap_or("null".to_owned())
+                },
+                _ => "null".to_owned(),
+            }
+        },
+        Line (line_request)  =>{
+            let format:OutputFormatLines = format.clone().into();
+            match shared_data.as_of(&line_request.as_of).await {
+                Ok(Some(indexed_data)) => {
+                    data_versions.insert(indexed_data.data_version().to_owned());
+                    get_linestring(&line_request.with_format(&format), &indexed_data).unwrap_or("null".to_owned())
+                },
+                _ => "null".to_owned(),
+            }
+        },
+    }
+}
+
+/// The response to a batch. Items answered from different snapshots are
+/// reported by joining their versions with `+`. If no item could load any
+/// data the version of the current data is reported.
+fn batch_response(body: String, data_versions: BTreeSet<String>) -> Response {
+    if data_versions.is_empty() {
+        return body.into_response();
+    }
+    with_data_version(body, &data_versions.into_iter().collect::<Vec<_>>().join("+"))
+}
 
 pub fn unified_batch(
-    indexed_data: Arc<IndexedData>
-) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
-    use QueryParametersPointLine::*;
+    shared_data: Arc<SharedIndexedData>
+) -> impl Filter<Extract = (Response,), Error = warp::Rejection> + Clone {
     warp::path("batch2").and(warp::path::end())
     .and(
         warp::post()
-        .and(with_shared_data(indexed_data.clone()))
+        .and(with_shared_data(shared_data.clone()))
         .and(warp::body::json())
         .and_then(|
-                indexed_data: Arc<IndexedData>,
+                shared_data: Arc<SharedIndexedData>,
                 query: QueryParametersUnifiedPost,
             | async move {
                 // TODO: must not be used with non JSON return types... or those must be handled differently?
@@ -37,24 +80,19 @@ pub fn unified_batch(
                     format,
                     items
                 } = query;
-                let results:Vec<String> = items.iter().map(|request| match request {
-                    Point(point_request) => {
-                        let format:OutputFormatPoints = format.clone().into();
-                        get_points(&point_request.with_format(&format), &indexed_data).unwrap_or("null".to_owned())
-                    },
-                    Line (line_request)  =>{
-                        let format:OutputFormatLines = format.clone().into();
-                        get_linestring(&line_request.with_format(&format), &indexed_data).unwrap_or("null".to_owned())
-                    },
-                }).collect();
-                Ok::<std::string::String, warp::Rejection>(format!("[{}]",results.join(",")))
+                let mut results:Vec<String> = Vec::with_capacity(items.len());
+                let mut data_versions = BTreeSet::new();
+                for request in items.iter() {
+                    results.push(get_item(request, &format, &shared_data, &mut data_versions).await);
+                }
+                Ok::<Response, warp::Rejection>(batch_response(format!("[{}]",results.join(",")), data_versions))
             })
     ).or(
             warp::get()
-            .and(with_shared_data(indexed_data.clone()))
+            .and(with_shared_data(shared_data.clone()))
             .and(warp::query())
             .and_then(|
-                indexed_data: Arc<IndexedData>,
+                shared_data: Arc<SharedIndexedData>,
                 query: QueryParametersUnifiedGet,
             | async move {
                 let QueryParametersUnifiedGet{
@@ -64,32 +102,25 @@ pub fn unified_batch(
                 let query:Result<Vec<QueryParametersPointLine>, _> = serde_json::from_str(items.as_str());
                 match query {
                     Ok(items)=>{
-                        let results = items.iter().map(|request| match request {
-                            Point(point_request) => {
-                                let format:OutputFormatPoints = format.clone().into();
-                                get_points(&point_request.with_format(&format), &indexed_data).unwrap_or("null".to_owned())
-                            },
-                            Line (line_request)  =>{
-                                let format:OutputFormatLines = format.clone().into();
-                                get_linestring(&line_request.with_format(&format), &indexed_data).unwrap_or("null".to_owned())
-                            },
-                        });
+                        let mut results:Vec<String> = Vec::with_capacity(items.len());
+                        let mut data_versions = BTreeSet::new();
+                        for request in items.iter() {
+                            results.push(get_item(request, &format, &shared_data, &mut data_versions).await);
+                        }
                         let results:Vec<String> = if format==OutputFormatUnified::wkt {
-                            results.map(|item| match item.as_str(){
+                            results.into_iter().map(|item| match item.as_str(){
                                 "null" => item,
                                 item   => format!(r#""{}""#, item)
                             }).collect()
                         }else{
-                            results.collect()
+                            results
                         };
-                        Ok::<std::string::String, _>(format!("[{}]",results.join(",")))
+                        Ok::<Response, _>(batch_response(format!("[{}]",results.join(",")), data_versions))
                     },
                     Err(_)=>Err(warp::reject()) // TODO: Add custom rejection
                 }
             })
         )
         .unify()
-   
-}
-
 
+}
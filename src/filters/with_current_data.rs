@@ -0,0 +1,13 @@
+use std::{convert::Infallible, sync::Arc};
+use warp::Filter;
+
+use crate::data::{IndexedData, SharedIndexedData};
+
+/// Like [super::with_shared_data], but provides whichever [IndexedData] is
+/// current at the moment the request arrives. The request keeps using that
+/// snapshot even if the data is reloaded before the request completes.
+pub fn with_current_data(
+    shared_data: Arc<SharedIndexedData>,
+) -> impl Filter<Extract = (Arc<IndexedData>,), Error = Infallible> + Clone {
+    warp::any().map(move || shared_data.current())
+}
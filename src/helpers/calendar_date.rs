@@ -0,0 +1,138 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A date without a time, written as `YYYY-MM-DD`. Used to label snapshots of
+/// the road network by the day they were downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CalendarDate {
+    // field order matters; the derived Ord compares year, then month, then day
+    year: i32,
+    month: u8,
+    day: u8,
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl CalendarDate {
+    /// Returns `None` if the day does not exist
+    pub fn new(year: i32, month: u8, day: u8) -> Option<Self> {
+        if (1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month) {
+            Some(Self { year, month, day })
+        } else {
+            None
+        }
+    }
+
+    /// The current date in UTC
+    pub fn today() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        Self::from_days_since_epoch((seconds / 86_400) as i64)
+    }
+
+    /// Convert a count of days since 1970-01-01. See
+    /// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    fn from_days_since_epoch(days: i64) -> Self {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+        let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u8;
+        let year = (year_of_era + era * 400 + i64::from(month <= 2)) as i32;
+        Self { year, month, day }
+    }
+}
+
+impl fmt::Display for CalendarDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for CalendarDate {
+    type Err = &'static str;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        const EXPECTED: &str = "expected a date formatted as YYYY-MM-DD";
+        let parts: Vec<&str> = text.split('-').collect();
+        match parts[..] {
+            [year, month, day]
+                if year.len() == 4
+                    && month.len() == 2
+                    && day.len() == 2
+                    && text.chars().all(|c| c.is_ascii_digit() || c == '-') =>
+            {
+                Self::new(
+                    year.parse().map_err(|_| EXPECTED)?,
+                    month.parse().map_err(|_| EXPECTED)?,
+                    day.parse().map_err(|_| EXPECTED)?,
+                ).ok_or("the date does not exist")
+            }
+            _ => Err(EXPECTED),
+        }
+    }
+}
+
+impl Serialize for CalendarDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CalendarDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar_date_parse() {
+        assert_eq!("2024-06-13".parse(), Ok(CalendarDate::new(2024, 6, 13).unwrap()));
+        assert_eq!("2024-02-29".parse::<CalendarDate>().unwrap().to_string(), "2024-02-29");
+        assert!("2023-02-29".parse::<CalendarDate>().is_err());
+        assert!("2024-13-01".parse::<CalendarDate>().is_err());
+        assert!("2024-6-13".parse::<CalendarDate>().is_err());
+        assert!("13/06/2024".parse::<CalendarDate>().is_err());
+        assert!("2024-+6-13".parse::<CalendarDate>().is_err());
+    }
+
+    #[test]
+    fn test_calendar_date_order() {
+        let date = |text: &str| text.parse::<CalendarDate>().unwrap();
+        assert!(date("2023-12-31") < date("2024-01-01"));
+        assert!(date("2024-01-31") < date("2024-02-01"));
+    }
+
+    #[test]
+    fn test_calendar_date_from_days_since_epoch() {
+        assert_eq!(CalendarDate::from_days_since_epoch(0).to_string(), "1970-01-01");
+        assert_eq!(CalendarDate::from_days_since_epoch(59).to_string(), "1970-03-01");
+        assert_eq!(CalendarDate::from_days_since_epoch(19_887).to_string(), "2024-06-13");
+        assert_eq!(CalendarDate::from_days_since_epoch(-1).to_string(), "1969-12-31");
+    }
+}
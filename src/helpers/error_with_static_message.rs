@@ -21,7 +21,7 @@ impl ErrorWithStaticMessage {
     pub fn as_rejection(self) -> warp::Rejection {
         warp::reject::custom(self)
     }
-    pub fn get_message(&self) -> &str {
+    pub fn get_message(&self) -> &'static str {
         self.message
     }
 }
@@ -2,9 +2,18 @@ mod error_with_static_message;
 pub use error_with_static_message::ErrorWithStaticMessage;
 
 mod unit_conversion;
-pub use unit_conversion::convert_metres_to_degrees;
+pub use unit_conversion::{convert_metres_to_degrees, convert_degrees_to_metres, distance_metres};
+
+/// A flat frame in metres for offsetting lines stored in degrees
+mod local_frame;
+pub use local_frame::LocalFrame;
+#[cfg(test)]
+pub use local_frame::geodesic_distance_metres;
 
 mod mean_angle;
 pub use mean_angle::mean_angle;
 
+mod calendar_date;
+pub use calendar_date::CalendarDate;
+
 pub mod serde_helpers;
\ No newline at end of file
@@ -22,6 +22,12 @@ where D:Deserializer<'de>{
     }
 }
 
+/// Fail on nan or infinite values, for optional fields
+pub fn option_f32_finite_or_fail<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+where D:Deserializer<'de>{
+    f32_finite_or_fail(deserializer).map(Some)
+}
+
 /// Fail on nan or infinite values
 pub fn f32_not_nan_or_fail<'de, D>(deserializer: D) -> Result<f32, D::Error>
 where D:Deserializer<'de>{
@@ -30,4 +36,14 @@ where D:Deserializer<'de>{
         result if result.is_nan() => Err(serde::de::Error::custom("must not be nan")), // malformed input
         result => Ok(result)
     }
-}
\ No newline at end of file
+}
+
+/// Fail on nan or infinite values
+pub fn f64_finite_or_fail<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where D:Deserializer<'de>{
+    let result = f64::deserialize(deserializer)?;
+    match result {
+        result if result.is_finite() => Ok(result),
+        _ => Err(serde::de::Error::custom("must be a finite number")) // malformed input
+    }
+}
@@ -1,3 +1,5 @@
+use nickslinetoolsrust::vector2::Vector2;
+
 const EARTH_RADIUS_METRES:f64		= 6.3781e+6_f64;
 const EARTH_METRES_PER_RADIAN:f64	= EARTH_RADIUS_METRES;
 const EARTH_METRES_PER_DEGREE:f64	= EARTH_METRES_PER_RADIAN * std::f64::consts::PI / 180.0;
@@ -14,4 +16,20 @@ const EARTH_METRES_PER_DEGREE:f64	= EARTH_METRES_PER_RADIAN * std::f64::consts::
 /// It is not accurate but produces acceptable results in Western Australia
 pub fn convert_metres_to_degrees(metres: f64) -> f64{
 	metres / EARTH_METRES_PER_DEGREE
-}
\ No newline at end of file
+}
+
+/// The inverse of [convert_metres_to_degrees]. Subject to the same
+/// limitations.
+pub fn convert_degrees_to_metres(degrees: f64) -> f64{
+	degrees * EARTH_METRES_PER_DEGREE
+}
+
+/// Approximate distance in metres between two points stored in lat / lng
+/// degrees. The earth is treated as flat between the points, which is fine
+/// for the length of a road segment.
+pub fn distance_metres(a: &Vector2, b: &Vector2) -> f64{
+	let mean_latitude = (a.y + b.y) / 2.0;
+	let dx = convert_degrees_to_metres(b.x - a.x) * mean_latitude.to_radians().cos();
+	let dy = convert_degrees_to_metres(b.y - a.y);
+	(dx * dx + dy * dy).sqrt()
+}
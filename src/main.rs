@@ -3,18 +3,46 @@ mod filters;
 mod data;
 mod settings;
 
+use std::{sync::Arc, time::Duration};
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     let settings = settings::Settings::get_from_cli_or_env();
 
+    if let Some(settings::Command::Diff(args)) = &settings.command {
+        let report = data::compare_files(args.old.as_ref(), args.new.as_ref(), args.tolerance, &args.format)?;
+        match &args.output {
+            Some(output) => {
+                std::fs::write(output, report)?;
+                println!("INFO: Saved the report to '{}'", output);
+            },
+            None => println!("{}", report),
+        }
+        return Ok(());
+    }
+
     let indexed_data = data::IndexedData::load(
-        &settings.NLR_DATA_FILE,
-        &settings.NLR_DATA_SOURCE_URL,
-        &settings.NLR_FORCE_UPDATE_DATA
+        &settings.get_data_source_settings(),
+        &settings.NLR_FORCE_UPDATE_DATA,
+        &true,
     ).await?;
 
-    let filter = filters::get_combined_filters(&settings, indexed_data.into()).await?;
+    let snapshots = data::Snapshots::open(&settings.NLR_SNAPSHOT_DIR);
+
+    let shared_data:Arc<_> = data::SharedIndexedData::new(indexed_data, snapshots).into();
+
+    if settings.NLR_OFFLINE && settings.NLR_REFRESH_INTERVAL.is_some() {
+        println!("WARNING: --refresh-interval is ignored because --offline is set");
+    } else if let Some(refresh_interval) = settings.NLR_REFRESH_INTERVAL {
+        data::spawn_background_refresh(
+            shared_data.clone(),
+            settings.get_data_source_settings(),
+            Duration::from_secs(refresh_interval),
+        );
+    }
+
+    let filter = filters::get_combined_filters(&settings, shared_data).await?;
 
     println!("Serving at {:?}", settings.get_socket_address());
     warp::serve(filter).run(settings.get_socket_address()).await;
@@ -0,0 +1,34 @@
+use clap::{Args, Subcommand};
+
+use crate::data::DiffFormat;
+
+/// Tasks which run once and exit instead of starting the server
+#[derive(Debug, Clone, Subcommand, PartialEq)]
+pub enum Command {
+    /// Compare two cache data files and report roads and carriageways which
+    /// were added, removed, re-measured or moved
+    Diff(DiffArgs),
+}
+
+#[derive(Debug, Clone, Args, PartialEq)]
+pub struct DiffArgs {
+    /// The older cache data file
+    #[clap(value_hint=clap::ValueHint::FilePath)]
+    pub old: String,
+
+    /// The newer cache data file
+    #[clap(value_hint=clap::ValueHint::FilePath)]
+    pub new: String,
+
+    #[clap(long="tolerance", default_value_t = 5.0)]
+    /// Geometry which moved further than this many metres is reported
+    pub tolerance: f64,
+
+    #[clap(long="format", value_enum, default_value_t = DiffFormat::geojson)]
+    /// Format of the report
+    pub format: DiffFormat,
+
+    #[clap(long="output", value_hint=clap::ValueHint::FilePath)]
+    /// File to write the report to. If not set the report is printed
+    pub output: Option<String>,
+}
@@ -1,2 +1,5 @@
 mod settings;
-pub use settings::Settings;
\ No newline at end of file
+pub use settings::Settings;
+
+mod command;
+pub use command::Command;
\ No newline at end of file
@@ -4,6 +4,10 @@ use std::{
 };
 use clap::Parser;
 
+use crate::data::{cached::CacheFormat, DataSourceSettings, DownloadSettings, FieldMapping};
+
+use super::Command;
+
 #[allow(non_snake_case)]
 #[derive(Debug, Deserialize, Clone, Parser, PartialEq)]
 #[command(name="nicklinref")]
@@ -36,6 +40,15 @@ pub struct Settings {
     /// File path to where the cache data file is/will be stored, including file name
     pub NLR_DATA_FILE: String,
 
+    #[clap(
+        long="cache-format",
+        env="NLR_CACHE_FORMAT",
+    )]
+    /// Format used when saving the cache data file. If not set, `binary` is
+    /// used when the data file name ends with `.bin`, otherwise `json`.
+    /// Either format can always be loaded
+    pub NLR_CACHE_FORMAT: Option<CacheFormat>,
+
     #[clap(
         long="static-http",
         env="NLR_STATIC_HTTP",
@@ -50,18 +63,84 @@ pub struct Settings {
         env="NLR_FORCE_UPDATE_DATA",
         default_value_t = false,
     )]
-    /// Cause the old data cache file to be deleted and re-downloaded
+    /// Download fresh data even if the cache data file can be loaded. The old
+    /// file is kept as a backup until the download succeeds
     pub NLR_FORCE_UPDATE_DATA: bool,
 
+    #[clap(
+        long="offline",
+        env="NLR_OFFLINE",
+        default_value_t = false,
+        conflicts_with = "NLR_FORCE_UPDATE_DATA",
+    )]
+    /// Never download data. Only the cache data file is loaded; the server
+    /// exits if it is missing or can not be read
+    pub NLR_OFFLINE: bool,
+
     #[clap(
         long="data-source-url",
         env="NLR_DATA_SOURCE_URL",
-        default_value = "https://mrgis.mainroads.wa.gov.au/arcgis/rest/services/OpenData/RoadAssets_DataPortal/MapServer/17/query?where=1%3D1&outFields=ROAD,START_SLK,END_SLK,CWY&outSR=4326&f=json",
+        default_value = "https://mrgis.mainroads.wa.gov.au/arcgis/rest/services/OpenData/RoadAssets_DataPortal/MapServer/17/query?where=1%3D1&outFields=ROAD,START_SLK,END_SLK,CWY,START_TRUE_DIST,END_TRUE_DIST,NETWORK_TYPE,ROAD_NAME,LG_NAME,RA_NO&outSR=4326&f=json",
         value_hint=clap::ValueHint::Url
     )]
-    /// Url of the esri rest service hosting the road network data
+    /// Url of the esri rest service hosting the road network data, or a
+    /// `file://` url or path to a local .geojson, .fgb or .gpkg file
     pub NLR_DATA_SOURCE_URL: String,
 
+    #[clap(
+        long="admin-token",
+        env="NLR_ADMIN_TOKEN",
+    )]
+    /// Secret token required to use the /admin/ routes. The /admin/ routes
+    /// are disabled if this is not set
+    pub NLR_ADMIN_TOKEN: Option<String>,
+
+    #[clap(
+        long="refresh-interval",
+        env="NLR_REFRESH_INTERVAL",
+        value_parser=clap::value_parser!(u64).range(1..),
+    )]
+    /// Number of seconds between checks for new data at the data source url.
+    /// New data is only downloaded if the source data has changed. If not set,
+    /// data is only refreshed at startup
+    pub NLR_REFRESH_INTERVAL: Option<u64>,
+
+    #[clap(
+        long="snapshot-dir",
+        env="NLR_SNAPSHOT_DIR",
+        value_hint=clap::ValueHint::DirPath
+    )]
+    /// Folder where a dated copy of the data is saved each time it is
+    /// downloaded. Queries with `as_of=` use these copies. If not set, no
+    /// copies are kept
+    pub NLR_SNAPSHOT_DIR: Option<String>,
+
+    #[clap(
+        long="max-data-errors",
+        env="NLR_MAX_DATA_ERRORS",
+    )]
+    /// Refuse to serve data with more validation errors than this. At startup
+    /// the server exits; on reload the previous data continues to be served.
+    /// If not set, errors are only reported at the /diagnostics route
+    pub NLR_MAX_DATA_ERRORS: Option<usize>,
+
+    // Describes the fields of the data source. Shown under a separate
+    // heading in --help
+    #[clap(flatten)]
+    #[serde(flatten)]
+    pub field_mapping: FieldMapping,
+
+    // Retries, paging and concurrency of downloads from the rest service.
+    // Shown under a separate heading in --help
+    #[clap(flatten)]
+    #[serde(flatten)]
+    pub download_settings: DownloadSettings,
+
+    #[clap(subcommand)]
+    #[serde(skip)]
+    /// Run a one-off task instead of starting the server
+    pub command: Option<Command>,
+
 }
 
 impl Settings {
@@ -72,6 +151,20 @@ impl Settings {
         Self::parse()
     }
 
+    /// collect the settings needed to load, download and save the data
+    pub fn get_data_source_settings(&self) -> DataSourceSettings {
+        DataSourceSettings {
+            path_to_data_cache_file: self.NLR_DATA_FILE.clone(),
+            url_to_download_new_data: self.NLR_DATA_SOURCE_URL.clone(),
+            cache_format: self.NLR_CACHE_FORMAT,
+            field_mapping: self.field_mapping.clone(),
+            download_settings: self.download_settings.clone(),
+            snapshot_directory: self.NLR_SNAPSHOT_DIR.clone(),
+            max_data_errors: self.NLR_MAX_DATA_ERRORS,
+            offline: self.NLR_OFFLINE,
+        }
+    }
+
     /// combine the NLR_ADDR and NLR_PORT values into a socket address
     pub fn get_socket_address(&self) -> std::net::SocketAddr { 
         (self.NLR_ADDR, self.NLR_PORT).into()
@@ -84,6 +177,7 @@ impl Settings {
 mod tests {
 
     use super::*;
+    use crate::{data::{download::PagingMode, DiffFormat}, settings::command::DiffArgs};
 
     impl Default for Settings {
         fn default() -> Self {
@@ -99,6 +193,28 @@ mod tests {
         assert_eq!(defaults.NLR_ADDR, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
         assert_eq!(defaults.NLR_PORT, 8080);
         assert_eq!(defaults.NLR_FORCE_UPDATE_DATA, false);
+        assert_eq!(defaults.NLR_OFFLINE, false);
+        assert_eq!(defaults.NLR_ADMIN_TOKEN, None);
+        assert_eq!(defaults.NLR_REFRESH_INTERVAL, None);
+        assert_eq!(defaults.NLR_SNAPSHOT_DIR, None);
+        assert_eq!(defaults.NLR_MAX_DATA_ERRORS, None);
+        assert_eq!(defaults.NLR_CACHE_FORMAT, None);
+        assert_eq!(defaults.field_mapping.NLR_FIELD_ROAD, "ROAD");
+        assert_eq!(defaults.field_mapping.NLR_FIELD_CWY, "CWY");
+        assert_eq!(defaults.field_mapping.NLR_FIELD_START_SLK, "START_SLK");
+        assert_eq!(defaults.field_mapping.NLR_FIELD_END_SLK, "END_SLK");
+        assert_eq!(defaults.field_mapping.NLR_FIELD_NETWORK_TYPE, "NETWORK_TYPE");
+        assert_eq!(defaults.field_mapping.NLR_FIELD_LG_NAME, "LG_NAME");
+        assert_eq!(defaults.field_mapping.NLR_CWY_LEFT, vec!["Left", "L"]);
+        assert_eq!(defaults.field_mapping.NLR_SOURCE_WHERE, None);
+        assert_eq!(defaults.download_settings.NLR_DOWNLOAD_RETRIES, 5);
+        assert_eq!(defaults.download_settings.NLR_DOWNLOAD_RETRY_DELAY, 1000);
+        assert_eq!(defaults.download_settings.NLR_DOWNLOAD_PAGE_SIZE, None);
+        assert_eq!(defaults.download_settings.NLR_DOWNLOAD_CONCURRENCY, 4);
+        assert_eq!(defaults.download_settings.NLR_DOWNLOAD_PAGING, PagingMode::auto);
+        assert_eq!(defaults.download_settings.NLR_ARCGIS_TOKEN, None);
+        assert_eq!(defaults.download_settings.NLR_PROXY, None);
+        assert!(defaults.download_settings.NLR_CA_CERT.is_empty());
     }
 
 
@@ -110,21 +226,118 @@ mod tests {
             "--ip-address", "127.0.0.5",
             "--port", "8093",
             "--data-file", "none.lz4",
+            "--cache-format", "binary",
             "--static-http", "__static_none",
             "--force-update-data",
             "--data-source-url", "https://none.none.none",
+            "--admin-token", "secret",
+            "--refresh-interval", "86400",
+            "--snapshot-dir", "./snapshots",
+            "--max-data-errors", "10",
+            "--field-road", "road_id",
+            "--field-cwy", "cway",
+            "--field-start-slk", "from_km",
+            "--field-end-slk", "to_km",
+            "--field-network-type", "",
+            "--field-road-name", "name",
+            "--field-lg-name", "lga",
+            "--field-ra-no", "region",
+            "--field-start-true-dist", "from_true_km",
+            "--field-end-true-dist", "to_true_km",
+            "--cwy-left", "1,LHS",
+            "--cwy-right", "2",
+            "--cwy-single", "0",
+            "--source-where", "STATUS='Active'",
+            "--download-retries", "2",
+            "--download-retry-delay", "250",
+            "--download-page-size", "500",
+            "--download-concurrency", "8",
+            "--download-paging", "object-id",
+            "--arcgis-username", "reader",
+            "--arcgis-password", "hunter2",
+            "--arcgis-token-url", "https://none.none.none/portal/sharing/rest/generateToken",
+            "--proxy", "http://proxy.none:8080",
+            "--ca-cert", "root.pem,intermediate.pem",
         ];
         let settings = Settings::parse_from(simulated_args.into_iter());
         assert_eq!(settings, Settings{
             NLR_ADDR: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 5)),
             NLR_PORT: 8093,
             NLR_DATA_FILE: "none.lz4".to_string(),
+            NLR_CACHE_FORMAT: Some(CacheFormat::binary),
             NLR_STATIC_HTTP: "__static_none".to_string(),
             NLR_FORCE_UPDATE_DATA: true,
+            NLR_OFFLINE: false,
             NLR_DATA_SOURCE_URL: "https://none.none.none".to_string(),
+            NLR_ADMIN_TOKEN: Some("secret".to_string()),
+            NLR_REFRESH_INTERVAL: Some(86400),
+            NLR_SNAPSHOT_DIR: Some("./snapshots".to_string()),
+            NLR_MAX_DATA_ERRORS: Some(10),
+            field_mapping: FieldMapping {
+                NLR_FIELD_ROAD: "road_id".to_string(),
+                NLR_FIELD_CWY: "cway".to_string(),
+                NLR_FIELD_START_SLK: "from_km".to_string(),
+                NLR_FIELD_END_SLK: "to_km".to_string(),
+                NLR_FIELD_NETWORK_TYPE: "".to_string(),
+                NLR_FIELD_ROAD_NAME: "name".to_string(),
+                NLR_FIELD_LG_NAME: "lga".to_string(),
+                NLR_FIELD_RA_NO: "region".to_string(),
+                NLR_FIELD_START_TRUE_DIST: "from_true_km".to_string(),
+                NLR_FIELD_END_TRUE_DIST: "to_true_km".to_string(),
+                NLR_CWY_LEFT: vec!["1".to_string(), "LHS".to_string()],
+                NLR_CWY_RIGHT: vec!["2".to_string()],
+                NLR_CWY_SINGLE: vec!["0".to_string()],
+                NLR_SOURCE_WHERE: Some("STATUS='Active'".to_string()),
+            },
+            download_settings: DownloadSettings {
+                NLR_DOWNLOAD_RETRIES: 2,
+                NLR_DOWNLOAD_RETRY_DELAY: 250,
+                NLR_DOWNLOAD_PAGE_SIZE: Some(500),
+                NLR_DOWNLOAD_CONCURRENCY: 8,
+                NLR_DOWNLOAD_PAGING: PagingMode::object_id,
+                NLR_ARCGIS_TOKEN: None,
+                NLR_ARCGIS_USERNAME: Some("reader".to_string()),
+                NLR_ARCGIS_PASSWORD: Some("hunter2".to_string()),
+                NLR_ARCGIS_TOKEN_URL: Some("https://none.none.none/portal/sharing/rest/generateToken".to_string()),
+                NLR_PROXY: Some("http://proxy.none:8080".to_string()),
+                NLR_CA_CERT: vec!["root.pem".to_string(), "intermediate.pem".to_string()],
+            },
+            command: None,
         });
     }
 
+    #[test]
+    fn test_settings_parse_diff_command(){
+        let settings = Settings::parse_from([
+            "none.exe",
+            "diff", "old.bin", "new.json.lz4",
+            "--tolerance", "2",
+            "--format", "csv",
+        ].into_iter());
+        assert_eq!(settings.command, Some(Command::Diff(DiffArgs{
+            old: "old.bin".to_string(),
+            new: "new.json.lz4".to_string(),
+            tolerance: 2.0,
+            format: DiffFormat::csv,
+            output: None,
+        })));
+        assert_eq!(Settings::default().command, None);
+    }
+
+    #[test]
+    /// `--offline` can't be combined with `--force-update-data`
+    fn test_settings_offline(){
+        let settings = Settings::parse_from(["none.exe", "--offline"].into_iter());
+        assert!(settings.NLR_OFFLINE);
+        assert!(settings.get_data_source_settings().offline);
+        let settings = Settings::try_parse_from([
+            "none.exe",
+            "--offline",
+            "--force-update-data",
+        ].into_iter());
+        assert!(settings.is_err());
+    }
+
     #[test]
     /// confirm that clap will not accept unexpected arguments such as `--pux`
     fn test_settings_unexpected_extra_arg(){